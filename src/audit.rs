@@ -0,0 +1,426 @@
+//! Audit an existing directory tree against a template.
+//!
+//! The audit walks a real directory tree from `root`, classifying each entry as
+//! matching a node, matching a volume node, or matching no node at all (a rogue
+//! entry). Entries which match are checked against the node's `perms` and
+//! `owner` metadata, and against the node's `autocreate` children, which must
+//! exist whenever their parent does. Rogue entries are not descended into, and
+//! entries which cannot be read are reported rather than ending the audit.
+use crate::{Matcher, sandbox::resolve_owner};
+use jsp::{EntryType, NIndex};
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    ffi::CStr,
+    fmt, fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The form in which an audit report is written
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ReportFormat {
+    /// One line per entry, followed by its issues and a summary
+    Text,
+    /// A single json document
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(format!("invalid report format: {}. Expected one of text, json", s)),
+        }
+    }
+}
+
+/// How an entry on disk relates to the template
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Classification {
+    /// The entry matches a node
+    Node,
+    /// The entry matches a node marked `volume`
+    Volume,
+    /// The entry matches none of the children of its parent's node
+    Rogue,
+}
+
+impl fmt::Display for Classification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // pad, so that reports may align the classification
+        f.pad(match self {
+            Classification::Node => "node",
+            Classification::Volume => "volume",
+            Classification::Rogue => "rogue",
+        })
+    }
+}
+
+/// A way in which a matching entry differs from its node
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Issue {
+    /// The permission bits, in octal, differ from the node's `perms`
+    Permissions { expected: String, actual: String },
+    /// The owner differs from the node's `owner`
+    Owner { expected: String, actual: String },
+    /// An `autocreate` child of the node does not exist
+    Missing { node: String },
+    /// The entry is a file rather than a directory
+    NotADirectory,
+    /// The entry, or the contents of the directory, could not be read
+    Unreadable { reason: String },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::Permissions { expected, actual } => write!(f, "perms are {}, expected {}", actual, expected),
+            Issue::Owner { expected, actual } => write!(f, "owned by {}, expected {}", actual, expected),
+            Issue::Missing { node } => write!(f, "missing the autocreate child `{}`", node),
+            Issue::NotADirectory => write!(f, "is not a directory"),
+            Issue::Unreadable { reason } => write!(f, "could not be read: {}", reason),
+        }
+    }
+}
+
+/// An entry on disk, along with the node which it matched and any issues found
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct AuditEntry {
+    path: PathBuf,
+    classification: Classification,
+    node: Option<String>,
+    issues: Vec<Issue>,
+    // the index is meaningless outside of the graph, so we leave it out of reports
+    #[serde(skip)]
+    index: Option<NIndex>,
+}
+
+impl AuditEntry {
+    /// Retrieve the path of the entry
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Retrieve the classification of the entry
+    pub fn classification(&self) -> Classification {
+        self.classification
+    }
+
+    /// Retrieve the name of the node which the entry matched, if any
+    pub fn node(&self) -> Option<&str> {
+        self.node.as_ref().map(|x| &**x)
+    }
+
+    /// Retrieve the index of the matching node within the JGraph, if any
+    pub fn node_index(&self) -> Option<NIndex> {
+        self.index
+    }
+
+    /// Retrieve the ways in which the entry differs from its node
+    pub fn issues(&self) -> &[Issue] {
+        &self.issues
+    }
+}
+
+/// The result of auditing a directory tree, with entries in walk order
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Report {
+    root: PathBuf,
+    entries: Vec<AuditEntry>,
+}
+
+impl Report {
+    /// Retrieve the directory which was audited
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Retrieve every entry which was visited
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    /// Retrieve the entries which match no node
+    pub fn rogue(&self) -> impl Iterator<Item = &AuditEntry> {
+        self.entries.iter().filter(|entry| entry.classification == Classification::Rogue)
+    }
+
+    /// Count the issues across every entry
+    pub fn issue_count(&self) -> usize {
+        self.entries.iter().map(|entry| entry.issues.len()).sum()
+    }
+
+    /// Does the tree conform to the template? That is, are there neither rogue
+    /// entries nor issues?
+    pub fn is_clean(&self) -> bool {
+        self.rogue().next().is_none() && self.issue_count() == 0
+    }
+
+    /// Render the report as a json document
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Report serializes to json")
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut counts = [0; 3];
+        for entry in &self.entries {
+            counts[entry.classification as usize] += 1;
+            write!(f, "{:6}  {}", entry.classification, entry.path.display())?;
+            if let Some(node) = &entry.node {
+                write!(f, "  ({})", node)?;
+            }
+            writeln!(f)?;
+            for issue in &entry.issues {
+                writeln!(f, "        {}", issue)?;
+            }
+        }
+        write!(
+            f,
+            "{} entries: {} nodes, {} volumes, {} rogue, {} issues",
+            self.entries.len(), counts[0], counts[1], counts[2], self.issue_count()
+        )
+    }
+}
+
+/// Audit the directory tree beneath `dir`, which is taken to correspond to the
+/// template's `root`.
+///
+/// # Parameters
+///
+/// * `matcher` - a Matcher over the loaded template
+/// * `autocreate` - the nodes marked `autocreate`, as reported by the Loader
+/// * `dir` - the directory to audit
+///
+/// # Returns
+/// A Result wrapping the Report if successful. Otherwise the io::Error
+/// encountered reading `dir` itself. Entries beneath it which cannot be read
+/// are reported as Unreadable issues.
+pub fn audit(matcher: &Matcher, autocreate: &HashSet<NIndex>, dir: &Path) -> io::Result<Report> {
+    let mut auditor = Auditor { matcher, autocreate, entries: Vec::new() };
+    let metadata = fs::metadata(dir)?;
+    auditor.check(matcher.root(), dir, "", &metadata);
+    auditor.visit(matcher.root(), dir, 0);
+    Ok(Report { root: dir.to_path_buf(), entries: auditor.entries })
+}
+
+/// Retrieve the name of the user with the supplied uid, if there is one
+pub fn user_name(uid: u32) -> Option<String> {
+    // getpwuid returns a pointer into static storage, which we copy out of
+    // immediately
+    unsafe {
+        let passwd = libc::getpwuid(uid);
+        if passwd.is_null() {
+            return None;
+        }
+        Some(CStr::from_ptr((*passwd).pw_name).to_string_lossy().into_owned())
+    }
+}
+
+// state for the walk
+struct Auditor<'a, 'b> {
+    matcher: &'a Matcher<'b>,
+    autocreate: &'a HashSet<NIndex>,
+    entries: Vec<AuditEntry>,
+}
+
+impl<'a, 'b> Auditor<'a, 'b> {
+    // visit the contents of `dir`, which matched `node` and was recorded at `index`
+    fn visit(&mut self, node: NIndex, dir: &Path, index: usize) {
+        let contents = match fs::read_dir(dir) {
+            Ok(contents) => contents,
+            Err(error) => {
+                self.entries[index].issues.push(Issue::Unreadable { reason: error.to_string() });
+                return;
+            }
+        };
+        let mut contents = contents.filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(error) => {
+                self.entries[index].issues.push(Issue::Unreadable { reason: error.to_string() });
+                None
+            }
+        }).collect::<Vec<_>>();
+        contents.sort_by_key(|entry| entry.file_name());
+        let children = self.matcher.children(node);
+        let mut present = HashSet::new();
+        for entry in contents {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let child = children.iter().find(|child| self.matcher.test(**child, &name).is_ok()).cloned();
+            // we do not follow symlinks, lest we wander out of the tree
+            let metadata = match fs::symlink_metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(error) => {
+                    present.extend(child);
+                    self.entries.push(AuditEntry {
+                        path,
+                        classification: child.map_or(Classification::Rogue, |child| self.classify(child)),
+                        node: child.map(|child| self.matcher.name(child).to_string()),
+                        issues: vec![Issue::Unreadable { reason: error.to_string() }],
+                        index: child,
+                    });
+                    continue;
+                }
+            };
+            match child {
+                Some(child) => {
+                    present.insert(child);
+                    let child_index = self.entries.len();
+                    self.check(child, &path, &name, &metadata);
+                    if metadata.is_dir() {
+                        self.visit(child, &path, child_index);
+                    }
+                }
+                None => self.entries.push(AuditEntry {
+                    path,
+                    classification: Classification::Rogue,
+                    node: None,
+                    issues: Vec::new(),
+                    index: None,
+                }),
+            }
+        }
+        for child in children {
+            if self.autocreate.contains(&child) && !present.contains(&child) {
+                self.entries[index].issues.push(Issue::Missing { node: self.matcher.name(child).to_string() });
+            }
+        }
+    }
+
+    // the classification of an entry which matched `node`
+    fn classify(&self, node: NIndex) -> Classification {
+        if *self.matcher.graph()[node].entry_type() == EntryType::Volume { Classification::Volume } else { Classification::Node }
+    }
+
+    // record an entry which matched `node`, comparing it with the node's metadata
+    fn check(&mut self, node: NIndex, path: &Path, name: &str, metadata: &fs::Metadata) {
+        let jnode = &self.matcher.graph()[node];
+        let mut issues = Vec::new();
+        if !metadata.is_dir() {
+            issues.push(Issue::NotADirectory);
+        }
+        if let Some(expected) = jnode.metadata().perms().and_then(|perms| u32::from_str_radix(perms, 8).ok()) {
+            let actual = metadata.mode() & 0o7777;
+            if actual != expected {
+                issues.push(Issue::Permissions { expected: format!("{:o}", expected), actual: format!("{:o}", actual) });
+            }
+        }
+        if let Some(expected) = jnode.metadata().owner().and_then(|owner| resolve_owner(&owner.to_string(), jnode.identity(), name)) {
+            let actual = user_name(metadata.uid()).unwrap_or_else(|| metadata.uid().to_string());
+            if actual != expected {
+                issues.push(Issue::Owner { expected, actual });
+            }
+        }
+        self.entries.push(AuditEntry {
+            path: path.to_path_buf(),
+            classification: self.classify(node),
+            node: Some(self.matcher.name(node).to_string()),
+            issues,
+            index: Some(node),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::{load_template_with_autocreate, mkdir};
+    use std::os::unix::fs::PermissionsExt;
+
+    const TEMPLATE: &str = "[regex]\n[nodes]\ndd\nshows\nshow = \"[A-Z]+\" [perms: 750]\nref = REF [volume]\nwork = work [autocreate]\n[graph]\nroot -> dd -> shows -> show -> work\nshow -> ref\n";
+
+    #[test]
+    fn conforming_tree_is_clean() {
+        let (graph, keymap, autocreate) = load_template_with_autocreate(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        mkdir(&dir.path().join("dd/shows/FOO/work"), 0o755);
+        mkdir(&dir.path().join("dd/shows/FOO/REF"), 0o755);
+        fs::set_permissions(dir.path().join("dd/shows/FOO"), fs::Permissions::from_mode(0o750)).unwrap();
+        let report = audit(&matcher, &autocreate, dir.path()).unwrap();
+        assert!(report.is_clean(), "{}", report);
+        let classifications = report.entries().iter()
+            .map(|entry| (entry.path().strip_prefix(dir.path()).unwrap().to_str().unwrap(), entry.classification()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            classifications,
+            vec![
+                ("", Classification::Node),
+                ("dd", Classification::Node),
+                ("dd/shows", Classification::Node),
+                ("dd/shows/FOO", Classification::Node),
+                ("dd/shows/FOO/REF", Classification::Volume),
+                ("dd/shows/FOO/work", Classification::Node),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_drift() {
+        let (graph, keymap, autocreate) = load_template_with_autocreate(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        mkdir(&dir.path().join("dd/shows/FOO"), 0o755);
+        mkdir(&dir.path().join("dd/shows/foo"), 0o755);
+        fs::write(dir.path().join("dd/shows/FOO/work"), "").unwrap();
+        let report = audit(&matcher, &autocreate, dir.path()).unwrap();
+        let issues = report.entries().iter()
+            .filter(|entry| !entry.issues().is_empty())
+            .map(|entry| (entry.path().strip_prefix(dir.path()).unwrap().to_str().unwrap(), entry.issues().to_vec()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            vec![
+                ("dd/shows/FOO", vec![Issue::Permissions { expected: "750".to_string(), actual: "755".to_string() }]),
+                ("dd/shows/FOO/work", vec![Issue::NotADirectory]),
+            ]
+        );
+        assert_eq!(report.rogue().map(|entry| entry.path().to_path_buf()).collect::<Vec<_>>(), vec![dir.path().join("dd/shows/foo")]);
+    }
+
+    #[test]
+    fn reports_missing_autocreate_children() {
+        let (graph, keymap, autocreate) = load_template_with_autocreate(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        mkdir(&dir.path().join("dd/shows/BAR"), 0o750);
+        let report = audit(&matcher, &autocreate, dir.path()).unwrap();
+        let show = report.entries().iter().find(|entry| entry.node() == Some("show")).unwrap();
+        assert_eq!(show.issues(), &[Issue::Missing { node: "work".to_string() }]);
+        assert!(report.to_json().contains("\"kind\": \"missing\""));
+    }
+
+    #[test]
+    fn reports_unreadable_directories_and_carries_on() {
+        let (graph, keymap, autocreate) = load_template_with_autocreate(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        mkdir(&dir.path().join("dd/shows/FOO/work"), 0o755);
+        mkdir(&dir.path().join("dd/shows/GOO/work"), 0o750);
+        fs::set_permissions(dir.path().join("dd/shows/FOO"), fs::Permissions::from_mode(0o000)).unwrap();
+        let report = audit(&matcher, &autocreate, dir.path());
+        // restore the permissions, so that the tempdir may be removed
+        fs::set_permissions(dir.path().join("dd/shows/FOO"), fs::Permissions::from_mode(0o755)).unwrap();
+        let report = report.unwrap();
+        // root may read anything, in which case there is nothing to report
+        if crate::sandbox::is_root() {
+            return;
+        }
+        let foo = report.entries().iter().find(|entry| entry.path() == dir.path().join("dd/shows/FOO")).unwrap();
+        assert!(foo.issues().iter().any(|issue| match issue {
+            Issue::Unreadable { .. } => true,
+            _ => false,
+        }), "{}", report);
+        assert!(report.entries().iter().any(|entry| entry.path() == dir.path().join("dd/shows/GOO/work")), "{}", report);
+        assert!(!report.is_clean());
+    }
+}
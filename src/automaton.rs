@@ -0,0 +1,331 @@
+//! Finite automata over path components.
+//!
+//! Deciding whether one regex accepts everything another does is beyond the
+//! regex crate, so we compile each regex's syntax tree into a Thompson NFA and
+//! explore the product of several NFAs at once, determinizing lazily. Path
+//! components never contain `/`, so it is left out of the alphabet.
+use jsp::Regexp;
+use regex_syntax::{
+    Parser,
+    hir::{Anchor, Class, Hir, HirKind, Literal, RepetitionKind, RepetitionRange},
+};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+// one past the largest char
+const CHAR_END: u32 = 0x11_0000;
+
+#[derive(Debug, Clone)]
+enum State {
+    // consume a char within one of the ranges, inclusive
+    Class(Vec<(u32, u32)>, usize),
+    // epsilon transitions
+    Split(Vec<usize>),
+    // an epsilon transition which is only taken at the start of the input
+    Start(usize),
+    // an epsilon transition which is only taken at the end of the input
+    End(usize),
+    Match,
+}
+
+/// A nondeterministic finite automaton which accepts the same strings as a
+/// regex, or a literal.
+#[derive(Debug, Clone)]
+pub struct Nfa {
+    states: Vec<State>,
+    start: usize,
+}
+
+impl Nfa {
+    /// Build an Nfa which accepts the strings that `regex.is_match` does. As with
+    /// `is_match`, an unanchored regex matches anywhere within a string.
+    ///
+    /// # Returns
+    /// The Nfa, or None if the regex's syntax cannot be parsed
+    pub fn from_regex(regex: &Regexp) -> Option<Self> {
+        let hir = Parser::new().parse(regex.as_str()).ok()?;
+        let mut nfa = Self { states: Vec::new(), start: 0 };
+        let mut next = nfa.push(State::Match);
+        if !hir.is_anchored_end() {
+            next = nfa.any_loop(next);
+        }
+        let mut start = nfa.compile(&hir, next);
+        if !hir.is_anchored_start() {
+            start = nfa.any_loop(start);
+        }
+        nfa.start = start;
+        Some(nfa)
+    }
+
+    /// Build an Nfa which accepts exactly `literal`
+    pub fn literal(literal: &str) -> Self {
+        let mut nfa = Self { states: Vec::new(), start: 0 };
+        let mut next = nfa.push(State::Match);
+        for c in literal.chars().rev() {
+            next = nfa.push(State::Class(vec![(c as u32, c as u32)], next));
+        }
+        nfa.start = next;
+        nfa
+    }
+
+    /// Build an Nfa which accepts every string
+    pub fn any() -> Self {
+        let mut nfa = Self { states: Vec::new(), start: 0 };
+        let accept = nfa.push(State::Match);
+        nfa.start = nfa.any_loop(accept);
+        nfa
+    }
+
+    /// Test whether the Nfa accepts `input`
+    pub fn is_match(&self, input: &str) -> bool {
+        let mut set = self.closure(vec![self.start], true);
+        for c in input.chars() {
+            set = self.step(&set, c as u32);
+        }
+        self.accepts(&set)
+    }
+
+    fn push(&mut self, state: State) -> usize {
+        self.states.push(state);
+        self.states.len() - 1
+    }
+
+    // a state which consumes any number of chars before continuing to `next`
+    fn any_loop(&mut self, next: usize) -> usize {
+        let split = self.push(State::Split(Vec::new()));
+        let class = self.push(State::Class(vec![(0, CHAR_END - 1)], split));
+        self.states[split] = State::Split(vec![class, next]);
+        split
+    }
+
+    // compile the hir into states which continue to `next`, returning the first of them.
+    // We work backwards, so that every state knows its successor when it is created.
+    fn compile(&mut self, hir: &Hir, next: usize) -> usize {
+        match hir.kind() {
+            HirKind::Empty | HirKind::WordBoundary(_) => next,
+            HirKind::Anchor(Anchor::StartText) | HirKind::Anchor(Anchor::StartLine) => self.push(State::Start(next)),
+            HirKind::Anchor(Anchor::EndText) | HirKind::Anchor(Anchor::EndLine) => self.push(State::End(next)),
+            HirKind::Literal(Literal::Unicode(c)) => self.push(State::Class(vec![(*c as u32, *c as u32)], next)),
+            HirKind::Literal(Literal::Byte(b)) => self.push(State::Class(vec![(*b as u32, *b as u32)], next)),
+            HirKind::Class(Class::Unicode(class)) => {
+                let ranges = class.iter().map(|r| (r.start() as u32, r.end() as u32)).collect();
+                self.push(State::Class(ranges, next))
+            }
+            HirKind::Class(Class::Bytes(class)) => {
+                let ranges = class.iter().map(|r| (r.start() as u32, r.end() as u32)).collect();
+                self.push(State::Class(ranges, next))
+            }
+            HirKind::Group(group) => self.compile(&group.hir, next),
+            HirKind::Concat(hirs) => hirs.iter().rev().fold(next, |next, hir| self.compile(hir, next)),
+            HirKind::Alternation(hirs) => {
+                let branches = hirs.iter().map(|hir| self.compile(hir, next)).collect();
+                self.push(State::Split(branches))
+            }
+            HirKind::Repetition(repetition) => {
+                let (min, max) = match &repetition.kind {
+                    RepetitionKind::ZeroOrOne => (0, Some(1)),
+                    RepetitionKind::ZeroOrMore => (0, None),
+                    RepetitionKind::OneOrMore => (1, None),
+                    RepetitionKind::Range(RepetitionRange::Exactly(n)) => (*n, Some(*n)),
+                    RepetitionKind::Range(RepetitionRange::AtLeast(n)) => (*n, None),
+                    RepetitionKind::Range(RepetitionRange::Bounded(m, n)) => (*m, Some(*n)),
+                };
+                let mut tail = match max {
+                    None => {
+                        let split = self.push(State::Split(Vec::new()));
+                        let body = self.compile(&repetition.hir, split);
+                        self.states[split] = State::Split(vec![body, next]);
+                        split
+                    }
+                    // each optional repetition either continues to the next, or skips to the end
+                    Some(max) => (min..max).fold(next, |tail, _| {
+                        let body = self.compile(&repetition.hir, tail);
+                        self.push(State::Split(vec![body, next]))
+                    }),
+                };
+                for _ in 0..min {
+                    tail = self.compile(&repetition.hir, tail);
+                }
+                tail
+            }
+        }
+    }
+
+    // follow the epsilon transitions from the seeds, returning the sorted states which
+    // consume input, match, or wait for the end of input
+    fn closure(&self, seeds: Vec<usize>, at_start: bool) -> Vec<usize> {
+        let mut seen = vec![false; self.states.len()];
+        let mut stack = seeds;
+        let mut set = Vec::new();
+        while let Some(idx) = stack.pop() {
+            if seen[idx] {
+                continue;
+            }
+            seen[idx] = true;
+            match &self.states[idx] {
+                State::Split(next) => stack.extend(next.iter().cloned()),
+                State::Start(next) => if at_start { stack.push(*next) },
+                State::Class(..) | State::End(_) | State::Match => set.push(idx),
+            }
+        }
+        set.sort();
+        set
+    }
+
+    // consume `c`
+    fn step(&self, set: &[usize], c: u32) -> Vec<usize> {
+        let seeds = set.iter().filter_map(|idx| match &self.states[*idx] {
+            State::Class(ranges, next) if ranges.iter().any(|(lo, hi)| *lo <= c && c <= *hi) => Some(*next),
+            _ => None,
+        }).collect();
+        self.closure(seeds, false)
+    }
+
+    // does the set accept, were the input to end here?
+    fn accepts(&self, set: &[usize]) -> bool {
+        let mut seen = vec![false; self.states.len()];
+        let mut stack = set.to_vec();
+        while let Some(idx) = stack.pop() {
+            if seen[idx] {
+                continue;
+            }
+            seen[idx] = true;
+            match &self.states[idx] {
+                State::Match => return true,
+                State::End(next) => stack.push(*next),
+                State::Split(next) => stack.extend(next.iter().cloned()),
+                State::Class(..) | State::Start(_) => (),
+            }
+        }
+        false
+    }
+
+    // the boundaries between ranges of chars which the Nfa treats differently
+    fn boundaries(&self, boundaries: &mut BTreeSet<u32>) {
+        for state in &self.states {
+            if let State::Class(ranges, _) = state {
+                for (lo, hi) in ranges {
+                    boundaries.insert(*lo);
+                    boundaries.insert(*hi + 1);
+                }
+            }
+        }
+    }
+}
+
+/// Explore the strings accepted by the product of several automata. For each
+/// distinct combination of automata which accept a non-empty string together,
+/// the shortest such string is returned. Strings are only extended while the
+/// first automaton may still accept, as it is the one of interest.
+///
+/// # Parameters
+///
+/// * `automata` - the automata to explore
+/// * `limit` - the maximum number of product states to visit
+///
+/// # Returns
+/// Pairs of which automata accept and the shortest string they accept, or None
+/// if the exploration exceeded the limit.
+pub fn acceptance_witnesses(automata: &[&Nfa], limit: usize) -> Option<Vec<(Vec<bool>, String)>> {
+    let symbols = alphabet(automata);
+    let start = automata.iter().map(|nfa| nfa.closure(vec![nfa.start], true)).collect::<Vec<_>>();
+    // each visited state along with the index of its predecessor and the char which led here
+    let mut visited = vec![(start.clone(), 0, ' ')];
+    let mut seen = HashMap::new();
+    seen.insert(start, 0);
+    let mut queue = VecDeque::new();
+    queue.push_back(0);
+    let mut witnesses: Vec<(Vec<bool>, String)> = Vec::new();
+    while let Some(idx) = queue.pop_front() {
+        if idx > 0 {
+            let accepted = automata.iter().zip(&visited[idx].0).map(|(nfa, set)| nfa.accepts(set)).collect::<Vec<_>>();
+            if !witnesses.iter().any(|(seen, _)| *seen == accepted) {
+                witnesses.push((accepted, witness(&visited, idx)));
+            }
+        }
+        for (lo, c) in &symbols {
+            let next = automata.iter().zip(&visited[idx].0).map(|(nfa, set)| nfa.step(set, *lo)).collect::<Vec<_>>();
+            if next[0].is_empty() || seen.contains_key(&next) {
+                continue;
+            }
+            if visited.len() == limit {
+                return None;
+            }
+            seen.insert(next.clone(), visited.len());
+            visited.push((next, idx, *c));
+            queue.push_back(visited.len() - 1);
+        }
+    }
+    Some(witnesses)
+}
+
+// rebuild the string which led to the state at idx
+fn witness(visited: &[(Vec<Vec<usize>>, usize, char)], mut idx: usize) -> String {
+    let mut chars = Vec::new();
+    while idx > 0 {
+        chars.push(visited[idx].2);
+        idx = visited[idx].1;
+    }
+    chars.iter().rev().collect()
+}
+
+// partition the chars into ranges which every automaton treats alike, returning the
+// first char of each range, along with the char we use to represent it
+fn alphabet(automata: &[&Nfa]) -> Vec<(u32, char)> {
+    let mut boundaries = BTreeSet::new();
+    boundaries.insert(0);
+    boundaries.insert('/' as u32);
+    boundaries.insert('/' as u32 + 1);
+    for nfa in automata {
+        nfa.boundaries(&mut boundaries);
+    }
+    boundaries.insert(CHAR_END);
+    let boundaries = boundaries.into_iter().filter(|b| *b <= CHAR_END).collect::<Vec<_>>();
+    boundaries.windows(2)
+        .filter(|range| range[0] != '/' as u32)
+        .filter_map(|range| representative(range[0], range[1] - 1).map(|c| (range[0], c)))
+        .collect()
+}
+
+// pick a readable char within the range, if there is one at all
+fn representative(lo: u32, hi: u32) -> Option<char> {
+    let preferred = ('A'..='Z').chain('a'..='z').chain('0'..='9').chain("_-.".chars());
+    preferred.chain((0x21..0x7f).filter_map(std::char::from_u32))
+        .find(|c| lo <= *c as u32 && *c as u32 <= hi)
+        .or_else(|| (lo..=hi).filter_map(std::char::from_u32).find(|c| !c.is_control()))
+        .or_else(|| (lo..=hi).filter_map(std::char::from_u32).next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nfa(pattern: &str) -> Nfa {
+        Nfa::from_regex(&Regexp::new(pattern).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn agrees_with_regex() {
+        let patterns = ["^[A-Z]+[0-9]*$", "^(REF|SHARED)$", "[0-9]{2,3}", "^a(b|c)?d{1,2}$", "^work\\.[a-z]+$", "x$"];
+        let inputs = ["FOO", "FOO12", "foo", "REF", "SHARED", "SHAREDX", "12", "a1234", "ad", "abdd", "acddd", "work.jg", "work.", "box", "xb", ""];
+        for pattern in patterns.iter() {
+            let regex = Regexp::new(pattern).unwrap();
+            let nfa = nfa(pattern);
+            for input in inputs.iter() {
+                assert_eq!(nfa.is_match(input), regex.is_match(input), "{} against {}", pattern, input);
+            }
+        }
+    }
+
+    #[test]
+    fn finds_shortest_witness_for_each_combination() {
+        let (narrow, wide) = (nfa("^[A-Z]{2,}$"), nfa("^[A-Z]+$"));
+        let witnesses = acceptance_witnesses(&[&wide, &narrow], 1000).unwrap();
+        assert_eq!(witnesses, vec![(vec![true, false], "A".to_string()), (vec![true, true], "AA".to_string())]);
+    }
+
+    #[test]
+    fn gives_up_at_the_limit() {
+        let counter = nfa("^[a-z]{50}$");
+        assert!(acceptance_witnesses(&[&counter], 10).is_none());
+    }
+}
@@ -0,0 +1,159 @@
+//! Build the path to a node from the values of its variables, which is the
+//! reverse of matching.
+//!
+//! Each route from `root` to the node is tried. Literal nodes are filled with
+//! their literal. Regex nodes are filled with the value set for their varname,
+//! or for their name if they have no varname, provided that the node accepts
+//! it. The path is built if exactly one route can be filled.
+use crate::{JSPTemplateError, Matcher, helpers::is_path_component, samples::node_paths};
+use jsp::{NIndex, NodeType};
+use std::collections::HashMap;
+
+/// Build the path from `root` to a node, filling regex nodes from the values
+/// supplied.
+///
+/// # Parameters
+///
+/// * `matcher` - a Matcher over the loaded template
+/// * `node` - the node at which the path ends
+/// * `values` - the value of each variable, keyed by varname or by node name
+///
+/// # Returns
+/// A Result wrapping the path if successful. Otherwise a JSPTemplateError: if no
+/// route leads to the node, if more than one route can be filled, or else why the
+/// first route could not be, preferring a value which was given but is invalid
+/// to one which is missing.
+///
+/// # Examples
+///
+/// ```
+/// use jspcompile::{Loader, Matcher, build_path::build_path};
+/// use std::{collections::HashMap, io::Cursor};
+///
+/// let template = "[regex]\n[nodes]\nshows\nshow = \"[A-Z]+\" [varname: DD_SHOW]\n[graph]\nroot -> shows -> show\n";
+/// let (mut graph, mut keymap, mut regexmap) = Loader::setup();
+/// {
+///     let mut loader = Loader::new(&mut graph, &mut keymap, &mut regexmap);
+///     loader.load(Cursor::new(template)).unwrap();
+/// }
+/// let matcher = Matcher::new(&graph, &keymap).unwrap();
+/// let mut values = HashMap::new();
+/// values.insert("DD_SHOW".to_string(), "FOO".to_string());
+/// assert_eq!(build_path(&matcher, keymap["show"], &values).unwrap(), "/shows/FOO");
+/// ```
+pub fn build_path(matcher: &Matcher, node: NIndex, values: &HashMap<String, String>) -> Result<String, JSPTemplateError> {
+    if node == matcher.root() {
+        return Ok("/".to_string());
+    }
+    let mut built: Vec<String> = Vec::new();
+    let mut failures = Vec::new();
+    for route in node_paths(matcher, Some(node)) {
+        match fill(matcher, &route, values) {
+            Ok(path) => {
+                if !built.contains(&path) {
+                    built.push(path);
+                }
+            }
+            Err(failure) => failures.push(failure),
+        }
+    }
+    match built.len() {
+        1 => Ok(built.remove(0)),
+        0 => {
+            let invalid = failures.iter().position(|failure| match failure {
+                JSPTemplateError::InvalidValueError(..) => true,
+                _ => false,
+            });
+            match invalid {
+                Some(index) => Err(failures.remove(index)),
+                None if !failures.is_empty() => Err(failures.remove(0)),
+                None => Err(JSPTemplateError::NoRouteError(matcher.name(node).to_string())),
+            }
+        }
+        _ => Err(JSPTemplateError::AmbiguousRouteError(matcher.name(node).to_string(), built.join(", "))),
+    }
+}
+
+// build the path along a route, failing at the first node which cannot be filled
+fn fill(matcher: &Matcher, route: &[NIndex], values: &HashMap<String, String>) -> Result<String, JSPTemplateError> {
+    let mut path = String::new();
+    for node in route {
+        let name = matcher.name(*node);
+        let jnode = &matcher.graph()[*node];
+        let component = match jnode.identity() {
+            NodeType::Simple(value) => value.clone(),
+            _ => {
+                let key = jnode.metadata().varname().unwrap_or(name);
+                let value = values.get(key)
+                    .ok_or_else(|| JSPTemplateError::MissingValueError(name.to_string(), key.to_string()))?;
+                let invalid = |reason: String| JSPTemplateError::InvalidValueError(key.to_string(), value.clone(), name.to_string(), reason);
+                if !is_path_component(value) {
+                    return Err(invalid("not a single directory name".to_string()));
+                }
+                matcher.test(*node, value).map_err(|rejection| invalid(rejection.to_string()))?;
+                value.clone()
+            }
+        };
+        path.push('/');
+        path.push_str(&component);
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_template;
+
+    const TEMPLATE: &str = "[regex]\nshow = \"[A-Z]+\" \"(REF)\"\n[nodes]\ndd\nshows\nshow = $show [varname: DD_SHOW]\nseq = \"[A-Z]{2}\" [varname: DD_SEQUENCE]\nshot = \"[A-Z]{2}_[0-9]{4}\" [varname: DD_SHOT]\nref = REF\nlib = \"[a-z]+\"\nassets\nasset = \"[a-z]+\" [varname: DD_ASSET]\n[graph]\nroot -> dd -> shows -> show -> seq -> shot\ndd -> ref -> lib\nshow -> assets -> asset\nshot -> assets\n";
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn fills_literals_and_regexes() {
+        let (graph, keymap) = load_template(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let shot = values(&[("DD_SHOW", "FOO"), ("DD_SEQUENCE", "AA"), ("DD_SHOT", "AA_0100")]);
+        assert_eq!(build_path(&matcher, keymap["shot"], &shot).unwrap(), "/dd/shows/FOO/AA/AA_0100");
+        // a regex node without a varname is set by its name
+        assert_eq!(build_path(&matcher, keymap["lib"], &values(&[("lib", "python")])).unwrap(), "/dd/REF/python");
+        assert_eq!(build_path(&matcher, keymap["root"], &HashMap::new()).unwrap(), "/");
+    }
+
+    #[test]
+    fn reports_invalid_and_missing_values() {
+        let (graph, keymap) = load_template(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        match build_path(&matcher, keymap["shot"], &values(&[("DD_SHOW", "REF"), ("DD_SEQUENCE", "AA"), ("DD_SHOT", "AA_0100")])) {
+            Err(JSPTemplateError::InvalidValueError(key, value, node, _)) => assert_eq!((&*key, &*value, &*node), ("DD_SHOW", "REF", "show")),
+            other => panic!("expected an invalid value, got {:?}", other),
+        }
+        match build_path(&matcher, keymap["shot"], &values(&[("DD_SHOW", "FOO"), ("DD_SEQUENCE", "A/B"), ("DD_SHOT", "AA_0100")])) {
+            Err(JSPTemplateError::InvalidValueError(key, ..)) => assert_eq!(key, "DD_SEQUENCE"),
+            other => panic!("expected an invalid value, got {:?}", other),
+        }
+        match build_path(&matcher, keymap["shot"], &values(&[("DD_SHOW", "FOO"), ("DD_SHOT", "AA_0100")])) {
+            Err(JSPTemplateError::MissingValueError(node, key)) => assert_eq!((&*node, &*key), ("seq", "DD_SEQUENCE")),
+            other => panic!("expected a missing value, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn chooses_between_routes_by_their_values() {
+        let (graph, keymap) = load_template(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        // without a shot, only the route through show can be filled
+        let show = values(&[("DD_SHOW", "FOO"), ("DD_ASSET", "chair")]);
+        assert_eq!(build_path(&matcher, keymap["asset"], &show).unwrap(), "/dd/shows/FOO/assets/chair");
+        let both = values(&[("DD_SHOW", "FOO"), ("DD_SEQUENCE", "AA"), ("DD_SHOT", "AA_0100"), ("DD_ASSET", "chair")]);
+        match build_path(&matcher, keymap["asset"], &both) {
+            Err(JSPTemplateError::AmbiguousRouteError(node, paths)) => {
+                assert_eq!(node, "asset");
+                assert_eq!(paths, "/dd/shows/FOO/AA/AA_0100/assets/chair, /dd/shows/FOO/assets/chair");
+            }
+            other => panic!("expected an ambiguous route, got {:?}", other),
+        }
+    }
+}
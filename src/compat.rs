@@ -0,0 +1,241 @@
+//! Check that a new version of a template accepts every path the old one did.
+//!
+//! The old and new templates are walked together from `root`. At each step we
+//! track the old node reached, along with every new node which the same path
+//! could have reached. For each child of the old node, the automata of the
+//! child and of the new candidates are explored together, which tells us which
+//! combinations of new candidates a component accepted by the old child may
+//! match. A component which matches none of them yields a counterexample.
+use crate::{Matcher, automaton::{Nfa, acceptance_witnesses}};
+use jsp::NodeType;
+use std::{collections::{HashMap, HashSet, VecDeque}, fmt};
+
+// the maximum number of product states explored when comparing a node with its
+// candidates
+const STATE_LIMIT: usize = 50_000;
+
+/// A path which the old template accepts and the new one does not
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Counterexample {
+    path: String,
+    route: String,
+}
+
+impl Counterexample {
+    /// Retrieve the path, such as `/dd/shows/A`
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Retrieve the route through the old template which the path matches,
+    /// as node names from root, such as `/dd/shows/show`
+    pub fn route(&self) -> &str {
+        &self.route
+    }
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (matching {} in the old template) is not valid in the new template", self.path, self.route)
+    }
+}
+
+/// The result of checking compatibility
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Compatibility {
+    counterexamples: Vec<Counterexample>,
+    undecided: Vec<String>,
+}
+
+impl Compatibility {
+    /// Retrieve the counterexamples. There is one for each route through the old
+    /// template at which paths stop being valid, and for each distinct reason they
+    /// do so, such as being too short for the new regex, or being excluded by it.
+    /// Routes beneath those are not reported.
+    pub fn counterexamples(&self) -> &[Counterexample] {
+        &self.counterexamples
+    }
+
+    /// Retrieve the routes through the old template which we could not check,
+    /// because a regex was too complex to explore or could not be parsed
+    pub fn undecided(&self) -> &[String] {
+        &self.undecided
+    }
+
+    /// Is every path valid under the old template known to be valid under the new one?
+    pub fn is_compatible(&self) -> bool {
+        self.counterexamples.is_empty() && self.undecided.is_empty()
+    }
+
+    // a route may be reached along with several sets of new nodes, but we need only
+    // mention it once
+    fn undecide(&mut self, route: String) {
+        if !self.undecided.contains(&route) {
+            self.undecided.push(route);
+        }
+    }
+}
+
+// the strings a node accepts: those its pattern accepts, less those its exclude does
+struct Language {
+    pattern: Nfa,
+    exclude: Option<Nfa>,
+}
+
+impl Language {
+    fn new(identity: &NodeType) -> Option<Self> {
+        match identity {
+            NodeType::Simple(value) => Some(Self { pattern: Nfa::literal(value), exclude: None }),
+            NodeType::RegEx { pattern, exclude, .. } => Some(Self {
+                pattern: Nfa::from_regex(pattern)?,
+                exclude: match exclude {
+                    Some(exclude) => Some(Nfa::from_regex(exclude)?),
+                    None => None,
+                },
+            }),
+            NodeType::Untracked => Some(Self { pattern: Nfa::any(), exclude: None }),
+            NodeType::Root => None,
+        }
+    }
+
+    // add our automata to the list, returning the indices of each
+    fn push<'a>(&'a self, automata: &mut Vec<&'a Nfa>) -> (usize, Option<usize>) {
+        automata.push(&self.pattern);
+        let pattern = automata.len() - 1;
+        let exclude = self.exclude.as_ref().map(|exclude| {
+            automata.push(exclude);
+            automata.len() - 1
+        });
+        (pattern, exclude)
+    }
+}
+
+// interpret the acceptances of a product exploration for a language
+fn accepts(accepted: &[bool], (pattern, exclude): (usize, Option<usize>)) -> bool {
+    accepted[pattern] && !exclude.map_or(false, |exclude| accepted[exclude])
+}
+
+/// Check whether every path which the old template accepts is accepted by the new one.
+///
+/// # Parameters
+///
+/// * `old` - a Matcher over the old template
+/// * `new` - a Matcher over the new template
+///
+/// # Returns
+/// The counterexamples found, along with the routes which could not be checked
+pub fn compat(old: &Matcher, new: &Matcher) -> Compatibility {
+    let mut result = Compatibility::default();
+    let mut old_languages = HashMap::new();
+    let mut new_languages = HashMap::new();
+    let mut failed = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    queue.push_back((old.root(), vec![new.root()], String::new(), String::new()));
+    while let Some((node, reached, path, route)) = queue.pop_front() {
+        let mut candidates = reached.iter()
+            .flat_map(|parent| new.children(*parent))
+            .filter(|candidate| *candidate != new.root())
+            .collect::<Vec<_>>();
+        candidates.sort();
+        candidates.dedup();
+        for candidate in &candidates {
+            new_languages.entry(*candidate).or_insert_with(|| Language::new(new.graph()[*candidate].identity()));
+        }
+        for child in old.children(node) {
+            if child == old.root() {
+                continue;
+            }
+            let child_route = format!("{}/{}", route, old.name(child));
+            let language = old_languages.entry(child).or_insert_with(|| Language::new(old.graph()[child].identity()));
+            let language = match language {
+                Some(language) => &*language,
+                None => {
+                    result.undecide(child_route);
+                    continue;
+                }
+            };
+            let mut automata = Vec::new();
+            let indices = language.push(&mut automata);
+            let mut candidate_indices = Vec::new();
+            let mut decidable = true;
+            for candidate in &candidates {
+                match &new_languages[candidate] {
+                    Some(candidate_language) => candidate_indices.push((*candidate, candidate_language.push(&mut automata))),
+                    None => decidable = false,
+                }
+            }
+            let witnesses = match acceptance_witnesses(&automata, STATE_LIMIT) {
+                Some(witnesses) if decidable => witnesses,
+                _ => {
+                    result.undecide(child_route);
+                    continue;
+                }
+            };
+            for (accepted, component) in witnesses {
+                if !accepts(&accepted, indices) {
+                    continue;
+                }
+                let matched = candidate_indices.iter()
+                    .filter(|(_, indices)| accepts(&accepted, *indices))
+                    .map(|(candidate, _)| *candidate)
+                    .collect::<Vec<_>>();
+                let child_path = format!("{}/{}", path, component);
+                if matched.is_empty() {
+                    if failed.insert((child_route.clone(), accepted)) {
+                        result.counterexamples.push(Counterexample { path: child_path, route: child_route.clone() });
+                    }
+                } else if visited.insert((child, matched.clone())) {
+                    queue.push_back((child, matched, child_path, child_route.clone()));
+                }
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_template;
+
+    fn check(old: &str, new: &str) -> Compatibility {
+        let (old_graph, old_keymap) = load_template(old);
+        let (new_graph, new_keymap) = load_template(new);
+        compat(&Matcher::new(&old_graph, &old_keymap).unwrap(), &Matcher::new(&new_graph, &new_keymap).unwrap())
+    }
+
+    fn paths(compatibility: &Compatibility) -> Vec<&str> {
+        compatibility.counterexamples().iter().map(|c| c.path()).collect()
+    }
+
+    const OLD: &str = "[regex]\n[nodes]\ndd\nshows\nref = REF\nshow = \"[A-Z]+\"\nseq = \"[A-Z]{2,4}\"\n[graph]\nroot -> dd -> shows -> show -> seq\ndd -> ref\n";
+
+    #[test]
+    fn template_is_compatible_with_itself() {
+        assert!(check(OLD, OLD).is_compatible());
+        let example = include_str!("../examples/jsptemplate.jspt");
+        assert!(check(example, example).is_compatible());
+    }
+
+    #[test]
+    fn widening_is_compatible() {
+        let new = "[regex]\n[nodes]\ndd\nshows\nref = REF\nshow = \"[A-Z0-9]+\"\nseq = \"[A-Z]+\"\n[graph]\nroot -> dd -> shows -> show -> seq\ndd -> ref\ndd -> shows\n";
+        assert!(check(OLD, new).is_compatible());
+    }
+
+    #[test]
+    fn narrowing_produces_counterexamples() {
+        let new = "[regex]\n[nodes]\ndd\nshows\nshow = \"[A-Z]{2,}\" \"(REF|SHARED)\"\nseq = \"[A-Z]{2,3}\"\n[graph]\nroot -> dd -> shows -> show -> seq\n";
+        let result = check(OLD, new);
+        assert_eq!(paths(&result), vec!["/dd/REF", "/dd/shows/A", "/dd/shows/REF", "/dd/shows/AA/AAAA"]);
+        assert_eq!(result.counterexamples()[1].route(), "/dd/shows/show");
+    }
+
+    #[test]
+    fn paths_may_move_between_nodes() {
+        // every old show is still valid, albeit as one of two new nodes
+        let new = "[regex]\n[nodes]\ndd\nshows\nref = REF\nshort = \"[A-Z]{1,3}\"\nlong = \"[A-Z]{4,}\"\nseq = \"[A-Z]{2,4}\"\n[graph]\nroot -> dd -> shows -> short -> seq\nshows -> long -> seq\ndd -> ref\n";
+        assert!(check(OLD, new).is_compatible());
+    }
+}
@@ -12,7 +12,7 @@ pub enum MetadataComponent {
     // to get around this in the future, as I don't like this leaking.
     Separator, 
     //NavAlias(String), 
-    //AutoCreate
+    AutoCreate,
 }
 
 /// Tracks the supported metadata values in the template, delimited
@@ -22,6 +22,7 @@ pub enum MetadataComponent {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Metadata {
     volume: bool,
+    autocreate: bool,
     permissions: Option<String>,
     varname: Option<String>,
     owner: Option<String>
@@ -33,16 +34,17 @@ impl Metadata {
     pub fn new() -> Self {
         Self {
             volume: false,
+            autocreate: false,
             permissions: None,
             varname: None,
             owner: None,
         }
     }
 
-    /// Determine whether the Metadata instance is empty, defined as the volume field being false, 
-    /// and all of the optional terms being None. 
+    /// Determine whether the Metadata instance is empty, defined as the volume and autocreate 
+    /// fields being false, and all of the optional terms being None. 
     pub fn is_empty(&self) -> bool {
-        self.volume == false && self.permissions.is_none() && self.varname.is_none() && self.owner.is_none()
+        self.volume == false && self.autocreate == false && self.permissions.is_none() && self.varname.is_none() && self.owner.is_none()
     }
 
     /// Set volume and get back moved self. This is designed to be used in 
@@ -85,6 +87,29 @@ impl Metadata {
         self.volume
     }
 
+    /// Set autocreate and get back moved self. An autocreate node is one which must 
+    /// exist whenever its parent does. 
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use jspcompile::Metadata;
+    /// 
+    /// let metadata = Metadata::new()
+    ///                 .set_autocreate(true);
+    /// 
+    /// assert_eq!(metadata.is_autocreate(), true);
+    /// ```
+    pub fn set_autocreate(mut self, is: bool) -> Self {
+        self.autocreate = is;
+        self
+    }
+
+    /// Test to see if the Metadata marks an autocreate node. 
+    pub fn is_autocreate(&self) -> bool {
+        self.autocreate
+    }
+
     /// Set permissions, passing in an Option of a type which we 
     /// can get a string from (via into). This method consumes and
     /// returns `self`, so it is convenient when using in a chained,
@@ -266,6 +291,7 @@ mod tests {
         let md = Metadata::new();
         let expect = Metadata {
             volume: false,
+            autocreate: false,
             permissions: None,
             varname: None,
             owner: None,
@@ -278,6 +304,7 @@ mod tests {
         let md = Metadata::new().set_volume(true);
         let expect = Metadata {
             volume: true,
+            autocreate: false,
             permissions: None,
             varname: None,
             owner: None,
@@ -290,6 +317,7 @@ mod tests {
         let md = Metadata::new().set_volume(true).set_owner(Some("jgerber"));
         let expect = Metadata {
             volume: true,
+            autocreate: false,
             permissions: None,
             varname: None,
             owner: Some("jgerber".to_string()),
@@ -302,6 +330,7 @@ mod tests {
         let md = Metadata::new().set_volume(true).set_owner(Some("jgerber")).set_varname(Some("jg_show"));
         let expect = Metadata {
             volume: true,
+            autocreate: false,
             permissions: None,
             varname: Some("jg_show".to_string()),
             owner: Some("jgerber".to_string()),
@@ -319,6 +348,7 @@ mod tests {
 
         let expect = Metadata {
             volume: true,
+            autocreate: false,
             permissions: Some("777".to_string()),
             varname: Some("jg_show".to_string()),
             owner: Some("jgerber".to_string()),
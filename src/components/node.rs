@@ -151,4 +151,27 @@ impl Node {
         }
     }
 
+    /// Retrieve the name of the Node, regardless of its variety. This is the 
+    /// name by which the Node is referred to in the graph section of the template.
+    pub fn name(&self) -> &str {
+        match self {
+            Node::Simple(name, _) => name,
+            Node::Pair{name, ..} => name,
+            Node::ReVar{name, ..} => name,
+            Node::RegexSimple{name, ..} => name,
+            Node::RegexComplex{name, ..} => name,
+        }
+    }
+
+    /// Retrieve the Metadata of the Node, if it has any, regardless of its variety.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        match self {
+            Node::Simple(_, metadata) => metadata.as_ref(),
+            Node::Pair{metadata, ..} => metadata.as_ref(),
+            Node::ReVar{metadata, ..} => metadata.as_ref(),
+            Node::RegexSimple{metadata, ..} => metadata.as_ref(),
+            Node::RegexComplex{metadata, ..} => metadata.as_ref(),
+        }
+    }
+
 }
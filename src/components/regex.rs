@@ -20,3 +20,13 @@ pub enum Regex {
     },
 }
 
+impl Regex {
+    /// Retrieve the name of the Regex, by which it is referred to from the 
+    /// node section of the template.
+    pub fn name(&self) -> &str {
+        match self {
+            Regex::Simple{name, ..} => name,
+            Regex::Complex{name, ..} => name,
+        }
+    }
+}
@@ -1,55 +1,381 @@
-
-//! Currently unused. This struct models an output device for communicating 
-//! with end users via the terminal. Its aim is to provide user readable 
+//! This struct models an output device for communicating
+//! with end users via the terminal. Its aim is to provide user readable
 //! communication that is clearly targeted, unlike logging, which generally
-//! is for developers and tds, and is often dismissed by end users due to 
+//! is for developers and tds, and is often dismissed by end users due to
 //! its noisy appearance.
-//!  
-//! Jspcompile currently handles this via loose functions. 
+//!
+//! All user facing messages - errors, warnings, lint findings and summaries -
+//! go through the Console, which writes to stderr by default so that they never
+//! mix with compiled output on stdout.
+use crate::{Diagnostic, JSPTemplateError, JSPTemplateLineWarning, Severity, State, Summary};
 use colored::*;
+use serde::Serialize;
+use std::{fmt, io::{self, Write}, str::FromStr};
+
+/// When to colorize output
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ColorChoice {
+    /// Colorize if writing to a terminal and NO_COLOR is not set
+    Auto,
+    /// Always colorize
+    Always,
+    /// Never colorize
+    Never,
+}
+
+impl ColorChoice {
+    /// Should output to stderr be colorized, given the choice and the environment?
+    pub fn for_stderr(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                // see https://no-color.org
+                let no_color = std::env::var_os("NO_COLOR").map_or(false, |v| !v.is_empty());
+                !no_color && atty::is(atty::Stream::Stderr)
+            }
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("invalid color choice: {}. Expected one of auto, always, never", s)),
+        }
+    }
+}
+
+/// The form in which errors and warnings are reported
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MessageFormat {
+    /// Formatted for people
+    Human,
+    /// One json object per line, for tools
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            _ => Err(format!("invalid message format: {}. Expected one of human, json", s)),
+        }
+    }
+}
+
+// an informational message, as emitted in json mode
+#[derive(Serialize)]
+struct Info<'a> {
+    severity: &'static str,
+    message: String,
+    file: Option<&'a str>,
+}
 
-#[derive(Debug, ParitalEq, Eq)]
-pub struct Console {
-    error_color: String,
+/// Reports errors, warnings, lint findings and summaries to the user.
+pub struct Console<W: Write = io::Stderr> {
+    writer: W,
+    color: bool,
+    format: MessageFormat,
+    file: Option<String>,
 }
 
-impl Console {
-    /// New up a console, given an error_color of a type which implements Into<String>
-    pub fn new<S>(error_color: S) -> Console where S: Into<String> {
-        Console {
-            error_color: error_color.into(),
+impl Console<io::Stderr> {
+    /// New up a Console which writes to stderr, colorizing according to `color`.
+    pub fn stderr(color: ColorChoice, format: MessageFormat) -> Self {
+        let color = color.for_stderr();
+        // colored makes its own decision otherwise, based on stdout
+        colored::control::set_override(color);
+        Console::new(io::stderr(), color, format)
+    }
+}
+
+impl<W: Write> Console<W> {
+    /// New up a Console which writes to the supplied writer.
+    ///
+    /// # Parameters
+    ///
+    /// * `writer` - where the messages go
+    /// * `color` - whether to colorize the messages
+    /// * `format` - whether to report errors and warnings for people, or as json
+    pub fn new(writer: W, color: bool, format: MessageFormat) -> Self {
+        Self {
+            writer,
+            color,
+            format,
+            file: None,
         }
     }
 
-    /// Display an error, given an error of a type which implements ToString.
-    pub fn error<E>(&self, error:E) where E:ToString {
-        println!("");
-        println!("{}", "Error".color(self.error_color.as_str()).bold());
-        println!("\n\t{}", error.to_string());
-        println!("");
+    /// Set the file which subsequent messages pertain to.
+    pub fn set_file<F>(&mut self, file: Option<F>) where F: Into<String> {
+        self.file = file.map(|f| f.into());
+    }
+
+    /// Retrieve the file which messages pertain to, if set
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_ref().map(|x| &**x)
+    }
+
+    /// Retrieve the message format
+    pub fn format(&self) -> MessageFormat {
+        self.format
+    }
+
+    /// Consume the Console, returning its writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Display an error. Errors which occured on a particular line are displayed
+    /// along with the line and the state of the parser.
+    pub fn error(&mut self, error: &JSPTemplateError) -> io::Result<()> {
+        if self.format == MessageFormat::Json {
+            return self.json(&Diagnostic::from(error));
+        }
+        match error {
+            JSPTemplateError::ErrorAtLine(line_num, line, state, inner) => self.error_at(*line_num, line, state, inner),
+            _ => {
+                let title = self.paint("Error", Color::Red, true);
+                writeln!(self.writer)?;
+                writeln!(self.writer, "{}{}", title, self.code(error.code()))?;
+                writeln!(self.writer, "\n\t{}", error.to_string())?;
+                self.explain_hint(error.code())?;
+                writeln!(self.writer)
+            }
+        }
     }
 
     /// Display an Error at a supplied context and error, wherein the context consists of
-    /// a line number, line, and Statemachine State, and the error is of a type which implements
-    /// ToString. 
-    pub fn error_at<E>(&self, line_num: usize, line: &str, state: &State, error: E ) where E: ToString {
-        println!("");
-        let error_title = "Error".color(self.error_color.as_str()).bold();
-        let title = "Error Parsing File".color(self.error_color.as_str()).bold();
-        let line_num_title = "LineNo".bright_red();
-        let line_title = "Line".bright_red();
-        let state_title = "State".bright_red();
-        println!("{}\n\n\t{} {}\n\t{}   {}\n\t{}  {}\n\t{}  {}", 
+    /// a line number, line, and Statemachine State.
+    pub fn error_at(&mut self, line_num: usize, line: &str, state: &State, error: &JSPTemplateError) -> io::Result<()> {
+        let title = self.paint("Error Parsing File", Color::Red, true);
+        let error_title = self.paint("Error", Color::BrightRed, false);
+        let line_num_title = self.paint("LineNo", Color::BrightRed, false);
+        let line_title = self.paint("Line", Color::BrightRed, false);
+        let state_title = self.paint("State", Color::BrightRed, false);
+        writeln!(self.writer)?;
+        writeln!(self.writer, "{}{}\n\n\t{} {}\n\t{}   {}\n\t{}  {}\n\t{}  {}",
+            title,
+            self.code(error.code()),
+            line_num_title,
+            line_num,
+            line_title,
+            line,
+            state_title,
+            state,
+            error_title,
+            error.to_string())?;
+        self.explain_hint(error.code())?;
+        writeln!(self.writer)
+    }
+
+    /// Display a warning produced while loading a template
+    pub fn warning(&mut self, warning: &JSPTemplateLineWarning) -> io::Result<()> {
+        if self.format == MessageFormat::Json {
+            return self.json(&Diagnostic::from(warning));
+        }
+        let JSPTemplateLineWarning::WarningAtLine(line_num, line, state, warning) = warning;
+        let title = self.paint("Warning", Color::Yellow, true);
+        let warning_title = self.paint("Warning", Color::Yellow, false);
+        let line_num_title = self.paint("LineNo", Color::Yellow, false);
+        let line_title = self.paint("Line", Color::Yellow, false);
+        let state_title = self.paint("State", Color::Yellow, false);
+        writeln!(self.writer)?;
+        writeln!(self.writer, "{}{}\n\n\t{}  {}\n\t{}    {}\n\t{}   {}\n\t{} {}",
             title,
+            self.code(warning.code()),
             line_num_title,
-            line_num.to_string(),
-            line_title, 
+            line_num,
+            line_title,
             line,
             state_title,
-            state, 
-            error_title,        
-            error.to_string());
+            state,
+            warning_title,
+            warning.to_string())?;
+        self.explain_hint(warning.code())?;
+        writeln!(self.writer)
+    }
+
+    /// Display a lint finding. Findings arrive as Diagnostics, and are displayed
+    /// as a single line, in the style of a compiler, prefixed by their location.
+    pub fn lint(&mut self, finding: &Diagnostic) -> io::Result<()> {
+        if self.format == MessageFormat::Json {
+            return self.json(finding);
+        }
+        let label = match finding.severity() {
+            Severity::Error => self.paint("error", Color::Red, true),
+            Severity::Warning => self.paint("warning", Color::Yellow, true),
+        };
+        let file = finding.file().or_else(|| self.file()).map(|f| f.to_string());
+        let location = match (file, finding.line(), finding.columns()) {
+            (Some(file), Some(line), Some((column, _))) => format!("{}:{}:{}: ", file, line, column),
+            (Some(file), Some(line), None) => format!("{}:{}: ", file, line),
+            (Some(file), None, _) => format!("{}: ", file),
+            (None, Some(line), _) => format!("line {}: ", line),
+            (None, None, _) => String::new(),
+        };
+        writeln!(self.writer, "{}{}[{}]: {}", location, label, finding.code(), finding.message())
+    }
+
+    /// Display the summary of a successfully loaded template. Summaries are
+    /// for people, so nothing is displayed in json mode.
+    pub fn success(&mut self, summary: &Summary) -> io::Result<()> {
+        if self.format == MessageFormat::Json {
+            return Ok(());
+        }
+        let title = self.paint("Success", Color::Green, true);
+        match self.file.clone() {
+            Some(file) => writeln!(self.writer, "{} {}: {}", title, file, summary),
+            None => writeln!(self.writer, "{} {}", title, summary),
+        }
+    }
+
+    /// Display a message which is neither an error nor a warning. In json mode,
+    /// the message is emitted as an object with the severity `info`, so that
+    /// every line remains json.
+    pub fn info<M>(&mut self, message: M) -> io::Result<()> where M: fmt::Display {
+        if self.format == MessageFormat::Json {
+            let info = Info { severity: "info", message: message.to_string(), file: self.file() };
+            return writeln!(self.writer, "{}", serde_json::to_string(&info).expect("Info serializes to json"));
+        }
+        writeln!(self.writer, "{}", message)
+    }
+
+    // Emit a diagnostic as a single line of json, attributed to the current file
+    // unless it already names one
+    fn json(&mut self, diagnostic: &Diagnostic) -> io::Result<()> {
+        let diagnostic = match (diagnostic.file(), self.file.clone()) {
+            (None, Some(file)) => diagnostic.clone().with_file(file),
+            _ => diagnostic.clone(),
+        };
+        writeln!(self.writer, "{}", diagnostic.to_json())
+    }
+
+    // Point the user at the detailed explanation of a code
+    fn explain_hint(&mut self, code: &str) -> io::Result<()> {
+        writeln!(self.writer, "\n\trun `jspcompile --explain {}` for more information", code)
+    }
+
+    // format a code for display after a title
+    fn code(&self, code: &str) -> String {
+        format!(" [{}]", code)
+    }
+
+    // colorize the text if color is enabled
+    fn paint(&self, text: &str, color: Color, bold: bool) -> String {
+        if !self.color {
+            return text.to_string();
+        }
+        let colored = text.color(color);
+        if bold { colored.bold().to_string() } else { colored.to_string() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{JSPTemplateWarning, spelling::Suggestions};
+
+    fn console(format: MessageFormat) -> Console<Vec<u8>> {
+        let mut console = Console::new(Vec::new(), false, format);
+        console.set_file(Some("show.jspt"));
+        console
+    }
+
+    fn output(console: Console<Vec<u8>>) -> String {
+        String::from_utf8(console.into_inner()).unwrap()
+    }
+
+    fn error() -> JSPTemplateError {
+        JSPTemplateError::ErrorAtLine(
+            7,
+            "dd -> shows -> sequence".to_string(),
+            State::EdgeParsing,
+            Box::new(JSPTemplateError::KeyMapLookupError("sequence".to_string(), Suggestions::new(vec!["seq".to_string()])))
+        )
+    }
+
+    #[test]
+    fn parses_choices() {
+        assert_eq!("never".parse::<ColorChoice>(), Ok(ColorChoice::Never));
+        assert_eq!("json".parse::<MessageFormat>(), Ok(MessageFormat::Json));
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+    }
+
+    #[test]
+    fn displays_error_at_line() {
+        let mut console = console(MessageFormat::Human);
+        console.error(&error()).unwrap();
+        let output = output(console);
+        assert!(output.contains("Error Parsing File [JSP0002]"));
+        assert!(output.contains("LineNo 7"));
+        assert!(output.contains("did you mean `seq`?"));
+        assert!(output.contains("jspcompile --explain JSP0002"));
+        // no escape codes when color is disabled
+        assert!(!output.contains('\u{1b}'));
+    }
+
+    #[test]
+    fn displays_error_as_json() {
+        let mut console = console(MessageFormat::Json);
+        console.error(&error()).unwrap();
+        let output = output(console);
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.starts_with(r#"{"severity":"error","code":"JSP0002""#));
+        assert!(output.contains(r#""file":"show.jspt""#));
+    }
+
+    #[test]
+    fn displays_warning() {
+        let mut console = console(MessageFormat::Human);
+        let warning = JSPTemplateLineWarning::from((
+            7,
+            "dd".to_string(),
+            State::NodeParsing,
+            JSPTemplateWarning::DuplicateNode("dd".to_string(), 5)
+        ));
+        console.warning(&warning).unwrap();
+        assert!(output(console).contains("Warning [JSP1001]"));
+    }
+
+    #[test]
+    fn displays_lint_finding_on_one_line() {
+        let mut console = console(MessageFormat::Human);
+        console.lint(&Diagnostic::from(&error())).unwrap();
+        assert_eq!(
+            output(console),
+            "show.jspt:7:16: error[JSP0002]: key Map Lookup failed for: sequence; did you mean `seq`?\n"
+        );
+    }
+
+    #[test]
+    fn displays_summary() {
+        let mut human = console(MessageFormat::Human);
+        human.success(&Summary { nodes: 3, regexes: 1, edges: 3, warnings: 0 }).unwrap();
+        assert_eq!(output(human), "Success show.jspt: 3 nodes, 1 regexes, 3 edges, 0 warnings\n");
+
+        let mut json = console(MessageFormat::Json);
+        json.success(&Summary::default()).unwrap();
+        assert_eq!(output(json), "");
+    }
+
+    #[test]
+    fn displays_info_as_json() {
+        let mut human = console(MessageFormat::Human);
+        human.info("formatted show.jspt").unwrap();
+        assert_eq!(output(human), "formatted show.jspt\n");
 
-        println!("")  
+        let mut json = console(MessageFormat::Json);
+        json.info("formatted show.jspt").unwrap();
+        assert_eq!(output(json), "{\"severity\":\"info\",\"message\":\"formatted show.jspt\",\"file\":\"show.jspt\"}\n");
     }
 }
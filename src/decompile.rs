@@ -0,0 +1,320 @@
+//! Reconstruct jspt source from a compiled JGraph.
+//!
+//! A compiled graph does not record the names of its nodes, so names are chosen
+//! from what each node matches: the literal value, lowercased, or for a regex,
+//! the last part of its varname or else the name of the regex. A name which is
+//! already taken is prefixed with the name of the node's parent, and then
+//! numbered. Regexes used by more than one node are hoisted into the `[regex]`
+//! section, and the edges are written as chains, each continuing for as long as
+//! the last node has an edge not yet written. The result is passed through the
+//! formatter.
+use crate::{JSPTemplateError, formatter::format_template, helpers::{is_ident_char, is_regex_char}};
+use jsp::{EntryType, JGraph, NIndex, NodeType, Regexp};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Reconstruct the source of a template from its compiled graph. Loading the
+/// source produces a graph equivalent to the original, although the names of
+/// the nodes may differ. `autocreate` is not recorded in compiled graphs, and
+/// untracked nodes, which templates cannot express, become nodes matching any
+/// name.
+///
+/// # Parameters
+///
+/// * `graph` - the compiled graph
+///
+/// # Returns
+/// A Result wrapping the source if successful. Otherwise a JSPTemplateError, if
+/// the graph has no root.
+pub fn decompile(graph: &JGraph) -> Result<String, JSPTemplateError> {
+    let root = graph.node_indices().find(|node| match graph[*node].identity() {
+        NodeType::Root => true,
+        _ => false,
+    }).ok_or(JSPTemplateError::MissingRootError)?;
+    let order = order(graph, root);
+    let names = name_nodes(graph, root, &order);
+    let regexes = hoist_regexes(graph, &order);
+
+    let mut source = String::from("[regex]\n");
+    let mut entries = regexes.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(_, (_, index))| *index);
+    for ((pattern, exclude), (name, _)) in entries {
+        source.push_str(&format!("{} = {}\n", name, quote(pattern, exclude.as_ref().map(|x| &**x))));
+    }
+
+    source.push_str("\n[nodes]\n");
+    for node in order.iter().filter(|node| **node != root) {
+        let name = &names[node];
+        let jnode = &graph[*node];
+        let definition = match jnode.identity() {
+            NodeType::Simple(value) if value == name => name.clone(),
+            NodeType::Simple(value) if value.chars().all(is_ident_char) && !value.is_empty() => format!("{} = {}", name, value),
+            NodeType::Simple(value) => format!("{} = {}", name, quote(&ext_regex::escape(value), None)),
+            NodeType::RegEx { pattern, exclude, .. } => {
+                let key = regex_key(pattern, exclude.as_ref());
+                match regexes.get(&key) {
+                    Some((regex, _)) => format!("{} = ${}", name, regex),
+                    None => format!("{} = {}", name, quote(&key.0, key.1.as_ref().map(|x| &**x))),
+                }
+            }
+            NodeType::Untracked => format!("{} = {}", name, quote(".+", None)),
+            NodeType::Root => unreachable!("there is only one root"),
+        };
+        source.push_str(&definition);
+        let metadata = metadata(jnode);
+        if !metadata.is_empty() {
+            source.push_str(&format!(" [{}]", metadata.join(", ")));
+        }
+        source.push('\n');
+    }
+
+    source.push_str("\n[graph]\n");
+    for chain in chains(graph, &order) {
+        let chain = chain.iter().map(|node| &*names[node]).collect::<Vec<_>>();
+        source.push_str(&format!("{}\n", chain.join(" -> ")));
+    }
+    format_template(&source)
+}
+
+// the nodes in breadth first order from root, followed by any unreachable nodes
+fn order(graph: &JGraph, root: NIndex) -> Vec<NIndex> {
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(root);
+    visited.insert(root);
+    while let Some(node) = queue.pop_front() {
+        order.push(node);
+        for child in children(graph, node) {
+            if visited.insert(child) {
+                queue.push_back(child);
+            }
+        }
+    }
+    order.extend(graph.node_indices().filter(|node| !visited.contains(node)));
+    order
+}
+
+// the children of a node, each listed once, in the order in which they were defined
+fn children(graph: &JGraph, node: NIndex) -> Vec<NIndex> {
+    let mut children = graph.neighbors(node).collect::<Vec<_>>();
+    children.sort();
+    children.dedup();
+    children
+}
+
+// choose a unique name for each node, visiting parents before their children
+fn name_nodes(graph: &JGraph, root: NIndex, order: &[NIndex]) -> HashMap<NIndex, String> {
+    let mut parents = HashMap::new();
+    for node in order {
+        for child in children(graph, *node) {
+            parents.entry(child).or_insert(*node);
+        }
+    }
+    let mut names = HashMap::new();
+    let mut taken = HashSet::new();
+    names.insert(root, "root".to_string());
+    taken.insert("root".to_string());
+    for node in order.iter().filter(|node| **node != root) {
+        // a varname such as DD_SHOW is the best clue as to what a regex node was called
+        let varname = graph[*node].metadata().varname()
+            .and_then(|varname| varname.rsplit('_').next())
+            .filter(|name| !name.is_empty());
+        let base = match (graph[*node].identity(), varname) {
+            (NodeType::Simple(value), _) => sanitize(&value.to_lowercase()),
+            (_, Some(name)) => sanitize(&name.to_lowercase()),
+            (NodeType::RegEx { name, .. }, None) => sanitize(name),
+            _ => "untracked".to_string(),
+        };
+        let parent = parents.get(node).and_then(|parent| names.get(parent)).map(|parent| parent.as_str());
+        let name = unique_name(&base, parent, &mut taken);
+        names.insert(*node, name);
+    }
+    names
+}
+
+// name each regex used by more than one node, keyed by its unanchored pattern and
+// exclude, and ordered by first use
+fn hoist_regexes(graph: &JGraph, order: &[NIndex]) -> HashMap<(String, Option<String>), (String, usize)> {
+    let mut uses: HashMap<(String, Option<String>), (String, usize, usize)> = HashMap::new();
+    for node in order {
+        if let NodeType::RegEx { name, pattern, exclude } = graph[*node].identity() {
+            let first = uses.len();
+            let entry = uses.entry(regex_key(pattern, exclude.as_ref())).or_insert_with(|| (sanitize(name), 0, first));
+            entry.1 += 1;
+        }
+    }
+    let mut hoisted = uses.into_iter().filter(|(_, (_, count, _))| *count > 1).collect::<Vec<_>>();
+    hoisted.sort_by_key(|(_, (_, _, first))| *first);
+    let mut taken = HashSet::new();
+    hoisted.into_iter().map(|(key, (base, _, first))| {
+        let name = unique_name(&base, None, &mut taken);
+        (key, (name, first))
+    }).collect()
+}
+
+// make `base` unique among the names taken, first by prefixing the name of its
+// parent, unless that is `root` or `base` itself, and then by numbering it. The
+// name chosen is added to those taken
+pub(crate) fn unique_name(base: &str, parent: Option<&str>, taken: &mut HashSet<String>) -> String {
+    let prefixed = parent
+        .filter(|parent| *parent != "root" && *parent != base)
+        .map(|parent| format!("{}_{}", parent, base));
+    let name = std::iter::once(base.to_string())
+        .chain(prefixed)
+        .chain((2..).map(|suffix| format!("{}_{}", base, suffix)))
+        .find(|candidate| !taken.contains(candidate))
+        .expect("there is always another suffix");
+    taken.insert(name.clone());
+    name
+}
+
+// the pattern and exclude of a regex node, without the anchors added by the Loader
+fn regex_key(pattern: &Regexp, exclude: Option<&Regexp>) -> (String, Option<String>) {
+    (unanchor(pattern.as_str()), exclude.map(|exclude| unanchor(exclude.as_str())))
+}
+
+// remove the anchors added by the Loader. A regex without them matches anywhere
+// within a name, which we have to spell out, as the Loader will anchor it.
+fn unanchor(regex: &str) -> String {
+    if regex.len() > 1 && regex.starts_with('^') && regex.ends_with('$') && !regex.ends_with("\\$") {
+        regex[1..regex.len() - 1].to_string()
+    } else {
+        format!(".*(?:{}).*", regex)
+    }
+}
+
+// quote a regex, and its exclude if there is one. Quoted regexes may only contain
+// printable ascii other than spaces and quotes, so anything else is escaped.
+pub(crate) fn quote(pattern: &str, exclude: Option<&str>) -> String {
+    let escape = |regex: &str| regex.chars().map(|c| {
+        if is_regex_char(c) { c.to_string() } else { format!("\\x{{{:X}}}", c as u32) }
+    }).collect::<String>();
+    match exclude {
+        Some(exclude) => format!("\"{}\" \"{}\"", escape(pattern), escape(exclude)),
+        None => format!("\"{}\"", escape(pattern)),
+    }
+}
+
+// turn anything into a valid name
+pub(crate) fn sanitize(name: &str) -> String {
+    let name = name.chars().map(|c| if is_ident_char(c) { c } else { '_' }).collect::<String>();
+    if name.is_empty() { "node".to_string() } else { name }
+}
+
+// the metadata of a node, as written in the template
+fn metadata(node: &jsp::Node) -> Vec<String> {
+    let mut components = Vec::new();
+    if *node.entry_type() == EntryType::Volume {
+        components.push("volume".to_string());
+    }
+    if let Some(owner) = node.metadata().owner() {
+        components.push(format!("owner: {}", owner));
+    }
+    if let Some(perms) = node.metadata().perms() {
+        components.push(format!("perms: {}", perms));
+    }
+    if let Some(varname) = node.metadata().varname() {
+        components.push(format!("varname: {}", varname));
+    }
+    components
+}
+
+// cover every edge with chains, starting from each node in turn and following the
+// first edge not yet written for as long as there is one
+fn chains(graph: &JGraph, order: &[NIndex]) -> Vec<Vec<NIndex>> {
+    let mut written = HashSet::new();
+    let mut chains = Vec::new();
+    for start in order {
+        loop {
+            let mut chain = vec![*start];
+            let mut current = *start;
+            while let Some(next) = children(graph, current).into_iter().find(|child| !written.contains(&(current, *child))) {
+                written.insert((current, next));
+                chain.push(next);
+                current = next;
+            }
+            if chain.len() == 1 {
+                break;
+            }
+            chains.push(chain);
+        }
+    }
+    chains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{JGraphKeyMap, loader::load_template, Matcher, matcher::{describe_identity, describe_metadata}, samples::node_paths};
+    use std::collections::BTreeSet;
+
+    // describe each route from root by what its nodes match, rather than by their
+    // names, which decompiling does not preserve
+    fn signature(graph: &JGraph, keymap: &JGraphKeyMap) -> BTreeSet<String> {
+        let matcher = Matcher::new(graph, keymap).unwrap();
+        node_paths(&matcher, None).iter().map(|route| {
+            route.iter().map(|node| {
+                let jnode = &graph[*node];
+                format!("{} {} {:?}", describe_identity(jnode.identity()), describe_metadata(jnode), jnode.metadata().varname())
+            }).collect::<Vec<_>>().join(" / ")
+        }).collect()
+    }
+
+    fn round_trip(template: &str) -> String {
+        let (graph, keymap) = load_template(template);
+        let source = decompile(&graph).unwrap();
+        let (decompiled, decompiled_keymap) = load_template(&source);
+        assert_eq!(signature(&graph, &keymap), signature(&decompiled, &decompiled_keymap), "{}", source);
+        assert_eq!(graph.node_count(), decompiled.node_count());
+        source
+    }
+
+    #[test]
+    fn round_trips_the_example_template() {
+        round_trip(include_str!("../examples/jsptemplate.jspt"));
+    }
+
+    #[test]
+    fn names_nodes_hoists_regexes_and_chains_edges() {
+        let template = "[regex]\nlevel = \"[A-Z]+\"\n[nodes]\ndd\nshows\nshow = $level [owner: jobsys, perms: 751, varname: DD_SHOW]\nseq = $level\nref = REF [volume]\netc\nseq_etc = etc\nversion = \"v[0-9]+\"\n[graph]\nroot -> dd -> shows -> show -> seq -> seq_etc\nshow -> etc\ndd -> ref -> version\n";
+        assert_eq!(
+            round_trip(template),
+            "[regex]\n\
+             level = \"[A-Z]+\"\n\
+             \n\
+             [nodes]\n\
+             dd\n\
+             shows\n\
+             ref       = REF [volume]\n\
+             show      = $level [owner: jobsys, perms: 751, varname: DD_SHOW]\n\
+             version   = \"v[0-9]+\"\n\
+             level     = $level\n\
+             etc\n\
+             level_etc = etc\n\
+             \n\
+             [graph]\n\
+             root -> dd -> shows -> show -> level -> level_etc\n\
+             dd -> ref -> version\n\
+             show -> etc\n"
+        );
+    }
+
+    #[test]
+    fn reports_a_graph_without_a_root() {
+        match decompile(&JGraph::new()) {
+            Err(JSPTemplateError::MissingRootError) => (),
+            other => panic!("expected a missing root, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn makes_names_unique() {
+        let mut taken = HashSet::new();
+        taken.insert("root".to_string());
+        assert_eq!(unique_name("etc", Some("root"), &mut taken), "etc");
+        assert_eq!(unique_name("etc", Some("show"), &mut taken), "show_etc");
+        assert_eq!(unique_name("etc", Some("show"), &mut taken), "etc_2");
+        assert_eq!(unique_name("etc", Some("etc"), &mut taken), "etc_3");
+        assert_eq!(unique_name("root", None, &mut taken), "root_2");
+    }
+}
@@ -0,0 +1,272 @@
+//! Diagnostics are a presentation neutral description of the errors and
+//! warnings produced while compiling a template. They carry everything a
+//! tool needs in order to report a problem - severity, code, message, location
+//! and suggestions - and may be serialized to JSON without going through the
+//! formatting used by the cli.
+//!
+//! Line and column numbers are one based. The column span is half open; that is,
+//! `column_end` is the column after the last offending character.
+use crate::{JSPTemplateError, JSPTemplateLineWarning, JSPTemplateWarning, helpers::is_ident_char};
+use serde::Serialize;
+
+/// How serious a diagnostic is. Errors prevent the template from compiling.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single error or warning, along with its context.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Diagnostic {
+    severity: Severity,
+    code: String,
+    message: String,
+    file: Option<String>,
+    line: Option<usize>,
+    column_start: Option<usize>,
+    column_end: Option<usize>,
+    state: Option<String>,
+    suggestions: Vec<String>,
+}
+
+impl Diagnostic {
+    /// New up a Diagnostic with a severity, code and message, but no context.
+    pub fn new<C, M>(severity: Severity, code: C, message: M) -> Self
+    where
+        C: Into<String>,
+        M: Into<String>
+    {
+        Self {
+            severity,
+            code: code.into(),
+            message: message.into(),
+            file: None,
+            line: None,
+            column_start: None,
+            column_end: None,
+            state: None,
+            suggestions: Vec::new(),
+        }
+    }
+
+    /// Set the file that the diagnostic pertains to. The library does not know
+    /// where the template came from, so this is up to the caller. Consumes and
+    /// returns `self`, for use in a fluent api.
+    pub fn with_file<F>(mut self, file: F) -> Self
+    where
+        F: Into<String>
+    {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Retrieve the severity
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Retrieve the code identifying the kind of problem
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// Retrieve the message describing the problem
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Retrieve the file, if set
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_ref().map(|x| &**x)
+    }
+
+    /// Retrieve the one based line number, if the problem is associated with a line
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    /// Retrieve the one based, half open column span of the problem, if known
+    pub fn columns(&self) -> Option<(usize, usize)> {
+        match (self.column_start, self.column_end) {
+            (Some(start), Some(end)) => Some((start, end)),
+            _ => None,
+        }
+    }
+
+    /// Retrieve the state that the StateMachine was in when the problem was found
+    pub fn state(&self) -> Option<&str> {
+        self.state.as_ref().map(|x| &**x)
+    }
+
+    /// Retrieve the names suggested as replacements for a misspelled one
+    pub fn suggestions(&self) -> &[String] {
+        &self.suggestions
+    }
+
+    /// Serialize the diagnostic as a single line of JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Diagnostic serializes to json")
+    }
+
+    // set the location from a line number, the line, and the zero based
+    // offsets of the offending text within it
+    fn set_location(&mut self, line_num: usize, span: Option<(usize, usize)>) {
+        self.line = Some(line_num);
+        if let Some((start, end)) = span {
+            self.column_start = Some(start + 1);
+            self.column_end = Some(end + 1);
+        }
+    }
+}
+
+impl<'a> From<&'a JSPTemplateError> for Diagnostic {
+    fn from(error: &'a JSPTemplateError) -> Self {
+        match error {
+            JSPTemplateError::ErrorAtLine(line_num, line, state, inner) => {
+                let mut diagnostic = Diagnostic::from(&**inner);
+                diagnostic.set_location(*line_num, span(line, inner));
+                diagnostic.state = Some(state.to_string());
+                diagnostic
+            }
+            _ => {
+                let mut diagnostic = Diagnostic::new(Severity::Error, error.code(), error.to_string());
+                diagnostic.suggestions = match error {
+                    JSPTemplateError::KeyMapLookupError(_, suggestions) |
+                    JSPTemplateError::RegexMapLookupError(_, suggestions) |
+                    JSPTemplateError::UnknownHeader(_, suggestions) => suggestions.names().to_vec(),
+                    JSPTemplateError::ParsingError(e) => match e.kind() {
+                        crate::ParseErrorKind::UnknownMetadataKey(_, suggestions) => suggestions.names().to_vec(),
+                        _ => Vec::new(),
+                    },
+                    _ => Vec::new(),
+                };
+                diagnostic
+            }
+        }
+    }
+}
+
+impl<'a> From<&'a JSPTemplateLineWarning> for Diagnostic {
+    fn from(warning: &'a JSPTemplateLineWarning) -> Self {
+        let JSPTemplateLineWarning::WarningAtLine(line_num, line, state, warning) = warning;
+        let mut diagnostic = Diagnostic::new(Severity::Warning, warning.code(), warning.to_string());
+        let span = match warning {
+            JSPTemplateWarning::DuplicateNode(name, _) |
+            JSPTemplateWarning::DuplicateRegex(name, _) => find_word(line, name),
+            JSPTemplateWarning::DuplicateEdge(..) => {
+                let trimmed = line.trim();
+                let start = line.len() - line.trim_start().len();
+                Some((start, start + trimmed.len()))
+            }
+        };
+        diagnostic.set_location(*line_num, span);
+        diagnostic.state = Some(state.to_string());
+        diagnostic
+    }
+}
+
+// Identify the offending text within the line, as zero based offsets, if possible
+fn span(line: &str, error: &JSPTemplateError) -> Option<(usize, usize)> {
+    match error {
+        JSPTemplateError::ParsingError(e) => Some(e.span()),
+        JSPTemplateError::KeyMapLookupError(name, _) |
+        JSPTemplateError::RegexMapLookupError(name, _) |
+        JSPTemplateError::UnknownHeader(name, _) => find_word(line, name),
+        _ => None,
+    }
+}
+
+// Find the first occurrence of `word` in `line` which is not part of a longer
+// identifier, returning its zero based start and end offsets.
+fn find_word(line: &str, word: &str) -> Option<(usize, usize)> {
+    let mut from = 0;
+    while let Some(idx) = line[from..].find(word) {
+        let start = from + idx;
+        let end = start + word.len();
+        let before = line[..start].chars().next_back().map_or(false, is_ident_char);
+        let after = line[end..].chars().next().map_or(false, is_ident_char);
+        if !before && !after {
+            return Some((start, end));
+        }
+        from = end;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParseError, ParseErrorKind, State, spelling::Suggestions};
+
+    fn at_line(line_num: usize, line: &str, state: State, error: JSPTemplateError) -> JSPTemplateError {
+        JSPTemplateError::ErrorAtLine(line_num, line.to_string(), state, Box::new(error))
+    }
+
+    #[test]
+    fn parsing_error_has_location() {
+        let error = at_line(
+            4,
+            "show = $level [ onwer: jobsys ]",
+            State::NodeParsing,
+            JSPTemplateError::from(ParseError::new(
+                ParseErrorKind::UnknownMetadataKey("onwer".to_string(), Suggestions::new(vec!["owner".to_string()])),
+                16,
+                21
+            ))
+        );
+        let diagnostic = Diagnostic::from(&error).with_file("show.jspt");
+        assert_eq!(diagnostic.severity(), Severity::Error);
+        assert_eq!(diagnostic.code(), "JSP0114");
+        assert_eq!(diagnostic.file(), Some("show.jspt"));
+        assert_eq!(diagnostic.line(), Some(4));
+        assert_eq!(diagnostic.columns(), Some((17, 22)));
+        assert_eq!(diagnostic.state(), Some("NodeParsing"));
+        assert_eq!(diagnostic.suggestions(), &["owner".to_string()]);
+    }
+
+    #[test]
+    fn lookup_error_locates_name() {
+        let error = at_line(
+            7,
+            "dd -> shows -> sequence -> shot",
+            State::EdgeParsing,
+            JSPTemplateError::KeyMapLookupError("sequence".to_string(), Suggestions::new(vec!["seq".to_string()]))
+        );
+        let diagnostic = Diagnostic::from(&error);
+        assert_eq!(diagnostic.columns(), Some((16, 24)));
+        assert_eq!(diagnostic.message(), "key Map Lookup failed for: sequence; did you mean `seq`?");
+        assert_eq!(diagnostic.suggestions(), &["seq".to_string()]);
+    }
+
+    #[test]
+    fn finds_whole_words_only() {
+        assert_eq!(find_word("shows -> show", "show"), Some((9, 13)));
+        assert_eq!(find_word("shows -> showroom", "show"), None);
+    }
+
+    #[test]
+    fn error_without_context() {
+        let error = JSPTemplateError::InaccesibleFileError(std::path::PathBuf::from("missing.jspt"));
+        let diagnostic = Diagnostic::from(&error);
+        assert_eq!(diagnostic.line(), None);
+        assert_eq!(diagnostic.columns(), None);
+        assert_eq!(diagnostic.code(), "JSP0007");
+    }
+
+    #[test]
+    fn warning_is_serializable() {
+        let warning = JSPTemplateLineWarning::from((
+            7,
+            "dd".to_string(),
+            State::NodeParsing,
+            JSPTemplateWarning::DuplicateNode("dd".to_string(), 5)
+        ));
+        let diagnostic = Diagnostic::from(&warning).with_file("show.jspt");
+        assert_eq!(
+            diagnostic.to_json(),
+            r#"{"severity":"warning","code":"JSP1001","message":"node `dd` was already defined on line 5; the new definition replaces it","file":"show.jspt","line":7,"column_start":1,"column_end":3,"state":"NodeParsing","suggestions":[]}"#
+        );
+    }
+}
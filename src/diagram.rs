@@ -0,0 +1,195 @@
+//! Render a loaded template as a Mermaid or PlantUML diagram, for documentation
+//! which cannot use Graphviz.
+//!
+//! Each node is labelled with its name and, where it differs, the literal or regex
+//! it matches. Volumes are drawn as cylinders, and metadata is shown in a note
+//! beside the node. Regexes longer than REGEX_WIDTH characters are truncated
+//! unless asked otherwise.
+use crate::Matcher;
+use jsp::{EntryType, NIndex, NodeType};
+use std::collections::HashSet;
+
+/// The number of characters of a regex shown, unless regexes are shown in full
+pub const REGEX_WIDTH: usize = 24;
+
+// what we draw for each node
+struct Shape {
+    id: String,
+    lines: Vec<String>,
+    volume: bool,
+    notes: Vec<String>,
+}
+
+// describe every node and edge in the graph, leaving the syntax to the caller
+fn shapes(matcher: &Matcher, autocreate: &HashSet<NIndex>, full_regex: bool) -> (Vec<Shape>, Vec<(String, String)>) {
+    let graph = matcher.graph();
+    let truncate = |regex: &str| {
+        if full_regex || regex.chars().count() <= REGEX_WIDTH {
+            regex.to_string()
+        } else {
+            format!("{}…", regex.chars().take(REGEX_WIDTH - 1).collect::<String>())
+        }
+    };
+    let shapes = matcher.nodes().into_iter().map(|node| {
+        let jnode = &graph[node];
+        let name = matcher.name(node);
+        let mut lines = vec![name.to_string()];
+        match jnode.identity() {
+            NodeType::Simple(value) if value != name => lines.push(value.clone()),
+            NodeType::RegEx { pattern, exclude, .. } => {
+                lines.push(truncate(pattern.as_str()));
+                if let Some(exclude) = exclude {
+                    lines.push(format!("not {}", truncate(exclude.as_str())));
+                }
+            }
+            _ => (),
+        }
+        let metadata = jnode.metadata();
+        let mut notes = Vec::new();
+        if let Some(owner) = metadata.owner() {
+            notes.push(format!("owner: {}", owner));
+        }
+        if let Some(perms) = metadata.perms() {
+            notes.push(format!("perms: {}", perms));
+        }
+        if let Some(varname) = metadata.varname() {
+            notes.push(format!("varname: {}", varname));
+        }
+        if autocreate.contains(&node) {
+            notes.push("autocreate".to_string());
+        }
+        Shape { id: format!("n{}", node.index()), lines, volume: *jnode.entry_type() == EntryType::Volume, notes }
+    }).collect();
+    let edges = matcher.edges().into_iter()
+        .map(|(from, to)| (format!("n{}", from.index()), format!("n{}", to.index())))
+        .collect();
+    (shapes, edges)
+}
+
+/// Render the template as a Mermaid flowchart, from the top down. Volumes are
+/// drawn as cylinders, and metadata is drawn as a flag linked to its node by a
+/// dotted line.
+///
+/// # Parameters
+///
+/// * `matcher` - a Matcher over the loaded template
+/// * `autocreate` - the nodes marked `autocreate`, as reported by the Loader
+/// * `full_regex` - if true, regexes are not truncated
+///
+/// # Returns
+/// The diagram
+pub fn mermaid(matcher: &Matcher, autocreate: &HashSet<NIndex>, full_regex: bool) -> String {
+    // mermaid labels are quoted, and understand html entities and line breaks
+    let label = |lines: &[String]| lines.iter()
+        .map(|line| line.replace('"', "#quot;").replace('<', "#lt;").replace('>', "#gt;"))
+        .collect::<Vec<_>>()
+        .join("<br/>");
+    let (shapes, edges) = shapes(matcher, autocreate, full_regex);
+    let mut out = String::from("graph TD\n");
+    for shape in &shapes {
+        if shape.volume {
+            out.push_str(&format!("    {}[(\"{}\")]\n", shape.id, label(&shape.lines)));
+        } else {
+            out.push_str(&format!("    {}[\"{}\"]\n", shape.id, label(&shape.lines)));
+        }
+        if !shape.notes.is_empty() {
+            out.push_str(&format!("    {}_note>\"{}\"]\n", shape.id, label(&shape.notes)));
+            out.push_str(&format!("    {} -.- {}_note\n", shape.id, shape.id));
+        }
+    }
+    for (from, to) in &edges {
+        out.push_str(&format!("    {} --> {}\n", from, to));
+    }
+    let volumes = shapes.iter().filter(|shape| shape.volume).map(|shape| &*shape.id).collect::<Vec<_>>();
+    if !volumes.is_empty() {
+        out.push_str("    classDef volume fill:#fde9c4,stroke:#b07d18\n");
+        out.push_str(&format!("    class {} volume\n", volumes.join(",")));
+    }
+    let notes = shapes.iter().filter(|shape| !shape.notes.is_empty()).map(|shape| format!("{}_note", shape.id)).collect::<Vec<_>>();
+    if !notes.is_empty() {
+        out.push_str("    classDef note fill:#fffbd6,stroke:#c9c08a\n");
+        out.push_str(&format!("    class {} note\n", notes.join(",")));
+    }
+    out
+}
+
+/// Render the template as a PlantUML diagram. Volumes are drawn as databases,
+/// and metadata as notes to the right of their node.
+///
+/// # Parameters
+///
+/// * `matcher` - a Matcher over the loaded template
+/// * `autocreate` - the nodes marked `autocreate`, as reported by the Loader
+/// * `full_regex` - if true, regexes are not truncated
+///
+/// # Returns
+/// The diagram
+pub fn plantuml(matcher: &Matcher, autocreate: &HashSet<NIndex>, full_regex: bool) -> String {
+    // quotes and backslashes are escaped as unicode, which plantuml understands
+    // everywhere, so that regexes survive intact
+    let escape = |line: &str| line.replace('\\', "<U+005C>").replace('"', "<U+0022>");
+    let (shapes, edges) = shapes(matcher, autocreate, full_regex);
+    let mut out = String::from("@startuml\n");
+    for shape in &shapes {
+        let kind = if shape.volume { "database" } else { "rectangle" };
+        let label = shape.lines.iter().map(|line| escape(line)).collect::<Vec<_>>().join("\\n");
+        out.push_str(&format!("{} \"{}\" as {}\n", kind, label, shape.id));
+        if !shape.notes.is_empty() {
+            out.push_str(&format!("note right of {}\n", shape.id));
+            for note in &shape.notes {
+                out.push_str(&format!("  {}\n", escape(note)));
+            }
+            out.push_str("end note\n");
+        }
+    }
+    for (from, to) in &edges {
+        out.push_str(&format!("{} --> {}\n", from, to));
+    }
+    out.push_str("@enduml\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_template_with_autocreate;
+
+    const TEMPLATE: &str = "[regex]\n[nodes]\nshows\nshow = \"[A-Z]+[A-Z0-9_]*[A-Z0-9]\" [owner: jobsys, perms: 751]\nref = REF [volume]\n[graph]\nroot -> shows -> show\nshows -> ref\n";
+
+    #[test]
+    fn renders_mermaid() {
+        let (graph, keymap, autocreate) = load_template_with_autocreate(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let (shows, show, reference) = (keymap["shows"].index(), keymap["show"].index(), keymap["ref"].index());
+        let diagram = mermaid(&matcher, &autocreate, false);
+        assert!(diagram.starts_with("graph TD\n"));
+        assert!(diagram.contains(&format!("    n{}[\"show<br/>^[A-Z]+[A-Z0-9_]*[A-Z0-…\"]\n", show)));
+        assert!(diagram.contains(&format!("    n{}_note>\"owner: jobsys<br/>perms: 751\"]\n", show)));
+        assert!(diagram.contains(&format!("    n{}[(\"ref<br/>REF\")]\n", reference)));
+        assert!(diagram.contains(&format!("    n{} --> n{}\n", shows, show)));
+        assert!(diagram.contains(&format!("    class n{} volume\n", reference)));
+        let full = mermaid(&matcher, &autocreate, true);
+        assert!(full.contains("^[A-Z]+[A-Z0-9_]*[A-Z0-9]$"));
+    }
+
+    #[test]
+    fn renders_plantuml() {
+        let (graph, keymap, autocreate) = load_template_with_autocreate(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let (show, reference) = (keymap["show"].index(), keymap["ref"].index());
+        let diagram = plantuml(&matcher, &autocreate, true);
+        assert!(diagram.starts_with("@startuml\n") && diagram.ends_with("@enduml\n"));
+        assert!(diagram.contains(&format!("rectangle \"show\\n^[A-Z]+[A-Z0-9_]*[A-Z0-9]$\" as n{}\n", show)));
+        assert!(diagram.contains(&format!("note right of n{}\n  owner: jobsys\n  perms: 751\nend note\n", show)));
+        assert!(diagram.contains(&format!("database \"ref\\nREF\" as n{}\n", reference)));
+    }
+    #[test]
+    fn leaves_out_redefined_nodes() {
+        let (graph, keymap, autocreate) = load_template_with_autocreate("[regex]\n[nodes]\nshows\nshows = SHOWS\n[graph]\nroot -> shows\n");
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let diagram = mermaid(&matcher, &autocreate, false);
+        assert!(!diagram.contains('?'), "{}", diagram);
+        assert_eq!(diagram.matches("shows").count(), 1, "{}", diagram);
+        assert!(!plantuml(&matcher, &autocreate, false).contains('?'));
+    }
+}
@@ -0,0 +1,183 @@
+//! Compare two loaded templates semantically.
+//!
+//! Nodes are identified by their path of names from `root`, such as
+//! `/dd/shows/show`, rather than by NIndex or by their position in the file, so
+//! reordering a template produces no changes. A node reachable by more than one
+//! route is compared at each of them.
+use crate::{Matcher, matcher::describe_identity, samples::node_paths};
+use jsp::{EntryType, NIndex};
+use std::{collections::{BTreeMap, BTreeSet, HashSet}, fmt};
+
+/// A single difference between two templates
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Change {
+    /// A path which exists only in the new template
+    AddedNode { path: String },
+    /// A path which exists only in the old template
+    RemovedNode { path: String },
+    /// The literal or regex which the node at the path matches has changed
+    Identity { path: String, old: String, new: String },
+    /// A metadata value of the node at the path has changed
+    Metadata { path: String, key: &'static str, old: Option<String>, new: Option<String> },
+    /// An edge which exists only in the new template
+    AddedEdge { from: String, to: String },
+    /// An edge which exists only in the old template
+    RemovedEdge { from: String, to: String },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::AddedNode { path } => write!(f, "+ node {}", path),
+            Change::RemovedNode { path } => write!(f, "- node {}", path),
+            Change::Identity { path, old, new } => write!(f, "~ node {}: {} => {}", path, old, new),
+            Change::Metadata { path, key, old, new } => write!(
+                f,
+                "~ node {}: {} {} => {}",
+                path, key, old.as_ref().map_or("(none)", |x| &**x), new.as_ref().map_or("(none)", |x| &**x)
+            ),
+            Change::AddedEdge { from, to } => write!(f, "+ edge {} -> {}", from, to),
+            Change::RemovedEdge { from, to } => write!(f, "- edge {} -> {}", from, to),
+        }
+    }
+}
+
+/// Compare two templates. Changes to nodes are reported first, ordered by path,
+/// followed by changes to edges, ordered by name.
+///
+/// # Parameters
+///
+/// * `old` - a Matcher over the old template
+/// * `old_autocreate` - the nodes of the old template marked `autocreate`, as reported by the Loader
+/// * `new` - a Matcher over the new template
+/// * `new_autocreate` - the nodes of the new template marked `autocreate`, as reported by the Loader
+///
+/// # Returns
+/// The changes which turn the old template into the new one. The templates are
+/// equivalent if there are none.
+pub fn diff(old: &Matcher, old_autocreate: &HashSet<NIndex>, new: &Matcher, new_autocreate: &HashSet<NIndex>) -> Vec<Change> {
+    let (old_nodes, old_edges) = index(old);
+    let (new_nodes, new_edges) = index(new);
+    let mut changes = Vec::new();
+    let paths = old_nodes.keys().chain(new_nodes.keys()).collect::<BTreeSet<_>>();
+    for path in paths {
+        match (old_nodes.get(path), new_nodes.get(path)) {
+            (Some(_), None) => changes.push(Change::RemovedNode { path: path.clone() }),
+            (None, Some(_)) => changes.push(Change::AddedNode { path: path.clone() }),
+            (Some(old_node), Some(new_node)) => compare(path, (old, old_autocreate, *old_node), (new, new_autocreate, *new_node), &mut changes),
+            (None, None) => unreachable!("every path comes from one of the templates"),
+        }
+    }
+    for (from, to) in old_edges.difference(&new_edges) {
+        changes.push(Change::RemovedEdge { from: from.clone(), to: to.clone() });
+    }
+    for (from, to) in new_edges.difference(&old_edges) {
+        changes.push(Change::AddedEdge { from: from.clone(), to: to.clone() });
+    }
+    changes
+}
+
+// map each path from root to the node at its end, and collect the edges along the
+// way by name. Only nodes reachable from root are considered.
+fn index(matcher: &Matcher) -> (BTreeMap<String, NIndex>, BTreeSet<(String, String)>) {
+    let mut nodes = BTreeMap::new();
+    let mut edges = BTreeSet::new();
+    for route in node_paths(matcher, None) {
+        let path = route.iter().fold(String::new(), |path, node| format!("{}/{}", path, matcher.name(*node)));
+        let node = *route.last().expect("routes are never empty");
+        let parent = if route.len() > 1 { route[route.len() - 2] } else { matcher.root() };
+        edges.insert((matcher.name(parent).to_string(), matcher.name(node).to_string()));
+        nodes.insert(path, node);
+    }
+    (nodes, edges)
+}
+
+// record the differences between two nodes at the same path. Each node is given
+// along with the Matcher over its template and that template's autocreate nodes.
+fn compare(
+    path: &str,
+    (old, old_autocreate, old_index): (&Matcher, &HashSet<NIndex>, NIndex),
+    (new, new_autocreate, new_index): (&Matcher, &HashSet<NIndex>, NIndex),
+    changes: &mut Vec<Change>,
+) {
+    let (old_node, new_node) = (&old.graph()[old_index], &new.graph()[new_index]);
+    let (old_identity, new_identity) = (describe_identity(old_node.identity()), describe_identity(new_node.identity()));
+    if old_identity != new_identity {
+        changes.push(Change::Identity { path: path.to_string(), old: old_identity, new: new_identity });
+    }
+    let volume = |node: &jsp::Node| if *node.entry_type() == EntryType::Volume { Some("volume".to_string()) } else { None };
+    let owner = |node: &jsp::Node| node.metadata().owner().map(|owner| owner.to_string());
+    let perms = |node: &jsp::Node| node.metadata().perms().map(|perms| perms.to_string());
+    let varname = |node: &jsp::Node| node.metadata().varname().map(|varname| varname.to_string());
+    let autocreate = |autocreate: &HashSet<NIndex>, node: NIndex| if autocreate.contains(&node) { Some("autocreate".to_string()) } else { None };
+    let values = [
+        ("volume", volume(old_node), volume(new_node)),
+        ("autocreate", autocreate(old_autocreate, old_index), autocreate(new_autocreate, new_index)),
+        ("owner", owner(old_node), owner(new_node)),
+        ("perms", perms(old_node), perms(new_node)),
+        ("varname", varname(old_node), varname(new_node)),
+    ];
+    for (key, old, new) in values.iter().cloned() {
+        if old != new {
+            changes.push(Change::Metadata { path: path.to_string(), key, old, new });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_template_with_autocreate;
+
+    fn changes(old: &str, new: &str) -> Vec<String> {
+        let (old_graph, old_keymap, old_autocreate) = load_template_with_autocreate(old);
+        let (new_graph, new_keymap, new_autocreate) = load_template_with_autocreate(new);
+        let old = Matcher::new(&old_graph, &old_keymap).unwrap();
+        let new = Matcher::new(&new_graph, &new_keymap).unwrap();
+        diff(&old, &old_autocreate, &new, &new_autocreate).iter().map(|change| change.to_string()).collect()
+    }
+
+    const OLD: &str = "[regex]\nlevel = \"[A-Z]+\"\n[nodes]\ndd\nshows\nref = REF [volume]\nshow = $level [owner: jobsys, perms: 751]\n[graph]\nroot -> dd -> shows -> show\ndd -> ref\n";
+
+    #[test]
+    fn reordering_is_not_a_change() {
+        let reordered = "[regex]\nlevel = \"[A-Z]+\"\n[nodes]\nshow = $level [perms: 751, owner: jobsys]\nref = REF [volume]\nshows\ndd\n[graph]\ndd -> ref\nroot -> dd -> shows -> show\n";
+        assert!(changes(OLD, reordered).is_empty());
+    }
+
+    #[test]
+    fn reports_node_metadata_and_edge_changes() {
+        let new = "[regex]\nlevel = \"[A-Z]+[0-9]*\"\n[nodes]\ndd\nshows\nshow = $level [owner: jobsys, perms: 750, varname: DD_SHOW]\nwork\n[graph]\nroot -> dd -> shows -> show -> work\n";
+        assert_eq!(
+            changes(OLD, new),
+            vec![
+                "- node /dd/ref",
+                "~ node /dd/shows/show: regex: ^[A-Z]+$ => regex: ^[A-Z]+[0-9]*$",
+                "~ node /dd/shows/show: perms 751 => 750",
+                "~ node /dd/shows/show: varname (none) => DD_SHOW",
+                "+ node /dd/shows/show/work",
+                "- edge dd -> ref",
+                "+ edge show -> work",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_autocreate_changes() {
+        let new = "[regex]\nlevel = \"[A-Z]+\"\n[nodes]\ndd\nshows [autocreate]\nref = REF [volume, autocreate]\nshow = $level [owner: jobsys, perms: 751]\n[graph]\nroot -> dd -> shows -> show\ndd -> ref\n";
+        assert_eq!(
+            changes(OLD, new),
+            vec![
+                "~ node /dd/ref: autocreate (none) => autocreate",
+                "~ node /dd/shows: autocreate (none) => autocreate",
+            ]
+        );
+        assert_eq!(
+            changes(new, OLD),
+            vec![
+                "~ node /dd/ref: autocreate autocreate => (none)",
+                "~ node /dd/shows: autocreate autocreate => (none)",
+            ]
+        );
+    }
+}
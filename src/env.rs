@@ -0,0 +1,144 @@
+//! Export the `varname` metadata of the nodes along a matched path as shell
+//! environment variables, so that tools which set a shot context need not
+//! reimplement the matching.
+use crate::{Matcher, matcher::PathMatch};
+use std::str::FromStr;
+
+/// The shells for which variables may be exported
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Shell {
+    /// bash, or any other Bourne shell
+    Bash,
+    /// zsh
+    Zsh,
+    /// tcsh, or csh
+    Tcsh,
+    /// fish
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "tcsh" => Ok(Shell::Tcsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(format!("invalid shell: {}. Expected one of bash, zsh, tcsh, fish", s)),
+        }
+    }
+}
+
+impl Shell {
+    /// The command which sets an environment variable in this shell. The value is
+    /// quoted, so that it is taken literally.
+    ///
+    /// # Parameters
+    ///
+    /// * `name` - the name of the variable
+    /// * `value` - the value of the variable
+    ///
+    /// # Returns
+    /// The command, without a trailing newline
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jspcompile::env::Shell;
+    ///
+    /// assert_eq!(Shell::Bash.export("DD_SHOW", "FOO"), "export DD_SHOW='FOO'");
+    /// assert_eq!(Shell::Tcsh.export("DD_SHOW", "FOO"), "setenv DD_SHOW 'FOO'");
+    /// ```
+    pub fn export(self, name: &str, value: &str) -> String {
+        match self {
+            Shell::Bash | Shell::Zsh => format!("export {}='{}'", name, value.replace('\'', r"'\''")),
+            // tcsh expands history even within single quotes
+            Shell::Tcsh => format!("setenv {} '{}'", name, value.replace('\'', r"'\''").replace('!', r"\!")),
+            Shell::Fish => format!("set -gx {} '{}'", name, value.replace('\\', r"\\").replace('\'', r"\'")),
+        }
+    }
+}
+
+/// The variables set by a matched path: the `varname` of each node along it which
+/// has one, along with the path component which the node matched. They are listed
+/// from `root` down, and a varname which appears more than once takes its last
+/// value.
+///
+/// # Parameters
+///
+/// * `matcher` - the Matcher which matched the path
+/// * `matched` - the result of matching the path
+///
+/// # Returns
+/// The name and value of each variable
+pub fn variables(matcher: &Matcher, matched: &PathMatch) -> Vec<(String, String)> {
+    let graph = matcher.graph();
+    let mut variables: Vec<(String, String)> = Vec::new();
+    for component in matched.components() {
+        if let Some(varname) = graph[component.node()].metadata().varname() {
+            match variables.iter_mut().find(|(name, _)| name == varname) {
+                Some(variable) => variable.1 = component.component().to_string(),
+                None => variables.push((varname.to_string(), component.component().to_string())),
+            }
+        }
+    }
+    variables
+}
+
+/// The commands which export the variables set by a matched path, one per line.
+///
+/// # Parameters
+///
+/// * `matcher` - the Matcher which matched the path
+/// * `matched` - the result of matching the path
+/// * `shell` - the shell which is to run the commands
+///
+/// # Returns
+/// The commands
+pub fn script(matcher: &Matcher, matched: &PathMatch, shell: Shell) -> String {
+    variables(matcher, matched).iter()
+        .map(|(name, value)| format!("{}\n", shell.export(name, value)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_template;
+
+    const TEMPLATE: &str = "[regex]\nshow = \"[A-Z]+\" \"(REF)\"\n[nodes]\ndd\nshows\nshow = $show [varname: DD_SHOW]\nseq = \"[A-Z]{2}\" [varname: DD_SEQUENCE]\nshot = \"[A-Z]{2}_[0-9]{4}\" [varname: DD_SHOT]\nwork\n[graph]\nroot -> dd -> shows -> show -> seq -> shot -> work\n";
+
+    #[test]
+    fn lists_the_variables_along_a_path() {
+        let (graph, keymap) = load_template(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let matched = matcher.match_path("/dd/shows/FOO/AA/AA_0100/work");
+        assert_eq!(
+            variables(&matcher, &matched),
+            vec![
+                ("DD_SHOW".to_string(), "FOO".to_string()),
+                ("DD_SEQUENCE".to_string(), "AA".to_string()),
+                ("DD_SHOT".to_string(), "AA_0100".to_string()),
+            ]
+        );
+        let matched = matcher.match_path("/dd/shows/FOO");
+        assert_eq!(script(&matcher, &matched, Shell::Fish), "set -gx DD_SHOW 'FOO'\n");
+    }
+
+    #[test]
+    fn quotes_values_for_each_shell() {
+        let value = r"it's\here!";
+        assert_eq!(Shell::Bash.export("X", value), r"export X='it'\''s\here!'");
+        assert_eq!(Shell::Zsh.export("X", value), r"export X='it'\''s\here!'");
+        assert_eq!(Shell::Tcsh.export("X", value), r"setenv X 'it'\''s\here\!'");
+        assert_eq!(Shell::Fish.export("X", value), r"set -gx X 'it\'s\\here!'");
+    }
+
+    #[test]
+    fn parses_shells() {
+        assert_eq!("tcsh".parse::<Shell>(), Ok(Shell::Tcsh));
+        assert!("csh".parse::<Shell>().is_err());
+    }
+}
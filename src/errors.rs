@@ -1,5 +1,5 @@
 use failure::Fail;
-use crate::State;
+use crate::{State, spelling::Suggestions};
 use nom;
 use std::{io, path::PathBuf};
 use ext_regex;
@@ -28,9 +28,10 @@ pub enum JSPTemplateError {
     /// this state
     #[fail(display = "ErrorState")]
     ErrorState,
-    /// Failure to parse rpst
+    /// Failure to parse a line of the jspt, describing what was expected
+    /// and where on the line the problem was found
     #[fail(display = "ParsingError: {}",_0)]
-    ParsingError(String),
+    ParsingError(ParseError),
     /// Error originating in the Nom crate
     #[fail(display = "NomError: {:?}", _0)]
     NomError(String),
@@ -43,15 +44,82 @@ pub enum JSPTemplateError {
     /// Error originating in the Regex crate
     #[fail(display = "{}", _0)]
     RegexError(#[cause] ext_regex::Error),
-    /// Error looking up a key in the RegexMap
-    #[fail(display = "Regex Map Lookup failed for: {}", _0)]
-    RegexMapLookupError(String),
-    /// Error looking up a key in the KeyMap
-    #[fail(display = "key Map Lookup failed for: {}", _0)]
-    KeyMapLookupError(String),
+    /// Error looking up a key in the RegexMap, along with the closest regex names
+    #[fail(display = "Regex Map Lookup failed for: {}{}", _0, _1)]
+    RegexMapLookupError(String, Suggestions),
+    /// Error looking up a key in the KeyMap, along with the closest node names
+    #[fail(display = "key Map Lookup failed for: {}{}", _0, _1)]
+    KeyMapLookupError(String, Suggestions),
+    /// A section header which is not one of regex, nodes or graph (or their aliases)
+    #[fail(display = "Unknown section header: [{}]{}", _0, _1)]
+    UnknownHeader(String, Suggestions),
     /// Error trying to access a Non extant or Inaccessible file
     #[fail(display = "File: {:?} does not exist or we lack permissions to access it", _0)]
     InaccesibleFileError(PathBuf),
+    /// A compiled template which could not be read back, along with the reason
+    #[fail(display = "File: {:?} is not a compiled template: {}", _0, _1)]
+    CompiledTemplateError(PathBuf, String),
+    /// A name which is among both the names a regex should accept and those it should reject
+    #[fail(display = "`{}` is both accepted and rejected", _0)]
+    ConflictingExamplesError(String),
+    /// A value which does not suit the node it was given for: the name it was set by, the value,
+    /// the node, and why
+    #[fail(display = "{}={} is not a valid value for node `{}`: {}", _0, _1, _2, _3)]
+    InvalidValueError(String, String, String, String),
+    /// A node which needs a value, along with the name by which it may be set
+    #[fail(display = "no value was given for node `{}`; set one with {}=...", _0, _1)]
+    MissingValueError(String, String),
+    /// More than one route leads to a node, along with the paths they build, separated by commas
+    #[fail(display = "more than one route leads to `{}`: {}", _0, _1)]
+    AmbiguousRouteError(String, String),
+    /// A node which cannot be reached from root
+    #[fail(display = "no route leads from root to `{}`", _0)]
+    NoRouteError(String),
+    /// A compiled graph without a root node
+    #[fail(display = "compiled graph has no root node")]
+    MissingRootError,
+}
+
+impl JSPTemplateError {
+    /// Retrieve the stable code identifying the kind of error. Codes never change
+    /// once assigned, so they are safe to refer to from documentation. ParsingErrors
+    /// are broken down further by the mistake made, and use the code of their 
+    /// ParseErrorKind. ErrorAtLine uses the code of the error which it wraps.
+    /// JSP0001 is reserved, and is never assigned.
+    /// 
+    /// # Examples
+    /// 
+    /// ```
+    /// use jspcompile::{JSPTemplateError, State};
+    /// 
+    /// let error = JSPTemplateError::InvalidStateTransition(State::Start, State::EdgeParsing);
+    /// assert_eq!(error.code(), "JSP0004");
+    /// ```
+    pub fn code(&self) -> &'static str {
+        match self {
+            JSPTemplateError::ParsingError(e) => e.kind().code(),
+            JSPTemplateError::KeyMapLookupError(..) => "JSP0002",
+            JSPTemplateError::RegexMapLookupError(..) => "JSP0003",
+            JSPTemplateError::InvalidStateTransition(..) => "JSP0004",
+            JSPTemplateError::UnknownHeader(..) => "JSP0005",
+            JSPTemplateError::RegexError(_) => "JSP0006",
+            JSPTemplateError::InaccesibleFileError(_) => "JSP0007",
+            JSPTemplateError::IoError(_) => "JSP0008",
+            JSPTemplateError::NoValidNextState(_) => "JSP0009",
+            JSPTemplateError::DoneState => "JSP0010",
+            JSPTemplateError::ErrorState => "JSP0011",
+            JSPTemplateError::NomError(_) => "JSP0012",
+            JSPTemplateError::Placeholder => "JSP0013",
+            JSPTemplateError::CompiledTemplateError(..) => "JSP0014",
+            JSPTemplateError::ConflictingExamplesError(_) => "JSP0015",
+            JSPTemplateError::InvalidValueError(..) => "JSP0016",
+            JSPTemplateError::MissingValueError(..) => "JSP0017",
+            JSPTemplateError::AmbiguousRouteError(..) => "JSP0018",
+            JSPTemplateError::NoRouteError(_) => "JSP0019",
+            JSPTemplateError::MissingRootError => "JSP0020",
+            JSPTemplateError::ErrorAtLine(_, _, _, inner) => inner.code(),
+        }
+    }
 }
 
 // Implement From Nom Error
@@ -101,3 +169,195 @@ impl From<(usize, String, State, JSPTemplateError)> for JSPTemplateLineError {
     }
 } 
 
+//-------------------------------//
+//      JSPTEMPLATEWARNING       //
+//-------------------------------//
+/// Likely mistakes in the template which do not prevent it from compiling.
+#[derive(Debug, PartialEq, Eq, Clone, Fail)]
+pub enum JSPTemplateWarning {
+    /// A node is defined more than once. The last definition wins. Stores the
+    /// name of the node and the line on which it was first defined.
+    #[fail(display = "node `{}` was already defined on line {}; the new definition replaces it", _0, _1)]
+    DuplicateNode(String, usize),
+    /// A regex is defined more than once. The last definition wins. Stores the
+    /// name of the regex and the line on which it was first defined.
+    #[fail(display = "regex `{}` was already defined on line {}; the new definition replaces it", _0, _1)]
+    DuplicateRegex(String, usize),
+    /// An edge is defined more than once. Stores the names of the nodes the edge
+    /// connects, and the line on which it was first defined.
+    #[fail(display = "edge `{} -> {}` was already defined on line {}", _0, _1, _2)]
+    DuplicateEdge(String, String, usize),
+}
+
+impl JSPTemplateWarning {
+    /// Retrieve the stable code identifying the kind of warning. 
+    pub fn code(&self) -> &'static str {
+        match self {
+            JSPTemplateWarning::DuplicateNode(..) => "JSP1001",
+            JSPTemplateWarning::DuplicateRegex(..) => "JSP1002",
+            JSPTemplateWarning::DuplicateEdge(..) => "JSP1003",
+        }
+    }
+}
+
+//-------------------------------//
+//    JSPTEMPLATELINEWARNING     //
+//-------------------------------//
+/// Wrap JSPTemplateWarning to provide the context in which it was encountered
+#[derive(Debug, Fail)]
+pub enum JSPTemplateLineWarning {
+    #[fail(display = "Warning at line: {} line: {} State: {} Warning: {}", _0, _1, _2, _3)]
+    WarningAtLine(usize, String, State, JSPTemplateWarning)
+}
+
+/// Convert from a tuple of (line number, line, state, warning) to a JSPTemplateLineWarning
+impl From<(usize, String, State, JSPTemplateWarning)> for JSPTemplateLineWarning {
+    fn from(warning: (usize, String, State, JSPTemplateWarning) ) -> Self {
+        JSPTemplateLineWarning::WarningAtLine(warning.0, warning.1, warning.2, warning.3)
+    }
+}
+
+//-------------------------------//
+//          PARSEERROR           //
+//-------------------------------//
+/// The specific mistake identified when a line of the jspt fails to parse. 
+/// Each variant's display is written for template authors rather than 
+/// developers.
+#[derive(Debug, PartialEq, Eq, Clone, Fail)]
+pub enum ParseErrorKind {
+    /// A definition appears before the first section header
+    #[fail(display = "expected a section header such as `[regex]` before any definitions")]
+    ExpectedHeader,
+    /// A section header is missing its closing bracket
+    #[fail(display = "unterminated section header; expected `]`")]
+    UnterminatedHeader,
+    /// A section is declared after the `[graph]` section, which must come last
+    #[fail(display = "the [graph] section must be last; `[{}]` may not follow it", _0)]
+    HeaderAfterGraph(String),
+    /// A node or regex name contains characters other than letters, numbers and underscores
+    #[fail(display = "invalid name `{}`; names may only contain letters, numbers and underscores", _0)]
+    InvalidName(String),
+    /// A node name is followed by something other than `=` or metadata
+    #[fail(display = "expected `=` after node name `{}`", _0)]
+    ExpectedNodeEquals(String),
+    /// A regex name is not followed by `=`
+    #[fail(display = "expected `=` after regex name `{}`", _0)]
+    ExpectedRegexEquals(String),
+    /// Nothing follows the `=` in a definition
+    #[fail(display = "expected a value after `=` for `{}`", _0)]
+    MissingValue(String),
+    /// A named regex is assigned a value which is not quoted
+    #[fail(display = "expected a quoted regex after `=` for `{}`", _0)]
+    ExpectedQuotedRegex(String),
+    /// A quoted regex is missing its closing quote
+    #[fail(display = "unterminated quoted regex")]
+    UnterminatedQuote,
+    /// A quoted regex contains a space or single quote
+    #[fail(display = "quoted regex may not contain {:?}", _0)]
+    InvalidRegexCharacter(char),
+    /// A `$` is not followed by a regex name
+    #[fail(display = "expected a regex name after `$`")]
+    MissingRegexName,
+    /// Metadata is missing its closing bracket
+    #[fail(display = "unterminated metadata; expected `]`")]
+    UnterminatedMetadata,
+    /// Metadata contains an empty entry, as in `[volume,]`
+    #[fail(display = "empty metadata entry")]
+    EmptyMetadataEntry,
+    /// Metadata contains a key other than volume, owner, perms or varname
+    #[fail(display = "unknown metadata key `{}`{}", _0, _1)]
+    UnknownMetadataKey(String, Suggestions),
+    /// A metadata key which requires a value does not have one
+    #[fail(display = "expected `:` and a value after metadata key `{}`", _0)]
+    MissingMetadataValue(String),
+    /// A metadata key which takes no value was given one
+    #[fail(display = "metadata key `{}` does not take a value", _0)]
+    UnexpectedMetadataValue(String),
+    /// A metadata value is malformed
+    #[fail(display = "invalid value `{}` for metadata key `{}`", _1, _0)]
+    InvalidMetadataValue(String, String),
+    /// Permissions are not made up of octal digits
+    #[fail(display = "invalid permissions `{}`; expected octal digits such as `751`", _0)]
+    InvalidPermissions(String),
+    /// An edge appears outside of the [graph] section
+    #[fail(display = "edges are not allowed in the [{}] section", _0)]
+    MisplacedEdge(String),
+    /// A definition appears in the [graph] section
+    #[fail(display = "definitions are not allowed in the [graph] section")]
+    MisplacedDefinition,
+    /// An arrow is missing a node on one of its sides
+    #[fail(display = "expected a node name on both sides of `->`")]
+    DanglingArrow,
+    /// A lone node name appears in the [graph] section
+    #[fail(display = "expected `->` after node name `{}`", _0)]
+    ExpectedArrow(String),
+    /// Anything we cannot otherwise classify
+    #[fail(display = "unexpected input `{}`", _0)]
+    Unexpected(String),
+}
+
+impl ParseErrorKind {
+    /// Retrieve the stable code identifying the kind of mistake. 
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseErrorKind::ExpectedHeader => "JSP0101",
+            ParseErrorKind::UnterminatedHeader => "JSP0102",
+            ParseErrorKind::HeaderAfterGraph(_) => "JSP0103",
+            ParseErrorKind::InvalidName(_) => "JSP0104",
+            ParseErrorKind::ExpectedNodeEquals(_) => "JSP0105",
+            ParseErrorKind::ExpectedRegexEquals(_) => "JSP0106",
+            ParseErrorKind::MissingValue(_) => "JSP0107",
+            ParseErrorKind::ExpectedQuotedRegex(_) => "JSP0108",
+            ParseErrorKind::UnterminatedQuote => "JSP0109",
+            ParseErrorKind::InvalidRegexCharacter(_) => "JSP0110",
+            ParseErrorKind::MissingRegexName => "JSP0111",
+            ParseErrorKind::UnterminatedMetadata => "JSP0112",
+            ParseErrorKind::EmptyMetadataEntry => "JSP0113",
+            ParseErrorKind::UnknownMetadataKey(..) => "JSP0114",
+            ParseErrorKind::MissingMetadataValue(_) => "JSP0115",
+            ParseErrorKind::UnexpectedMetadataValue(_) => "JSP0116",
+            ParseErrorKind::InvalidMetadataValue(..) => "JSP0117",
+            ParseErrorKind::InvalidPermissions(_) => "JSP0118",
+            ParseErrorKind::MisplacedEdge(_) => "JSP0119",
+            ParseErrorKind::MisplacedDefinition => "JSP0120",
+            ParseErrorKind::DanglingArrow => "JSP0121",
+            ParseErrorKind::ExpectedArrow(_) => "JSP0122",
+            ParseErrorKind::Unexpected(_) => "JSP0123",
+        }
+    }
+}
+
+/// A ParseErrorKind along with the span of the offending text within the line, 
+/// expressed as zero based byte offsets.
+#[derive(Debug, PartialEq, Eq, Clone, Fail)]
+#[fail(display = "{}", kind)]
+pub struct ParseError {
+    kind: ParseErrorKind,
+    start: usize,
+    end: usize,
+}
+
+impl ParseError {
+    /// New up a ParseError given its kind and the start and end offsets of 
+    /// the offending text in the line.
+    pub fn new(kind: ParseErrorKind, start: usize, end: usize) -> Self {
+        Self { kind, start, end }
+    }
+
+    /// Retrieve a reference to the kind of error
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+
+    /// Retrieve the (start, end) offsets of the offending text in the line
+    pub fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+}
+
+// Implement From ParseError
+impl From<ParseError> for JSPTemplateError {
+    fn from(error: ParseError) -> Self {
+        JSPTemplateError::ParsingError(error)
+    }
+}
@@ -0,0 +1,685 @@
+//! Long form explanations for each error and warning code, as printed by
+//! `jspcompile --explain <code>`.
+//!
+//! Codes are stable. Once assigned, a code always refers to the same mistake,
+//! regardless of how the wording of the associated message changes. Errors
+//! occupy JSP0002 - JSP0099, parsing errors JSP0101 - JSP0199, and warnings
+//! JSP1001 and up. JSP0001 is reserved, and is never reported.
+
+// pairs of (code, explanation)
+const EXPLANATIONS: &[(&str, &str)] = &[
+("JSP0002", r#"A node name used in the [graph] section was never defined.
+
+Every name on either side of `->` must be defined in the [nodes] section (or
+be `root`). This is usually a misspelling, or a node which was renamed in the
+[nodes] section but not in the [graph] section.
+
+Incorrect:
+
+    [nodes]
+    seq = $level
+    [graph]
+    root -> sequence
+
+Correct:
+
+    [nodes]
+    seq = $level
+    [graph]
+    root -> seq
+"#),
+("JSP0003", r#"A node refers to a named regex which was never defined.
+
+A `$name` in the [nodes] section refers to a regex in the [regex] section. The
+regex must be defined, and spelled identically.
+
+Incorrect:
+
+    [regex]
+    level = "[A-Z]+"
+    [nodes]
+    show = $levle
+
+Correct:
+
+    [regex]
+    level = "[A-Z]+"
+    [nodes]
+    show = $level
+"#),
+("JSP0004", r#"The sections of the template are out of order.
+
+A template consists of the [regex], [nodes] and [graph] sections, which must
+appear in that order. Nodes may refer to regexes, and the graph to nodes, so
+each must be defined before it is used.
+
+Incorrect:
+
+    [nodes]
+    show = $level
+    [regex]
+    level = "[A-Z]+"
+
+Correct:
+
+    [regex]
+    level = "[A-Z]+"
+    [nodes]
+    show = $level
+"#),
+("JSP0005", r#"A section header is not one that jspcompile recognizes.
+
+The recognized headers are [regex] (or [regexp], [re]), [nodes] (or [node])
+and [graph] (or [edge], [edges]).
+
+Incorrect:
+
+    [grapha]
+
+Correct:
+
+    [graph]
+"#),
+("JSP0006", r#"A regular expression is not valid.
+
+Regexes, whether named in the [regex] section or written inline in the [nodes]
+section, must be valid regular expressions. Unbalanced parentheses and brackets
+are the usual culprits.
+
+Incorrect:
+
+    [regex]
+    level = "([A-Z]+"
+
+Correct:
+
+    [regex]
+    level = "([A-Z]+)"
+"#),
+("JSP0007", r#"The input file does not exist, or cannot be read.
+
+Check the path supplied on the command line, along with the file's permissions.
+"#),
+("JSP0008", r#"An error occurred reading or writing a file.
+
+The message contains the details reported by the operating system.
+"#),
+("JSP0009", r#"A section header appears after the last possible section.
+
+No section may follow the [graph] section. See also JSP0103.
+"#),
+("JSP0010", r#"An internal error: the parser was asked to parse a line after finishing.
+
+This indicates a bug in jspcompile. Please report it, along with the template.
+"#),
+("JSP0011", r#"An internal error: the parser was asked to parse a line after failing.
+
+This indicates a bug in jspcompile. Please report it, along with the template.
+"#),
+("JSP0012", r#"An internal error reported by the parsing library.
+
+jspcompile failed to describe a parsing error in its own terms. Please report
+it, along with the template.
+"#),
+("JSP0013", r#"An internal error: unfinished code was reached.
+
+This indicates a bug in jspcompile. Please report it, along with the template.
+"#),
+("JSP0014", r#"A compiled template could not be read.
+
+`decompile` reads the output of `jspcompile compile`. The file exists, but it
+could not be understood as a compiled template. Check that it was produced by
+`compile` without `--dot` or `--format`, and that it has not been truncated.
+"#),
+("JSP0015", r#"A name was given both as a name to accept and as one to reject.
+
+`suggest-regex` looks for a regex which matches every name given to `--accept`,
+and none given to `--reject`. No regex can do both for the same name.
+
+Incorrect:
+
+    jspcompile suggest-regex --accept AA_0100 REF --reject REF SHARED
+
+Correct:
+
+    jspcompile suggest-regex --accept AA_0100 --reject REF SHARED
+"#),
+("JSP0016", r#"A value does not suit the node it was given for.
+
+`build-path` fills each regex node along the route to a node with the value set
+for its varname. The value must be a single directory name, which the node's
+regex matches, and its exclude regex does not.
+
+Incorrect, where `shot = "[0-9]+[A-Z0-9]*"`:
+
+    jspcompile build-path show.jspt shot --set DD_SHOW=FOO --set DD_SEQUENCE=AA --set DD_SHOT=AA_0100
+
+Correct:
+
+    jspcompile build-path show.jspt shot --set DD_SHOW=FOO --set DD_SEQUENCE=AA --set DD_SHOT=0100
+"#),
+("JSP0017", r#"No value was given for a node along the route.
+
+`build-path` fills literal nodes itself, but each regex node needs a value, set
+by the node's varname or, if it has none, by its name.
+
+Incorrect:
+
+    jspcompile build-path show.jspt shot --set DD_SHOW=FOO --set DD_SHOT=0100
+
+Correct:
+
+    jspcompile build-path show.jspt shot --set DD_SHOW=FOO --set DD_SEQUENCE=AA --set DD_SHOT=0100
+"#),
+("JSP0018", r#"More than one route leads to the node, and the values fit more than one.
+
+`build-path` follows the route from root to the named node. When the graph has
+several, the values decide between them, as a value only fits a route if each
+regex node along it accepts its value. Set values which fit only one route, or
+build the path to a node which has only one route.
+"#),
+("JSP0019", r#"No route leads from root to the node.
+
+`build-path` builds the path to a node by following the edges from root. A node
+which no edge leads to, directly or indirectly, has no path. Add the missing
+edge to the `[graph]` section.
+"#),
+("JSP0020", r#"A compiled graph has no root node.
+
+Every template is compiled with an implicit `root` node, from which all paths
+begin, and `decompile` starts from it. A compiled graph without one was not
+produced by `jspcompile compile`, or has been edited by hand. Recompile it from
+its template if the template is available.
+"#),
+("JSP0101", r#"A definition appears before any section header.
+
+Every template begins with a section header. Only comments and blank lines may
+precede it.
+
+Incorrect:
+
+    level = "[A-Z]+"
+
+Correct:
+
+    [regex]
+    level = "[A-Z]+"
+"#),
+("JSP0102", r#"A section header is missing its closing bracket.
+
+Incorrect:
+
+    [regex
+
+Correct:
+
+    [regex]
+"#),
+("JSP0103", r#"A section appears after the [graph] section.
+
+The [graph] section must be the last section in the template.
+
+Incorrect:
+
+    [graph]
+    root -> dd
+    [nodes]
+    shows
+
+Correct:
+
+    [nodes]
+    shows
+    [graph]
+    root -> dd
+"#),
+("JSP0104", r#"A name contains characters which are not allowed.
+
+Node and regex names may only contain letters, numbers and underscores.
+
+Incorrect:
+
+    [nodes]
+    client-vault = CLIENT_VAULT
+
+Correct:
+
+    [nodes]
+    client_vault = CLIENT_VAULT
+"#),
+("JSP0105", r#"A node name is followed by something other than `=` or metadata.
+
+A node is either a bare name, optionally followed by metadata, or a name
+followed by `=` and a value.
+
+Incorrect:
+
+    [nodes]
+    show $level
+
+Correct:
+
+    [nodes]
+    show = $level
+"#),
+("JSP0106", r#"A regex name is not followed by `=`.
+
+Incorrect:
+
+    [regex]
+    level "[A-Z]+"
+
+Correct:
+
+    [regex]
+    level = "[A-Z]+"
+"#),
+("JSP0107", r#"Nothing follows the `=` in a definition.
+
+Incorrect:
+
+    [nodes]
+    show = [volume]
+
+Correct:
+
+    [nodes]
+    show = $level [volume]
+"#),
+("JSP0108", r#"A named regex is not quoted.
+
+Regexes in the [regex] section must be surrounded by double quotes.
+
+Incorrect:
+
+    [regex]
+    level = [A-Z]+
+
+Correct:
+
+    [regex]
+    level = "[A-Z]+"
+"#),
+("JSP0109", r#"A quoted regex is missing its closing quote.
+
+Incorrect:
+
+    [regex]
+    level = "[A-Z]+
+
+Correct:
+
+    [regex]
+    level = "[A-Z]+"
+"#),
+("JSP0110", r#"A quoted regex contains a space or single quote.
+
+Regexes may not contain whitespace or single quotes. Use `\s` to match
+whitespace.
+
+Incorrect:
+
+    [regex]
+    level = "[A-Z] +"
+
+Correct:
+
+    [regex]
+    level = "[A-Z]+"
+"#),
+("JSP0111", r#"A `$` is not followed by the name of a regex.
+
+Incorrect:
+
+    [nodes]
+    show = $
+
+Correct:
+
+    [nodes]
+    show = $level
+"#),
+("JSP0112", r#"Metadata is missing its closing bracket.
+
+Incorrect:
+
+    [nodes]
+    show = $level [ owner: jobsys
+
+Correct:
+
+    [nodes]
+    show = $level [ owner: jobsys ]
+"#),
+("JSP0113", r#"Metadata contains an empty entry.
+
+Metadata entries are separated by commas. There may not be a trailing comma.
+
+Incorrect:
+
+    [nodes]
+    refdir = REF [ volume, ]
+
+Correct:
+
+    [nodes]
+    refdir = REF [ volume ]
+"#),
+("JSP0114", r#"Metadata contains an unknown key.
+
+The recognized metadata keys are `volume`, `autocreate`, `owner`, `perms` and
+`varname`.
+
+Incorrect:
+
+    [nodes]
+    show = $level [ onwer: jobsys ]
+
+Correct:
+
+    [nodes]
+    show = $level [ owner: jobsys ]
+"#),
+("JSP0115", r#"A metadata key which requires a value does not have one.
+
+The `owner`, `perms` and `varname` keys must be followed by `:` and a value.
+
+Incorrect:
+
+    [nodes]
+    show = $level [ owner ]
+
+Correct:
+
+    [nodes]
+    show = $level [ owner: jobsys ]
+"#),
+("JSP0116", r#"A metadata key which takes no value was given one.
+
+The `volume` and `autocreate` keys stand on their own.
+
+Incorrect:
+
+    [nodes]
+    refdir = REF [ volume: true ]
+
+Correct:
+
+    [nodes]
+    refdir = REF [ volume ]
+"#),
+("JSP0117", r#"A metadata value is malformed.
+
+Owners must be a user name or a `$` followed by the name of a regex capture
+group, and varnames must be made up of letters, numbers and underscores.
+
+Incorrect:
+
+    [nodes]
+    show = $level [ varname: DD-SHOW ]
+
+Correct:
+
+    [nodes]
+    show = $level [ varname: DD_SHOW ]
+"#),
+("JSP0118", r#"Permissions are not made up of octal digits.
+
+Incorrect:
+
+    [nodes]
+    show = $level [ perms: rwxr-x--x ]
+
+Correct:
+
+    [nodes]
+    show = $level [ perms: 751 ]
+"#),
+("JSP0119", r#"An edge appears outside of the [graph] section.
+
+Edges may only be defined in the [graph] section, after all of the nodes.
+
+Incorrect:
+
+    [nodes]
+    dd
+    shows
+    dd -> shows
+
+Correct:
+
+    [nodes]
+    dd
+    shows
+    [graph]
+    dd -> shows
+"#),
+("JSP0120", r#"A definition appears in the [graph] section.
+
+Nodes and regexes must be defined in the [nodes] and [regex] sections.
+
+Incorrect:
+
+    [graph]
+    clientvault = CLIENT_VAULT
+    refdir -> clientvault
+
+Correct:
+
+    [nodes]
+    clientvault = CLIENT_VAULT
+    [graph]
+    refdir -> clientvault
+"#),
+("JSP0121", r#"An arrow is missing the node on one of its sides.
+
+Incorrect:
+
+    [graph]
+    dd -> shows ->
+
+Correct:
+
+    [graph]
+    dd -> shows -> show
+"#),
+("JSP0122", r#"A lone node name appears in the [graph] section.
+
+Each line in the [graph] section connects two or more nodes with `->`.
+
+Incorrect:
+
+    [graph]
+    dd
+
+Correct:
+
+    [graph]
+    root -> dd
+"#),
+("JSP0123", r#"The line could not be understood.
+
+The line does not resemble any of the definitions allowed in the current
+section. Check it against the examples in the jspcompile repository.
+"#),
+("JSP1001", r#"A node is defined more than once.
+
+The last definition replaces the earlier ones, which is rarely intended.
+
+Incorrect:
+
+    [nodes]
+    shared = SHARED
+    shared = "(SHARED|COMMON)"
+
+Correct:
+
+    [nodes]
+    shared = "(SHARED|COMMON)"
+"#),
+("JSP1002", r#"A regex is defined more than once.
+
+The last definition replaces the earlier ones, which is rarely intended.
+
+Incorrect:
+
+    [regex]
+    level = "[A-Z]+"
+    level = "[A-Z0-9]+"
+
+Correct:
+
+    [regex]
+    level = "[A-Z0-9]+"
+"#),
+("JSP1003", r#"An edge is defined more than once.
+
+The repeated edge has no effect on which paths match. Remove it.
+
+Incorrect:
+
+    [graph]
+    show -> etc
+    show -> etc
+
+Correct:
+
+    [graph]
+    show -> etc
+"#),
+("JSP1004", r#"The owner of a directory could not be changed.
+
+When materialising a template with `mkdirs`, `owner` metadata is applied with
+chown. Only root may change the owner of a directory, and the owner must exist
+on this host. Owners taken from a `$name` capture group are resolved against
+the generated directory name, so they rarely exist. When a chown is skipped,
+the directory is still created and its permissions set. Use `--dry-run` to
+review the chown operations which would be performed.
+"#),
+("JSP1005", r#"No directory name can be generated for a node.
+
+`samples` and `mkdirs` name regex nodes with examples generated from the regex,
+restricted to printable ascii other than `/`. A regex which only matches other
+characters, such as `"\s"`, `"[/]"` or `"\p{Greek}+"`, cannot be named, so the
+paths through it are skipped. The template itself is unaffected.
+"#),
+("JSP1006", r#"A directory could not be read while inferring a draft template.
+
+`infer` reads the whole tree beneath the directory given. A directory which
+cannot be read, typically for lack of permission, is still recorded as a node,
+but nothing beneath it is reflected in the draft. Rerun `infer` as a user who
+can read the directory, or review the draft by hand beneath it.
+"#),
+];
+
+/// Retrieve the long form explanation for an error or warning code, including
+/// an incorrect and a correct example where appropriate. Codes are matched
+/// case insensitively.
+///
+/// # Examples
+///
+/// ```
+/// use jspcompile::explain::explain;
+///
+/// assert!(explain("JSP0004").is_some());
+/// assert!(explain("jsp0004").is_some());
+/// assert!(explain("JSP9999").is_none());
+/// ```
+pub fn explain(code: &str) -> Option<&'static str> {
+    EXPLANATIONS.iter()
+        .find(|(candidate, _)| candidate.eq_ignore_ascii_case(code))
+        .map(|(_, explanation)| *explanation)
+}
+
+/// Retrieve all of the codes which have explanations
+pub fn codes() -> impl Iterator<Item=&'static str> {
+    EXPLANATIONS.iter().map(|(code, _)| *code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{JSPTemplateError, JSPTemplateWarning, ParseErrorKind, State, spelling::Suggestions};
+    use std::collections::HashSet;
+
+    #[test]
+    fn codes_are_unique() {
+        let unique = codes().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), EXPLANATIONS.len());
+    }
+
+    #[test]
+    fn every_error_is_explained() {
+        let errors = vec![
+            JSPTemplateError::KeyMapLookupError(String::new(), Suggestions::default()),
+            JSPTemplateError::RegexMapLookupError(String::new(), Suggestions::default()),
+            JSPTemplateError::InvalidStateTransition(State::Start, State::Done),
+            JSPTemplateError::UnknownHeader(String::new(), Suggestions::default()),
+            JSPTemplateError::InaccesibleFileError(std::path::PathBuf::new()),
+            JSPTemplateError::IoError(std::io::Error::from(std::io::ErrorKind::NotFound)),
+            JSPTemplateError::NoValidNextState(State::Done),
+            JSPTemplateError::DoneState,
+            JSPTemplateError::ErrorState,
+            JSPTemplateError::NomError(String::new()),
+            JSPTemplateError::Placeholder,
+            JSPTemplateError::CompiledTemplateError(std::path::PathBuf::new(), String::new()),
+            JSPTemplateError::ConflictingExamplesError(String::new()),
+            JSPTemplateError::InvalidValueError(String::new(), String::new(), String::new(), String::new()),
+            JSPTemplateError::MissingValueError(String::new(), String::new()),
+            JSPTemplateError::AmbiguousRouteError(String::new(), String::new()),
+            JSPTemplateError::NoRouteError(String::new()),
+            JSPTemplateError::MissingRootError,
+        ];
+        for error in errors {
+            assert!(explain(error.code()).is_some(), "{} is not explained", error.code());
+            assert_ne!(error.code(), "JSP0001", "JSP0001 is reserved");
+        }
+    }
+
+    #[test]
+    fn every_parse_error_is_explained() {
+        let kinds = vec![
+            ParseErrorKind::ExpectedHeader,
+            ParseErrorKind::UnterminatedHeader,
+            ParseErrorKind::HeaderAfterGraph(String::new()),
+            ParseErrorKind::InvalidName(String::new()),
+            ParseErrorKind::ExpectedNodeEquals(String::new()),
+            ParseErrorKind::ExpectedRegexEquals(String::new()),
+            ParseErrorKind::MissingValue(String::new()),
+            ParseErrorKind::ExpectedQuotedRegex(String::new()),
+            ParseErrorKind::UnterminatedQuote,
+            ParseErrorKind::InvalidRegexCharacter(' '),
+            ParseErrorKind::MissingRegexName,
+            ParseErrorKind::UnterminatedMetadata,
+            ParseErrorKind::EmptyMetadataEntry,
+            ParseErrorKind::UnknownMetadataKey(String::new(), Suggestions::default()),
+            ParseErrorKind::MissingMetadataValue(String::new()),
+            ParseErrorKind::UnexpectedMetadataValue(String::new()),
+            ParseErrorKind::InvalidMetadataValue(String::new(), String::new()),
+            ParseErrorKind::InvalidPermissions(String::new()),
+            ParseErrorKind::MisplacedEdge(String::new()),
+            ParseErrorKind::MisplacedDefinition,
+            ParseErrorKind::DanglingArrow,
+            ParseErrorKind::ExpectedArrow(String::new()),
+            ParseErrorKind::Unexpected(String::new()),
+        ];
+        for kind in kinds {
+            assert!(explain(kind.code()).is_some(), "{} is not explained", kind.code());
+        }
+    }
+
+    #[test]
+    fn every_warning_is_explained() {
+        let warnings = vec![
+            JSPTemplateWarning::DuplicateNode(String::new(), 0),
+            JSPTemplateWarning::DuplicateRegex(String::new(), 0),
+            JSPTemplateWarning::DuplicateEdge(String::new(), String::new(), 0),
+        ];
+        for warning in warnings {
+            assert!(explain(warning.code()).is_some(), "{} is not explained", warning.code());
+        }
+        assert!(explain(crate::sandbox::SKIPPED_CHOWN).is_some());
+        assert!(explain(crate::samples::UNSAMPLED_NODE).is_some());
+        assert!(explain(crate::infer::UNREADABLE_DIRECTORY).is_some());
+    }
+}
@@ -0,0 +1,311 @@
+//! Describe a loaded template as data, for tools which would rather not parse
+//! the jsp format.
+//!
+//! A Document is serialized as json or yaml, with the following schema:
+//!
+//! ```text
+//! schema_version: 1
+//! nodes:                      # every node in the graph, in the order defined
+//!   - name: show              # the name of the node in the template
+//!     kind: regex             # root, literal, regex or untracked
+//!     pattern: ^[A-Z]+$       # regex only: the anchored regex a name must match
+//!     exclude: ^(REF)$        # regex only: the anchored regex it must not match, or null
+//!     entry_type: directory   # directory, volume or untracked
+//!     metadata:
+//!       owner: jobsys         # or null
+//!       perms: "751"          # or null
+//!       varname: DD_SHOW      # or null
+//!       autocreate: false
+//!   - name: refdir
+//!     kind: literal
+//!     value: REF              # literal only: the directory name
+//!     entry_type: volume
+//!     metadata: ...
+//! edges:                      # every edge, by node name, in the order defined
+//!   - from: shows
+//!     to: show
+//! ```
+//!
+//! The schema version is incremented whenever a field is removed or its meaning
+//! changes. Fields may be added without incrementing it.
+use crate::Matcher;
+use jsp::{EntryType, NIndex, NodeType};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, str::FromStr};
+
+/// The version of the schema written by this crate
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The forms in which a compiled template may be written
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OutputFormat {
+    /// The jsp template format
+    Template,
+    /// A Graphviz dot graph
+    Dot,
+    /// A json Document
+    Json,
+    /// A yaml Document
+    Yaml,
+    /// A Mermaid flowchart
+    Mermaid,
+    /// A PlantUML diagram
+    PlantUml,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "template" => Ok(OutputFormat::Template),
+            "dot" => Ok(OutputFormat::Dot),
+            "json" => Ok(OutputFormat::Json),
+            "yaml" => Ok(OutputFormat::Yaml),
+            "mermaid" => Ok(OutputFormat::Mermaid),
+            "plantuml" => Ok(OutputFormat::PlantUml),
+            _ => Err(format!("invalid output format: {}. Expected one of template, dot, json, yaml, mermaid, plantuml", s)),
+        }
+    }
+}
+
+/// What a node matches
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Kind {
+    /// The root of the graph
+    Root,
+    /// A directory with a fixed name
+    Literal { value: String },
+    /// A directory whose name matches `pattern` and, if there is one, does not match `exclude`
+    Regex { pattern: String, exclude: Option<String> },
+    /// Anything at all, which is not policed
+    Untracked,
+}
+
+/// What a node is on disk
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    /// A plain directory
+    Directory,
+    /// A directory marked `volume`
+    Volume,
+    /// Anything at all
+    Untracked,
+}
+
+/// The metadata of a node
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct NodeMetadata {
+    owner: Option<String>,
+    perms: Option<String>,
+    varname: Option<String>,
+    autocreate: bool,
+}
+
+impl NodeMetadata {
+    /// Retrieve the owner, if there is one
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_ref().map(|x| &**x)
+    }
+
+    /// Retrieve the permissions, if there are any
+    pub fn perms(&self) -> Option<&str> {
+        self.perms.as_ref().map(|x| &**x)
+    }
+
+    /// Retrieve the name of the environment variable, if there is one
+    pub fn varname(&self) -> Option<&str> {
+        self.varname.as_ref().map(|x| &**x)
+    }
+
+    /// Is the node created along with its parent?
+    pub fn is_autocreate(&self) -> bool {
+        self.autocreate
+    }
+}
+
+/// A node in a Document
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct NodeEntry {
+    name: String,
+    #[serde(flatten)]
+    kind: Kind,
+    entry_type: EntryKind,
+    metadata: NodeMetadata,
+}
+
+impl NodeEntry {
+    /// Retrieve the name of the node, as defined in the template
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Retrieve what the node matches
+    pub fn kind(&self) -> &Kind {
+        &self.kind
+    }
+
+    /// Retrieve what the node is on disk
+    pub fn entry_type(&self) -> EntryKind {
+        self.entry_type
+    }
+
+    /// Retrieve the metadata of the node
+    pub fn metadata(&self) -> &NodeMetadata {
+        &self.metadata
+    }
+}
+
+/// An edge in a Document, from a parent to a child
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct EdgeEntry {
+    from: String,
+    to: String,
+}
+
+impl EdgeEntry {
+    /// Retrieve the name of the parent
+    pub fn from(&self) -> &str {
+        &self.from
+    }
+
+    /// Retrieve the name of the child
+    pub fn to(&self) -> &str {
+        &self.to
+    }
+}
+
+/// A description of a loaded template
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Document {
+    schema_version: u32,
+    nodes: Vec<NodeEntry>,
+    edges: Vec<EdgeEntry>,
+}
+
+impl Document {
+    /// New up a Document describing every node and edge in the graph.
+    ///
+    /// # Parameters
+    ///
+    /// * `matcher` - a Matcher over the loaded template
+    /// * `autocreate` - the nodes marked `autocreate`, as reported by the Loader
+    ///
+    /// # Returns
+    /// A Document
+    pub fn new(matcher: &Matcher, autocreate: &HashSet<NIndex>) -> Self {
+        let graph = matcher.graph();
+        let nodes = matcher.nodes().into_iter().map(|node| {
+            let jnode = &graph[node];
+            let kind = match jnode.identity() {
+                NodeType::Root => Kind::Root,
+                NodeType::Simple(value) => Kind::Literal { value: value.clone() },
+                NodeType::RegEx { pattern, exclude, .. } => Kind::Regex {
+                    pattern: pattern.as_str().to_string(),
+                    exclude: exclude.as_ref().map(|exclude| exclude.as_str().to_string()),
+                },
+                NodeType::Untracked => Kind::Untracked,
+            };
+            let entry_type = match jnode.entry_type() {
+                EntryType::Directory => EntryKind::Directory,
+                EntryType::Volume => EntryKind::Volume,
+                EntryType::Untracked => EntryKind::Untracked,
+            };
+            let metadata = NodeMetadata {
+                owner: jnode.metadata().owner().map(|owner| owner.to_string()),
+                perms: jnode.metadata().perms().map(|perms| perms.to_string()),
+                varname: jnode.metadata().varname().map(|varname| varname.to_string()),
+                autocreate: autocreate.contains(&node),
+            };
+            NodeEntry { name: matcher.name(node).to_string(), kind, entry_type, metadata }
+        }).collect();
+        let edges = matcher.edges().into_iter()
+            .map(|(from, to)| EdgeEntry { from: matcher.name(from).to_string(), to: matcher.name(to).to_string() })
+            .collect();
+        Self { schema_version: SCHEMA_VERSION, nodes, edges }
+    }
+
+    /// Retrieve the version of the schema the Document follows
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
+    /// Retrieve the nodes, in the order in which they were defined
+    pub fn nodes(&self) -> &[NodeEntry] {
+        &self.nodes
+    }
+
+    /// Retrieve the edges, in the order in which they were defined
+    pub fn edges(&self) -> &[EdgeEntry] {
+        &self.edges
+    }
+
+    /// Render the Document as pretty printed json
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Document serializes to json")
+    }
+
+    /// Render the Document as yaml
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).expect("Document serializes to yaml")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_template_with_autocreate;
+
+    const TEMPLATE: &str = "[regex]\nshow = \"[A-Z]+\" \"(REF)\"\n[nodes]\ndd\nshows\nshow = $show [owner: jobsys, perms: 751, varname: DD_SHOW]\nref = REF [volume]\nwork = work [autocreate]\n[graph]\nroot -> dd -> shows -> show -> work\ndd -> ref\n";
+
+    fn document() -> Document {
+        let (graph, keymap, autocreate) = load_template_with_autocreate(TEMPLATE);
+        Document::new(&Matcher::new(&graph, &keymap).unwrap(), &autocreate)
+    }
+
+    #[test]
+    fn describes_nodes_and_edges() {
+        let document = document();
+        let json: serde_json::Value = serde_json::from_str(&document.to_json()).unwrap();
+        assert_eq!(json["schema_version"], 1);
+        let show = json["nodes"].as_array().unwrap().iter().find(|node| node["name"] == "show").unwrap();
+        assert_eq!(
+            *show,
+            serde_json::json!({
+                "name": "show",
+                "kind": "regex",
+                "pattern": "^[A-Z]+$",
+                "exclude": "^(REF)$",
+                "entry_type": "directory",
+                "metadata": { "owner": "jobsys", "perms": "751", "varname": "DD_SHOW", "autocreate": false },
+            })
+        );
+        let reference = document.nodes().iter().find(|node| node.name() == "ref").unwrap();
+        assert_eq!(reference.kind(), &Kind::Literal { value: "REF".to_string() });
+        assert_eq!(reference.entry_type(), EntryKind::Volume);
+        let work = document.nodes().iter().find(|node| node.name() == "work").unwrap();
+        assert!(work.metadata().is_autocreate());
+        let edges = document.edges().iter().map(|edge| format!("{} -> {}", edge.from(), edge.to())).collect::<Vec<_>>();
+        assert_eq!(edges, vec!["root -> dd", "dd -> shows", "shows -> show", "show -> work", "dd -> ref"]);
+    }
+
+    #[test]
+    fn leaves_out_redefined_nodes() {
+        let template = "[regex]\n[nodes]\ndd\nshows\nshows = SHOWS [perms: 751]\n[graph]\nroot -> dd -> shows\n";
+        let (graph, keymap, autocreate) = load_template_with_autocreate(template);
+        let document = Document::new(&Matcher::new(&graph, &keymap).unwrap(), &autocreate);
+        let names = document.nodes().iter().map(|node| node.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["root", "dd", "shows"]);
+        let shows = &document.nodes()[2];
+        assert_eq!(shows.kind(), &Kind::Literal { value: "SHOWS".to_string() });
+    }
+
+    #[test]
+    fn round_trips_through_json_and_yaml() {
+        let document = document();
+        assert_eq!(serde_json::from_str::<Document>(&document.to_json()).unwrap(), document);
+        assert_eq!(serde_yaml::from_str::<Document>(&document.to_yaml()).unwrap(), document);
+    }
+}
@@ -0,0 +1,210 @@
+//! Rewrite templates in a canonical style.
+//!
+//! The formatter works line by line over a SyntaxTree:
+//!
+//! * the `=` of regex and node definitions is aligned within each group of
+//!   definitions, a group being the lines between blank lines and headers
+//! * metadata keys are ordered volume, autocreate, owner, perms, varname and spaced as
+//!   `[volume, owner: jobsys, perms: 751]`
+//! * edges are spaced as `a -> b -> c`
+//! * headers are written without padding, as `[nodes]`
+//! * comments, blank lines and their grouping are preserved, although
+//!   indentation and trailing whitespace are removed
+use crate::{
+    Edge, JSPTemplateError, Metadata, Node, Regex,
+    syntax::{SyntaxKind, SyntaxLine, SyntaxTree},
+};
+
+/// Format a template in the canonical style.
+///
+/// # Parameters
+///
+/// * `input` - the contents of a jspt template
+///
+/// # Returns
+/// A Result wrapping the formatted template if successful. Otherwise a JSPTemplateError
+/// describing why the template could not be parsed.
+///
+/// # Examples
+///
+/// ```
+/// use jspcompile::formatter::format_template;
+///
+/// let formatted = format_template("[regex]\n[ nodes ]\ndd\nshow = \"[A-Z]+\" [perms: 751,volume]\n[graph]\ndd->show\n").unwrap();
+/// assert_eq!(formatted, "[regex]\n[nodes]\ndd\nshow = \"[A-Z]+\" [volume, perms: 751]\n[graph]\ndd -> show\n");
+/// ```
+pub fn format_template(input: &str) -> Result<String, JSPTemplateError> {
+    let tree = SyntaxTree::parse(input)?;
+    let lines = tree.lines();
+    let mut output = Vec::with_capacity(lines.len());
+    let mut group_start = 0;
+    while group_start < lines.len() {
+        let group_end = group_start + lines[group_start..].iter()
+            .position(|line| ends_group(line.kind()))
+            .map_or(lines.len() - group_start, |idx| idx.max(1));
+        let group = &lines[group_start..group_end];
+        let width = group.iter().filter_map(|line| assigned_name(line.kind())).map(str::len).max().unwrap_or(0);
+        output.extend(group.iter().map(|line| format_line(line, width)));
+        group_start = group_end;
+    }
+    // a single trailing newline, regardless of how many blank lines the template ended with
+    while output.last().map_or(false, |line| line.is_empty()) {
+        output.pop();
+    }
+    let mut formatted = output.join(tree.newline());
+    if !formatted.is_empty() {
+        formatted.push_str(tree.newline());
+    }
+    Ok(formatted)
+}
+
+/// Is the template already formatted in the canonical style?
+pub fn is_formatted(input: &str) -> Result<bool, JSPTemplateError> {
+    Ok(format_template(input)? == input)
+}
+
+// blank lines and headers start a new group of aligned definitions
+fn ends_group(kind: &SyntaxKind) -> bool {
+    match kind {
+        SyntaxKind::Blank | SyntaxKind::Header { .. } => true,
+        _ => false,
+    }
+}
+
+// the name of a definition which takes an `=`, as these are the lines which
+// participate in alignment
+fn assigned_name(kind: &SyntaxKind) -> Option<&str> {
+    match kind {
+        SyntaxKind::Regex(regex) => Some(regex.name()),
+        SyntaxKind::Node(Node::Simple(..)) => None,
+        SyntaxKind::Node(node) => Some(node.name()),
+        _ => None,
+    }
+}
+
+// format a single line, padding names to `width` so that the `=` aligns
+fn format_line(line: &SyntaxLine, width: usize) -> String {
+    match line.kind() {
+        SyntaxKind::Blank => String::new(),
+        SyntaxKind::Comment { marker, text } => format!("{}{}", marker.as_str(), text).trim_end().to_string(),
+        SyntaxKind::Header { name, .. } => format!("[{}]", name),
+        SyntaxKind::Regex(regex) => format_regex(regex, width),
+        SyntaxKind::Node(node) => format_node(node, width),
+        SyntaxKind::Edges(edges) => format_edges(edges),
+    }
+}
+
+fn format_regex(regex: &Regex, width: usize) -> String {
+    match regex {
+        Regex::Simple { name, value } => format!("{:width$} = \"{}\"", name, value, width = width),
+        Regex::Complex { name, positive, negative } => format!("{:width$} = \"{}\" \"{}\"", name, positive, negative, width = width),
+    }
+}
+
+fn format_node(node: &Node, width: usize) -> String {
+    let (definition, metadata) = match node {
+        Node::Simple(name, metadata) => (name.to_string(), metadata),
+        Node::Pair { name, value, metadata } => (format!("{:width$} = {}", name, value, width = width), metadata),
+        Node::ReVar { name, variable, metadata } => (format!("{:width$} = ${}", name, variable, width = width), metadata),
+        Node::RegexSimple { name, re, metadata } => (format!("{:width$} = \"{}\"", name, re, width = width), metadata),
+        Node::RegexComplex { name, pos, neg, metadata } => (format!("{:width$} = \"{}\" \"{}\"", name, pos, neg, width = width), metadata),
+    };
+    match metadata.as_ref().filter(|m| !m.is_empty()) {
+        Some(metadata) => format!("{} {}", definition, format_metadata(metadata)),
+        None => definition,
+    }
+}
+
+/// Format metadata in the canonical style, with keys in a consistent order.
+///
+/// # Examples
+///
+/// ```
+/// use jspcompile::{Metadata, formatter::format_metadata};
+///
+/// let metadata = Metadata::new().set_varname(Some("DD_SHOW")).set_owner(Some("jobsys"));
+/// assert_eq!(format_metadata(&metadata), "[owner: jobsys, varname: DD_SHOW]");
+/// ```
+pub fn format_metadata(metadata: &Metadata) -> String {
+    let mut components = Vec::new();
+    if metadata.is_volume() {
+        components.push("volume".to_string());
+    }
+    if metadata.is_autocreate() {
+        components.push("autocreate".to_string());
+    }
+    if let Some(owner) = metadata.owner() {
+        components.push(format!("owner: {}", owner));
+    }
+    if let Some(perms) = metadata.permissions() {
+        components.push(format!("perms: {}", perms));
+    }
+    if let Some(varname) = metadata.varname() {
+        components.push(format!("varname: {}", varname));
+    }
+    format!("[{}]", components.join(", "))
+}
+
+// edges are parsed from a chain, a -> b -> c, into pairs, (a, b), (b, c),
+// so we put the chain back together
+fn format_edges(edges: &[Edge]) -> String {
+    let mut names = Vec::with_capacity(edges.len() + 1);
+    if let Some(first) = edges.first() {
+        names.push(first.from.as_str());
+    }
+    names.extend(edges.iter().map(|edge| edge.to.as_str()));
+    names.join(" -> ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Loader;
+    use std::io::Cursor;
+
+    // load a template, returning the number of nodes, regexes and edges
+    fn counts(template: &str) -> crate::Summary {
+        let (mut graph, mut keymap, mut regexmap) = Loader::setup();
+        let mut loader = Loader::new(&mut graph, &mut keymap, &mut regexmap);
+        loader.load(Cursor::new(template)).unwrap();
+        loader.summary()
+    }
+
+    #[test]
+    fn aligns_equals_within_groups() {
+        let template = "[regex]\nlevel = \"[A-Z]+\"\nnum_under  =\"[0-9_]+\"\n\n[nodes]\ndd\nshows\n# a comment\nshow = $level\nclientvault = CLIENT_VAULT\n\nrefdir = REF\n";
+        let expected = "[regex]\nlevel     = \"[A-Z]+\"\nnum_under = \"[0-9_]+\"\n\n[nodes]\ndd\nshows\n# a comment\nshow        = $level\nclientvault = CLIENT_VAULT\n\nrefdir = REF\n";
+        assert_eq!(format_template(template).unwrap(), expected);
+    }
+
+    #[test]
+    fn orders_metadata() {
+        let template = "[regex]\n[nodes]\nwork = \"work\\.(?P<work>[a-z]+)\" [ varname:DD_WORK,perms: 770 , owner: $work ]\nuser    [perms: 751,volume]\n";
+        let expected = "[regex]\n[nodes]\nwork = \"work\\.(?P<work>[a-z]+)\" [owner: $work, perms: 770, varname: DD_WORK]\nuser [volume, perms: 751]\n";
+        assert_eq!(format_template(template).unwrap(), expected);
+    }
+
+    #[test]
+    fn spaces_edges_and_headers() {
+        let template = "[regex]\n[ nodes ]\ndd\nshows\n[graph]\n  root->dd   ->  shows\n";
+        assert_eq!(format_template(template).unwrap(), "[regex]\n[nodes]\ndd\nshows\n[graph]\nroot -> dd -> shows\n");
+    }
+
+    #[test]
+    fn preserves_comments_and_blank_lines() {
+        let template = "// leading\n\n\n[regex]\n[nodes]\n   # indented   \ndd\n\n\n";
+        assert_eq!(format_template(template).unwrap(), "// leading\n\n\n[regex]\n[nodes]\n# indented\ndd\n");
+    }
+
+    #[test]
+    fn formatting_is_idempotent_and_preserves_meaning() {
+        for template in &[
+            include_str!("../examples/jsptemplate.jspt"),
+            include_str!("../examples/features.jspt"),
+        ] {
+            let formatted = format_template(template).unwrap();
+            assert!(is_formatted(&formatted).unwrap());
+            assert_eq!(counts(&formatted), counts(template));
+        }
+    }
+}
@@ -9,7 +9,7 @@ use nom::{
 
 // Is the character an uppercase letter, lowercase letter, number, or underscore?
 #[inline]
-fn is_ident_char(c: char) -> bool {
+pub(crate) fn is_ident_char(c: char) -> bool {
         // uppercase letters
         (c > '\x40' && c < '\x5B') || 
         // numbers
@@ -48,11 +48,16 @@ mod id_char {
 // Is the character a valid regular expression character for this crate?
 // we exclude " & ' & space
 #[inline]
-fn is_regex_char(c: char) -> bool {
+pub(crate) fn is_regex_char(c: char) -> bool {
         // everything except for space
         !['"', '\''].contains(&c) && c > '\x20'  && c < '\x7F'  
 }
 
+// Is the name a single, normal path component? That is, neither empty, `.` nor `..`,
+// and free of the path separator
+pub(crate) fn is_path_component(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/')
+}
 
 #[cfg(test)]
 mod regex_char {
@@ -0,0 +1,463 @@
+//! Infer a draft template from an existing directory tree.
+//!
+//! The tree is read level by level. The directories which a node stands for are
+//! gathered, and the names of their subdirectories are grouped into nodes:
+//!
+//! * a name found beneath every one of the directories, when there is more than
+//!   one, is taken to be part of the structure, and becomes a literal
+//! * the remaining names are grouped by shape, which is the sequence of runs of
+//!   uppercase letters, lowercase letters and digits, and of any other characters
+//!   as written, so that `AA_0100` and `BB_0200` share the shape `A_9`
+//! * a shape shared by at least MIN_FAMILY names is a family, and becomes a regex.
+//!   Each run becomes a character class, with a count if every name agrees on
+//!   its length. A run which is the same in every name is kept as written, if
+//!   the names come from more than one directory
+//! * any other name is rare, and becomes a literal
+//!
+//! A family's regex excludes any literal beside it which it would otherwise
+//! match. The permissions most often seen among a node's directories are
+//! recorded as its `perms`. Directories beneath the root which cannot be read
+//! are skipped, and listed alongside the draft.
+use crate::{JSPTemplateError, decompile::{quote, sanitize, unique_name}, formatter::format_template, helpers::is_ident_char};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+/// The number of names sharing a shape which are taken to be a family rather
+/// than a handful of literals
+pub const MIN_FAMILY: usize = 3;
+
+/// The warning code reported for a directory which could not be read while
+/// inferring a draft
+pub const UNREADABLE_DIRECTORY: &str = "JSP1006";
+
+/// A draft template, along with the directories which were skipped because they
+/// could not be read
+#[derive(Debug)]
+pub struct Draft {
+    source: String,
+    unreadable: Vec<(PathBuf, String)>,
+}
+
+impl Draft {
+    /// Retrieve the source of the draft template
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Retrieve the directories which could not be read, along with the reason.
+    /// Nothing beneath them is reflected in the draft.
+    pub fn unreadable(&self) -> &[(PathBuf, String)] {
+        &self.unreadable
+    }
+}
+
+// a directory found on disk, along with its subdirectories
+struct Dir {
+    name: String,
+    perms: u32,
+    children: Vec<Dir>,
+}
+
+// what a node of the draft matches
+enum Identity {
+    Literal(String),
+    Regex(String, Option<String>),
+}
+
+// a node of the draft
+struct Node {
+    name: String,
+    identity: Identity,
+    perms: Option<String>,
+    children: Vec<Node>,
+}
+
+// a run of characters within a name
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub(crate) enum Run {
+    Upper,
+    Lower,
+    Digit,
+    Other(char),
+}
+
+impl Run {
+    fn of(c: char) -> Self {
+        match c {
+            'A'..='Z' => Run::Upper,
+            'a'..='z' => Run::Lower,
+            '0'..='9' => Run::Digit,
+            _ => Run::Other(c),
+        }
+    }
+
+    fn class(self) -> Option<&'static str> {
+        match self {
+            Run::Upper => Some("[A-Z]"),
+            Run::Lower => Some("[a-z]"),
+            Run::Digit => Some("[0-9]"),
+            Run::Other(_) => None,
+        }
+    }
+}
+
+/// Infer a draft template from a directory tree. Only directories are considered,
+/// and symlinks are not followed. The draft compiles, and matches every directory
+/// which was read, but is meant as a starting point: names, literals which ought
+/// to be regexes, and metadata other than `perms` are left to the author.
+///
+/// # Parameters
+///
+/// * `dir` - the directory corresponding to the template's root
+/// * `depth` - the number of levels beneath `dir` to read, or None to read them all
+///
+/// # Returns
+/// A Result wrapping the Draft if successful. Otherwise a JSPTemplateError, if
+/// `dir` itself could not be read.
+///
+/// # Examples
+///
+/// ```no_run
+/// use jspcompile::infer::infer;
+/// use std::path::Path;
+///
+/// let draft = infer(Path::new("/dd"), Some(4)).unwrap();
+/// for (dir, reason) in draft.unreadable() {
+///     eprintln!("skipped {}: {}", dir.display(), reason);
+/// }
+/// println!("{}", draft.source());
+/// ```
+pub fn infer(dir: &Path, depth: Option<usize>) -> Result<Draft, JSPTemplateError> {
+    let mut unreadable = Vec::new();
+    let children = match depth {
+        Some(0) => Vec::new(),
+        _ => scan(dir, fs::read_dir(dir)?, depth, &mut unreadable),
+    };
+    let root = Dir { name: "root".to_string(), perms: 0, children };
+    let mut taken = HashSet::new();
+    taken.insert("root".to_string());
+    let nodes = infer_nodes(&[&root], "root", &mut taken);
+
+    let mut source = String::from("[regex]\n\n[nodes]\n");
+    let mut stack = nodes.iter().rev().collect::<Vec<_>>();
+    while let Some(node) = stack.pop() {
+        let definition = match &node.identity {
+            Identity::Literal(value) if *value == node.name => node.name.clone(),
+            Identity::Literal(value) if value.chars().all(is_ident_char) => format!("{} = {}", node.name, value),
+            Identity::Literal(value) => format!("{} = {}", node.name, quote(&ext_regex::escape(value), None)),
+            Identity::Regex(pattern, exclude) => format!("{} = {}", node.name, quote(pattern, exclude.as_ref().map(|x| &**x))),
+        };
+        source.push_str(&definition);
+        if let Some(perms) = &node.perms {
+            source.push_str(&format!(" [perms: {}]", perms));
+        }
+        source.push('\n');
+        stack.extend(node.children.iter().rev());
+    }
+
+    source.push_str("\n[graph]\n");
+    for chain in chains("root", &nodes) {
+        source.push_str(&format!("{}\n", chain.join(" -> ")));
+    }
+    Ok(Draft { source: format_template(&source)?, unreadable })
+}
+
+// read the subdirectories of `path`, listed by `entries`, down to `depth` levels,
+// sorted by name. A subdirectory which cannot be read is kept without children,
+// and recorded in `unreadable`
+fn scan(path: &Path, entries: fs::ReadDir, depth: Option<usize>, unreadable: &mut Vec<(PathBuf, String)>) -> Vec<Dir> {
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                unreadable.push((path.to_path_buf(), e.to_string()));
+                continue;
+            }
+        };
+        let child = entry.path();
+        let metadata = match fs::symlink_metadata(&child) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                unreadable.push((child, e.to_string()));
+                continue;
+            }
+        };
+        if !metadata.is_dir() {
+            continue;
+        }
+        let children = match depth.map(|depth| depth - 1) {
+            Some(0) => Vec::new(),
+            depth => match fs::read_dir(&child) {
+                Ok(entries) => scan(&child, entries, depth, unreadable),
+                Err(e) => {
+                    unreadable.push((child.clone(), e.to_string()));
+                    Vec::new()
+                }
+            },
+        };
+        dirs.push(Dir {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            perms: metadata.permissions().mode() & 0o7777,
+            children,
+        });
+    }
+    dirs.sort_by(|a, b| a.name.cmp(&b.name));
+    dirs
+}
+
+// group the subdirectories of `parents`, which all stand for the node `parent`,
+// into nodes, and recurse
+fn infer_nodes(parents: &[&Dir], parent: &str, taken: &mut HashSet<String>) -> Vec<Node> {
+    // each name, along with the directories which have it
+    let mut names: BTreeMap<&str, Vec<&Dir>> = BTreeMap::new();
+    for dir in parents {
+        for child in &dir.children {
+            names.entry(&child.name).or_default().push(child);
+        }
+    }
+    let recurring = |dirs: &Vec<&Dir>| parents.len() > 1 && dirs.len() == parents.len();
+    let mut shapes: BTreeMap<Vec<Run>, Vec<&str>> = BTreeMap::new();
+    for (name, dirs) in &names {
+        if !recurring(dirs) {
+            shapes.entry(shape(name)).or_default().push(name);
+        }
+    }
+    let families = shapes.into_iter()
+        .filter(|(_, members)| members.len() >= MIN_FAMILY)
+        .map(|(_, members)| members)
+        .collect::<Vec<_>>();
+    let in_family = families.iter().flatten().cloned().collect::<HashSet<_>>();
+    let literals = names.keys().filter(|name| !in_family.contains(*name)).cloned().collect::<Vec<_>>();
+
+    let mut nodes = Vec::new();
+    for literal in &literals {
+        let dirs = &names[literal];
+        let name = unique_name(&sanitize(&literal.to_lowercase()), Some(parent), taken);
+        let children = infer_nodes(dirs, &name, taken);
+        nodes.push((*literal, Node { name, identity: Identity::Literal(literal.to_string()), perms: perms(dirs), children }));
+    }
+    for members in &families {
+        let dirs = members.iter().flat_map(|member| names[member].iter().cloned()).collect::<Vec<_>>();
+        let spread = parents.iter().filter(|parent| parent.children.iter().any(|child| members.contains(&&*child.name))).count();
+        let pattern = generalize(members, spread > 1);
+        let regex = ext_regex::Regex::new(&format!("^{}$", pattern)).expect("generalized names are valid regexes");
+        let excluded = literals.iter().filter(|literal| regex.is_match(literal)).map(|literal| ext_regex::escape(literal)).collect::<Vec<_>>();
+        let exclude = if excluded.is_empty() { None } else { Some(format!("({})", excluded.join("|"))) };
+        let name = unique_name(&family_name(parent), Some(parent), taken);
+        let children = infer_nodes(&dirs, &name, taken);
+        nodes.push((members[0], Node { name, identity: Identity::Regex(pattern, exclude), perms: perms(&dirs), children }));
+    }
+    nodes.sort_by(|a, b| a.0.cmp(b.0));
+    nodes.into_iter().map(|(_, node)| node).collect()
+}
+
+// the runs of a name, by kind
+pub(crate) fn shape(name: &str) -> Vec<Run> {
+    let mut runs: Vec<Run> = Vec::new();
+    for c in name.chars() {
+        let run = Run::of(c);
+        if runs.last() != Some(&run) || run.class().is_none() {
+            runs.push(run);
+        }
+    }
+    runs
+}
+
+// split a name into the text of each of its runs
+fn split(name: &str) -> Vec<String> {
+    let mut texts: Vec<String> = Vec::new();
+    let mut last = None;
+    for c in name.chars() {
+        let run = Run::of(c);
+        match texts.last_mut() {
+            Some(text) if last == Some(run) && run.class().is_some() => text.push(c),
+            _ => texts.push(c.to_string()),
+        }
+        last = Some(run);
+    }
+    texts
+}
+
+// a regex matching names which share a shape. Runs which are the same in every
+// name are kept as written if `keep_common` is set
+pub(crate) fn generalize(names: &[&str], keep_common: bool) -> String {
+    let runs = shape(names[0]);
+    let texts = names.iter().map(|name| split(name)).collect::<Vec<_>>();
+    runs.iter().enumerate().map(|(index, run)| {
+        let first = &texts[0][index];
+        let common = texts.iter().all(|text| text[index] == *first);
+        match run.class() {
+            Some(_) if common && keep_common => ext_regex::escape(first),
+            Some(class) => {
+                let length = first.chars().count();
+                if texts.iter().all(|text| text[index].chars().count() == length) {
+                    if length == 1 { class.to_string() } else { format!("{}{{{}}}", class, length) }
+                } else {
+                    format!("{}+", class)
+                }
+            }
+            None => ext_regex::escape(first),
+        }
+    }).collect()
+}
+
+// the name of a family beneath `parent`: the singular of a plural, as in `shows`
+// and `show`, or else a subdirectory of the parent
+fn family_name(parent: &str) -> String {
+    if parent.len() > 1 && parent.ends_with('s') && !parent.ends_with("ss") {
+        parent[..parent.len() - 1].to_string()
+    } else {
+        format!("{}_sd", parent)
+    }
+}
+
+// the permissions most often seen among the directories, in octal
+fn perms(dirs: &[&Dir]) -> Option<String> {
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for dir in dirs {
+        *counts.entry(dir.perms).or_default() += 1;
+    }
+    counts.into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+        .map(|(perms, _)| format!("{:o}", perms))
+}
+
+// the edges beneath `parent`, as chains which follow each node's first child
+fn chains<'a>(parent: &'a str, nodes: &'a [Node]) -> Vec<Vec<&'a str>> {
+    let mut chains = Vec::new();
+    for node in nodes {
+        let mut below = chains_from(node);
+        below[0].insert(0, parent);
+        chains.extend(below);
+    }
+    chains
+}
+
+// the chains starting at `node`
+fn chains_from(node: &Node) -> Vec<Vec<&str>> {
+    match node.children.split_first() {
+        None => vec![vec![&*node.name]],
+        Some((first, rest)) => {
+            let mut below = chains_from(first);
+            below[0].insert(0, &node.name);
+            below.extend(chains(&node.name, rest));
+            below
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{loader::load_template, Matcher};
+    use std::path::PathBuf;
+
+    fn tree(paths: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for path in paths {
+            fs::create_dir_all(dir.path().join(path)).unwrap();
+        }
+        dir
+    }
+
+    // every directory beneath `dir`, relative to it
+    fn walk(dir: &Path, relative: PathBuf, found: &mut Vec<String>) {
+        for entry in fs::read_dir(dir.join(&relative)).unwrap() {
+            let path = relative.join(entry.unwrap().file_name());
+            found.push(format!("/{}", path.display()));
+            walk(dir, path, found);
+        }
+    }
+
+    // the definition of a node in the draft, without its metadata
+    fn definition(draft: &str, name: &str) -> String {
+        let line = draft.lines()
+            .find(|line| line.split(|c| c == ' ' || c == '=').next() == Some(name))
+            .unwrap_or_else(|| panic!("no node {} in\n{}", name, draft));
+        let line = line.splitn(2, " [").next().unwrap();
+        line.splitn(2, '=').nth(1).unwrap_or("").trim().to_string()
+    }
+
+    #[test]
+    fn infers_families_and_literals_which_match_the_tree() {
+        let dir = tree(&[
+            "dd/shows/FOO/AA/AA_0100", "dd/shows/FOO/AA/AA_0110", "dd/shows/FOO/BB/BB_0200",
+            "dd/shows/BAR/CC/CC_0300", "dd/shows/BAZ/etc", "dd/shows/FOO/etc", "dd/shows/BAR/etc",
+            "dd/shows/FOO/v001", "dd/REF",
+        ]);
+        let draft = infer(dir.path(), None).unwrap().source().to_string();
+        assert_eq!(definition(&draft, "show"), "\"[A-Z]{3}\"");
+        assert_eq!(definition(&draft, "show_sd"), "\"[A-Z]{2}\"");
+        assert_eq!(definition(&draft, "show_sd_sd"), "\"[A-Z]{2}_[0-9]{4}\"");
+        assert_eq!(definition(&draft, "ref"), "REF");
+        assert_eq!(definition(&draft, "etc"), "");
+        assert_eq!(definition(&draft, "v001"), "");
+        let (graph, keymap) = load_template(&draft);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let mut found = Vec::new();
+        walk(dir.path(), PathBuf::new(), &mut found);
+        for path in found {
+            assert!(matcher.match_path(&path).is_match(), "{} in\n{}", path, draft);
+        }
+    }
+
+    #[test]
+    fn excludes_literals_and_records_perms() {
+        let dir = tree(&["shows/FOO/work", "shows/BAR/work", "shows/BAZ/work", "shows/FOO/ab", "shows/BAR/cde", "shows/BAZ/fghi"]);
+        for path in &["shows/FOO", "shows/BAR", "shows/BAZ"] {
+            fs::set_permissions(dir.path().join(path), fs::Permissions::from_mode(0o751)).unwrap();
+        }
+        let draft = infer(dir.path(), Some(3)).unwrap().source().to_string();
+        let default = fs::metadata(dir.path().join("shows")).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(
+            draft,
+            format!(
+                "[regex]\n\
+                 \n\
+                 [nodes]\n\
+                 shows [perms: {default:o}]\n\
+                 show    = \"[A-Z]{{3}}\" [perms: 751]\n\
+                 show_sd = \"[a-z]+\" \"(work)\" [perms: {default:o}]\n\
+                 work [perms: {default:o}]\n\
+                 \n\
+                 [graph]\n\
+                 root -> shows -> show -> show_sd\n\
+                 show -> work\n",
+                default = default,
+            )
+        );
+    }
+
+    #[test]
+    fn skips_unreadable_directories_and_carries_on() {
+        let dir = tree(&["shows/FOO/work", "shows/BAR/work", "shows/BAZ/work"]);
+        let locked = dir.path().join("shows/BAR");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        let draft = infer(dir.path(), None);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        let draft = draft.unwrap();
+        // root may read the directory regardless
+        if draft.unreadable().is_empty() {
+            return;
+        }
+        assert_eq!(draft.unreadable().len(), 1);
+        assert_eq!(draft.unreadable()[0].0, locked);
+        assert_eq!(definition(draft.source(), "show"), "\"[A-Z]{3}\"");
+        assert_eq!(definition(draft.source(), "work"), "");
+    }
+
+    #[test]
+    fn generalizes_names_by_shape() {
+        assert_eq!(shape("AA_0100"), vec![Run::Upper, Run::Other('_'), Run::Digit]);
+        assert_eq!(generalize(&["AA_0100", "AA_0110", "BB_0200"], false), "[A-Z]{2}_[0-9]{4}");
+        assert_eq!(generalize(&["v001", "v002", "v0010"], true), "v[0-9]+");
+        assert_eq!(generalize(&["v001", "v002", "v003"], false), "[a-z][0-9]{3}");
+        assert_eq!(family_name("shows"), "show");
+        assert_eq!(family_name("show"), "show_sd");
+    }
+}
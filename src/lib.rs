@@ -6,6 +6,8 @@ pub use parser::{start_parser, regex_parser, node_parser, edge_parser, parse_com
 
 pub mod helpers;
 
+pub mod spelling;
+
 pub mod components;
 pub use components::*;
 
@@ -13,7 +15,41 @@ pub mod statemachine;
 pub use statemachine::{State, StateMachine};
 
 pub mod errors;
-pub use errors::{JSPTemplateError, JSPTemplateLineError};
+pub use errors::{JSPTemplateError, JSPTemplateLineError, JSPTemplateWarning, JSPTemplateLineWarning, ParseError, ParseErrorKind};
+
+pub mod explain;
+
+pub mod diagnostic;
+pub use diagnostic::{Diagnostic, Severity};
+
+pub mod console;
+pub use console::{Console, ColorChoice, MessageFormat};
+
+pub mod syntax;
+
+pub mod formatter;
+
+pub mod matcher;
+pub use matcher::{Matcher, PathMatch};
+
+pub mod samples;
+
+pub mod sandbox;
+pub mod audit;
+pub mod repair;
+pub mod diff;
+pub mod automaton;
+pub mod compat;
+pub mod migrate;
+pub mod watch;
+pub mod tree;
+pub mod export;
+pub mod diagram;
+pub mod decompile;
+pub mod infer;
+pub mod suggest;
+pub mod env;
+pub mod build_path;
 
 pub mod loader;
-pub use loader::{Loader, JGraphKeyMap, RegexMap};
+pub use loader::{Loader, JGraphKeyMap, RegexMap, Summary};
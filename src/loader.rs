@@ -2,16 +2,19 @@ use crate::{
     StateMachine,
     JSPTemplateLineError,
     JSPTemplateError,
+    JSPTemplateWarning,
+    JSPTemplateLineWarning,
     //State,
     ParseResult,
     Regex,
     Node as SNode,
     Edge,
-    Metadata
+    Metadata,
+    spelling::Suggestions,
 };
 use jsp::{JGraph, NIndex, User, Node, Regexp, EntryType, NodeType, Metadata as JspMetadata };
 use log;
-use std::{io::BufRead, collections::HashMap};
+use std::{io::BufRead, collections::{HashMap, HashSet}};
 
 
 /// s! calls to_string() on its input
@@ -49,6 +52,25 @@ pub type JGraphKeyMap = HashMap<String, NIndex>;
 /// a JGraph instance after successfully parsing the jsptemplate.
 pub type RegexMap     = HashMap<String, NodeType>;
 
+/// Counts describing a loaded template, as reported once loading succeeds.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Summary {
+    /// The number of nodes defined, not counting root
+    pub nodes: usize,
+    /// The number of named regexes defined
+    pub regexes: usize,
+    /// The number of edges in the graph
+    pub edges: usize,
+    /// The number of warnings reported while loading
+    pub warnings: usize,
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} nodes, {} regexes, {} edges, {} warnings", self.nodes, self.regexes, self.edges, self.warnings)
+    }
+}
+
 /// Loader is responsible for loading the jspt  from something that implements
 /// the BufRead interace (like a buffered file or a Cursor) and producing
 /// a populated JGraph. 
@@ -77,6 +99,14 @@ pub struct Loader<'a> {
     graph: &'a mut JGraph,
     keymap: &'a mut JGraphKeyMap,
     regexmap: &'a mut RegexMap,
+    // the line on which each node, regex, and edge was first defined, 
+    // so that we may warn about redefinitions
+    node_lines: HashMap<String, usize>,
+    regex_lines: HashMap<String, usize>,
+    edge_lines: HashMap<(String, String), usize>,
+    warnings: Vec<JSPTemplateLineWarning>,
+    // jsp's Metadata has no notion of autocreate, so we track it here
+    autocreate: HashSet<NIndex>,
 }
 
 impl<'a> Loader<'a> {
@@ -105,7 +135,38 @@ impl<'a> Loader<'a> {
         keymap.insert(s!("root"), graph.add_node(Node::new_root()));
 
         Self {
-            graph, keymap, regexmap
+            graph, 
+            keymap, 
+            regexmap,
+            node_lines: HashMap::new(),
+            regex_lines: HashMap::new(),
+            edge_lines: HashMap::new(),
+            warnings: Vec::new(),
+            autocreate: HashSet::new(),
+        }
+    }
+
+    /// Retrieve the warnings accumulated while loading. Warnings identify
+    /// likely mistakes which do not prevent the template from compiling, 
+    /// such as redefining a node.
+    pub fn warnings(&self) -> &[JSPTemplateLineWarning] {
+        &self.warnings
+    }
+
+    /// Retrieve the nodes whose metadata marks them `autocreate`. An autocreate 
+    /// node is one which must exist whenever its parent does.
+    pub fn autocreate(&self) -> &HashSet<NIndex> {
+        &self.autocreate
+    }
+
+    /// Summarize the loaded template, for reporting to the user.
+    pub fn summary(&self) -> Summary {
+        Summary {
+            // the root node is implicit, so we do not count it
+            nodes: self.keymap.len().saturating_sub(1),
+            regexes: self.regexmap.len(),
+            edges: self.graph.edge_count(),
+            warnings: self.warnings.len(),
         }
     }
 
@@ -142,7 +203,7 @@ impl<'a> Loader<'a> {
 
                             ParseResult::Regex(regex) => {
                                 log::info!("line: {} {:?}", statemachine.line_number(), regex);
-                                self.process_regex(regex)?;
+                                self.process_regex(regex, line.as_str(), &statemachine)?;
                             }
                             ParseResult::Edges(edges) => {
                                 log::info!("line: {} {:?}", statemachine.line_number(), edges);
@@ -168,6 +229,8 @@ impl<'a> Loader<'a> {
 
     // Process a vector of edges supplied by the parsing of a line of hte jsptemplate. 
     // We provide the line and statemachine for context in the case of failure. 
+    // Edges which have already been defined are added again, as they always have
+    // been, but with a warning.
     fn process_edges(&mut self, edges: Vec<Edge>, line: &str, statemachine: &StateMachine) -> Result<(), JSPTemplateError> {
         for edge in edges {
             log::debug!("Adding edge for {:?}", &edge);
@@ -176,7 +239,10 @@ impl<'a> Loader<'a> {
                     statemachine.line_number(),
                     line.to_owned(),
                     statemachine.state().clone(),
-                    JSPTemplateError::KeyMapLookupError(edge.from.clone())
+                    JSPTemplateError::KeyMapLookupError(
+                        edge.from.clone(),
+                        Suggestions::from_candidates(&edge.from, self.keymap.keys())
+                    )
                 ))
             )?;
             let to_node = self.keymap.get(&edge.to).ok_or_else(||
@@ -184,16 +250,30 @@ impl<'a> Loader<'a> {
                     statemachine.line_number(),
                     line.to_owned(),
                     statemachine.state().clone(),
-                    JSPTemplateError::KeyMapLookupError(edge.to.clone())
+                    JSPTemplateError::KeyMapLookupError(
+                        edge.to.clone(),
+                        Suggestions::from_candidates(&edge.to, self.keymap.keys())
+                    )
                 ))
             )?;
             self.graph.extend_with_edges(&[(from_node.clone(), to_node.clone())]);
+            let key = (edge.from, edge.to);
+            match self.edge_lines.get(&key).cloned() {
+                Some(first_line) => self.warn(line, statemachine, JSPTemplateWarning::DuplicateEdge(key.0, key.1, first_line)),
+                None => { self.edge_lines.insert(key, statemachine.line_number()); }
+            }
         }
         Ok(())
     }
 
     // Process a node, generated by the StateMachine's parsing of an appropriate line
     fn process_node(&mut self, node: SNode, line: &str, statemachine: &StateMachine) -> Result<(), JSPTemplateError> {
+        if let Some(first_line) = self.node_lines.get(node.name()).cloned() {
+            self.warn(line, statemachine, JSPTemplateWarning::DuplicateNode(node.name().to_string(), first_line));
+        } else {
+            self.node_lines.insert(node.name().to_string(), statemachine.line_number());
+        }
+        let autocreate = node.metadata().map_or(false, |metadata| metadata.is_autocreate());
         match node {
             // `rd`
             SNode::Simple(ref name, ref metadata) => {
@@ -234,9 +314,12 @@ impl<'a> Loader<'a> {
                         statemachine.line_number(),
                         line.to_owned(),
                         statemachine.state().clone(),
-                        JSPTemplateError::RegexMapLookupError(variable.clone()
+                        JSPTemplateError::RegexMapLookupError(
+                            variable.clone(),
+                            Suggestions::from_candidates(variable, self.regexmap.keys())
+                        )
                     ))
-                ))?;
+                )?;
                 //let entrytype = if is_volume(metadata) {EntryType::Volume} else {EntryType::Directory};
                 let entrytype = if metadata.is_volume() {EntryType::Volume} else {EntryType::Directory};
                 self.keymap.insert(
@@ -286,6 +369,9 @@ impl<'a> Loader<'a> {
                 );
             }
         };
+        if autocreate {
+            self.autocreate.insert(self.keymap[node.name()]);
+        }
 
         Ok(())
     }
@@ -294,7 +380,12 @@ impl<'a> Loader<'a> {
     // line.
     // match against the various flavors or regex and construct Regex objects in the regexmap store
     // these will be used in node later.
-    fn process_regex(&mut self, regex: Regex)-> Result<(), JSPTemplateError> {
+    fn process_regex(&mut self, regex: Regex, line: &str, statemachine: &StateMachine)-> Result<(), JSPTemplateError> {
+        if let Some(first_line) = self.regex_lines.get(regex.name()).cloned() {
+            self.warn(line, statemachine, JSPTemplateWarning::DuplicateRegex(regex.name().to_string(), first_line));
+        } else {
+            self.regex_lines.insert(regex.name().to_string(), statemachine.line_number());
+        }
         match regex {
 
             Regex::Simple{ ref name,  ref value} => {
@@ -310,6 +401,19 @@ impl<'a> Loader<'a> {
         }
         Ok(())
     }
+
+    // Record a warning along with the context in which it was encountered
+    fn warn(&mut self, line: &str, statemachine: &StateMachine, warning: JSPTemplateWarning) {
+        log::info!("line: {} {}", statemachine.line_number(), warning);
+        self.warnings.push(
+            JSPTemplateLineWarning::from((
+                statemachine.line_number(),
+                line.to_owned(),
+                statemachine.state().clone(),
+                warning
+            ))
+        );
+    }
 }
 
 /**
@@ -357,3 +461,135 @@ fn new_jsp_metadata( meta: &Option<crate::Metadata> ) -> JspMetadata {
 //     }
 // }
 
+/// Load a template which is known to be valid, for use by tests
+#[cfg(test)]
+pub(crate) fn load_template(template: &str) -> (JGraph, JGraphKeyMap) {
+    let (graph, keymap, _) = load_template_with_autocreate(template);
+    (graph, keymap)
+}
+
+/// Load a template which is known to be valid, along with its autocreate nodes,
+/// for use by tests
+#[cfg(test)]
+pub(crate) fn load_template_with_autocreate(template: &str) -> (JGraph, JGraphKeyMap, HashSet<NIndex>) {
+    let (mut graph, mut keymap, mut regexmap) = Loader::setup();
+    let autocreate = {
+        let mut loader = Loader::new(&mut graph, &mut keymap, &mut regexmap);
+        loader.load(std::io::Cursor::new(template)).unwrap();
+        loader.autocreate().clone()
+    };
+    (graph, keymap, autocreate)
+}
+
+/// Create a directory, along with any missing parents, and set its permissions to
+/// the octal mode, for use by tests
+#[cfg(test)]
+pub(crate) fn mkdir(path: &std::path::Path, mode: u32) {
+    use std::{fs, os::unix::fs::PermissionsExt};
+    fs::create_dir_all(path).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // load the supplied template, returning the error it produces
+    fn load_err(template: &str) -> JSPTemplateError {
+        let (mut graph, mut keymap, mut regexmap) = Loader::setup();
+        let mut loader = Loader::new(&mut graph, &mut keymap, &mut regexmap);
+        loader.load(Cursor::new(template)).unwrap_err()
+    }
+
+    #[test]
+    fn can_load_example_template() {
+        let (mut graph, mut keymap, mut regexmap) = Loader::setup();
+        let mut loader = Loader::new(&mut graph, &mut keymap, &mut regexmap);
+        let result = loader.load(Cursor::new(include_str!("../examples/jsptemplate.jspt")));
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn records_autocreate_nodes() {
+        let template = "[regex]\n[nodes]\ndd\nwork = work [ autocreate, perms: 770 ]\n[graph]\nroot -> dd -> work\n";
+        let (mut graph, mut keymap, mut regexmap) = Loader::setup();
+        let autocreate = {
+            let mut loader = Loader::new(&mut graph, &mut keymap, &mut regexmap);
+            loader.load(Cursor::new(template)).unwrap();
+            loader.autocreate().clone()
+        };
+        assert_eq!(autocreate.into_iter().collect::<Vec<_>>(), vec![keymap["work"]]);
+    }
+
+    #[test]
+    fn warns_about_redefinitions() {
+        let template = "[regex]\nlevel = \"[A-Z]+\"\nlevel = \"[a-z]+\"\n[nodes]\ndd\nshow = $level\ndd\n[graph]\nroot -> dd -> show\ndd -> show\n";
+        let (mut graph, mut keymap, mut regexmap) = Loader::setup();
+        let mut loader = Loader::new(&mut graph, &mut keymap, &mut regexmap);
+        loader.load(Cursor::new(template)).unwrap();
+        let warnings = loader.warnings().iter()
+            .map(|JSPTemplateLineWarning::WarningAtLine(line_num, _, _, warning)| (*line_num, warning.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            vec![
+                (3, JSPTemplateWarning::DuplicateRegex(s!("level"), 2)),
+                (7, JSPTemplateWarning::DuplicateNode(s!("dd"), 5)),
+                (10, JSPTemplateWarning::DuplicateEdge(s!("dd"), s!("show"), 9)),
+            ]
+        );
+        // warnings do not change what is loaded, so each definition of an edge is added
+        assert_eq!(loader.summary(), Summary { nodes: 2, regexes: 1, edges: 3, warnings: 3 });
+    }
+
+    #[test]
+    fn suggests_node_name_for_unknown_edge() {
+        let template = "[regex]\n[nodes]\ndd\nshows\nseq\n[graph]\nroot -> dd -> shows -> sequence\n";
+        match load_err(template) {
+            JSPTemplateError::ErrorAtLine(7, _, _, error) => match *error {
+                JSPTemplateError::KeyMapLookupError(name, suggestions) => {
+                    assert_eq!(name, "sequence");
+                    assert_eq!(suggestions.names(), &[s!("seq")]);
+                }
+                other => panic!("expected KeyMapLookupError. got {:?}", other),
+            },
+            other => panic!("expected ErrorAtLine. got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn suggests_regex_name_for_unknown_variable() {
+        let template = "[regex]\nlevel = \"[A-Z]+\"\n[nodes]\nshow = $levle\n";
+        match load_err(template) {
+            JSPTemplateError::ErrorAtLine(4, _, _, error) => {
+                assert_eq!(error.to_string(), "Regex Map Lookup failed for: levle; did you mean `level`?");
+            }
+            other => panic!("expected ErrorAtLine. got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn suggests_header_for_unknown_header() {
+        match load_err("[regex]\n[nodes]\n[grapha]\n") {
+            JSPTemplateError::ErrorAtLine(3, _, _, error) => {
+                assert_eq!(error.to_string(), "Unknown section header: [grapha]; did you mean `graph`?");
+            }
+            other => panic!("expected ErrorAtLine. got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn error_example_suggests_misspelled_node() {
+        match load_err(include_str!("../examples/error.jspt")) {
+            JSPTemplateError::ErrorAtLine(25, _, _, error) => match *error {
+                JSPTemplateError::KeyMapLookupError(name, suggestions) => {
+                    assert_eq!(name, "quicktimes");
+                    assert_eq!(suggestions.names(), &[s!("quickimes")]);
+                }
+                other => panic!("expected KeyMapLookupError. got {:?}", other),
+            },
+            other => panic!("expected ErrorAtLine. got {:?}", other),
+        }
+    }
+}
@@ -1,15 +1,18 @@
 use chrono;
-use colored::Colorize;
 use fern::{ colors::{Color, ColoredLevelConfig}, self} ;
-use jsp::diskutils;
-use jspcompile::{JSPTemplateError, Loader, State};
+use jsp::{diskutils, JGraph, NIndex};
+use jspcompile::{ColorChoice, Console, JGraphKeyMap, JSPTemplateError, Loader, Matcher, MessageFormat, matcher::{describe_identity, describe_metadata}, Summary, audit::{self, ReportFormat}, build_path::build_path, compat, decompile, diagram, diff, env::{self, Shell}, explain::explain, export::{Document, OutputFormat}, infer::{self, UNREADABLE_DIRECTORY}, migrate::{self, ScriptFormat}, formatter::format_template, repair, samples::{sample_paths, unsampled_nodes, UNSAMPLED_NODE}, sandbox::{self, Operation}, spelling::Suggestions, suggest::{self, Verdict}, tree, watch::Watcher, Diagnostic, Severity};
 use log::{ LevelFilter, self};
-use std::{fs::File,io::BufReader, path::PathBuf};
+use std::{collections::{HashMap, HashSet}, fs::File, io::{self, BufReader}, os::unix::fs::PermissionsExt, path::{Path, PathBuf}, time::Duration};
 use structopt::StructOpt;
 
 
+// exit codes. check distinguishes between templates with warnings and templates with errors
+const EXIT_ERRORS: i32 = 1;
+const EXIT_WARNINGS: i32 = 2;
+
 #[derive(Debug, StructOpt)]
-#[structopt(name = "jspcompile", about = "Compile a jsptemplate from a jspt file")]
+#[structopt(name = "jspcompile", about = "Compile and inspect jsptemplates")]
 struct Opt {
     /// Set logging level to one of trace, debug, info, warn, error
     #[structopt( short = "l", long = "level", default_value = "warn" )]
@@ -19,101 +22,839 @@ struct Opt {
     #[structopt(short = "d", long = "debug")]
     debug: bool,
 
-    /// ougput dot graph instead of template
-    #[structopt( long = "dot")]
-    dotgraph: bool,
+    /// Format of errors and warnings. json emits one json object per line
+    #[structopt( long = "message-format", default_value = "human", raw(possible_values = r#"&["human", "json"]"#))]
+    message_format: MessageFormat,
 
-    /// Input jspt file
-    #[structopt(parse(from_os_str))]
-    input: PathBuf,
-    
-    /// Output file, stdout if not present
-    #[structopt(parse(from_os_str))]
-    output: Option<PathBuf>,
+    /// When to colorize messages. auto colorizes when writing to a terminal, unless NO_COLOR is set
+    #[structopt( long = "color", default_value = "auto", raw(possible_values = r#"&["auto", "always", "never"]"#))]
+    color: ColorChoice,
+
+    /// Print a detailed explanation of an error or warning code, such as JSP0004, and exit
+    #[structopt( long = "explain")]
+    explain: Option<String>,
+
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Compile a jsptemplate from a jspt file
+    #[structopt(name = "compile")]
+    Compile {
+        /// ougput dot graph instead of template. Short for --format dot
+        #[structopt( long = "dot", raw(conflicts_with = r#""format""#))]
+        dotgraph: bool,
+
+        /// Format of the output. json and yaml describe every node and edge, following 
+        /// a versioned schema. mermaid and plantuml are diagrams [default: template]
+        #[structopt( long = "format", raw(possible_values = r#"&["template", "dot", "json", "yaml", "mermaid", "plantuml"]"#))]
+        format: Option<OutputFormat>,
+
+        /// Show regexes in full in mermaid and plantuml diagrams, rather than truncating them
+        #[structopt( long = "full-regex")]
+        full_regex: bool,
+
+        /// Input jspt file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Output file, stdout if not present
+        #[structopt(parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Recompile whenever the input changes, until interrupted. The output is only 
+        /// replaced when the new version compiles and is written successfully
+        #[structopt( long = "watch", raw(requires = r#""output""#))]
+        watch: bool,
+
+        /// How often to check the input for changes when watching, in milliseconds
+        #[structopt( long = "interval", default_value = "1000")]
+        interval: u64,
+    },
+
+    /// Validate one or more jspt files without writing any output. Exits with 1 if 
+    /// any file has errors, 2 if any file has warnings, and 0 otherwise
+    #[structopt(name = "check")]
+    Check {
+        /// Input jspt files
+        #[structopt(parse(from_os_str), raw(required = "true"))]
+        files: Vec<PathBuf>,
+    },
+
+    /// Match a path against a template, showing the node which matched each component, 
+    /// or why it failed to match. Exits with 1 if the path does not match
+    #[structopt(name = "match")]
+    Match {
+        /// Input jspt file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Path to match, such as /dd/shows/FOO
+        path: String,
+    },
+
+    /// Match a path against a template, printing the commands which export the varname 
+    /// of each node along it, set to the directory name it matched. Exits with 1 if 
+    /// the path does not match
+    #[structopt(name = "env")]
+    Env {
+        /// Input jspt file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Path to match, such as /dd/shows/FOO
+        path: String,
+
+        /// The shell which is to run the commands
+        #[structopt( long = "shell", default_value = "bash", raw(possible_values = r#"&["bash", "zsh", "tcsh", "fish"]"#))]
+        shell: Shell,
+    },
+
+    /// Build the path from root to a node, filling regex nodes with the values set for 
+    /// their varnames, and literal nodes with their literals
+    #[structopt(name = "build-path")]
+    BuildPath {
+        /// Input jspt file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// The node at which the path ends
+        node: String,
+
+        /// The value of a variable, as VARNAME=VALUE. A regex node without a varname 
+        /// is set by its name
+        #[structopt(long = "set", parse(try_from_str = "parse_assignment"), raw(number_of_values = "1"))]
+        set: Vec<(String, String)>,
+    },
+
+    /// Generate example paths which a template accepts
+    #[structopt(name = "samples")]
+    Samples {
+        /// Input jspt file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// The number of paths to generate
+        #[structopt(short = "n", long = "count", default_value = "10")]
+        count: usize,
+
+        /// Only generate paths which end at this node
+        #[structopt(long = "node")]
+        node: Option<String>,
+    },
+
+    /// Print the template as an indented tree from root, showing what each node matches 
+    /// and its metadata. Nodes with more than one parent are marked as shared
+    #[structopt(name = "tree")]
+    Tree {
+        /// Input jspt file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// The number of levels to show
+        #[structopt(long = "depth")]
+        depth: Option<usize>,
+
+        /// Start the tree at this node rather than at root
+        #[structopt(long = "from")]
+        from: Option<String>,
+    },
+
+    /// Reconstruct a jspt template from a compiled template, naming nodes after their 
+    /// values, hoisting regexes which are used more than once, and chaining edges
+    #[structopt(name = "decompile")]
+    Decompile {
+        /// Compiled template
+        #[structopt(parse(from_os_str))]
+        compiled: PathBuf,
+    },
+
+    /// Infer a draft template from an existing directory tree, proposing regexes for 
+    /// families of similar names and literals for the rest, and recording perms
+    #[structopt(name = "infer")]
+    Infer {
+        /// Directory corresponding to the template's root
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+
+        /// The number of levels to read
+        #[structopt(long = "depth")]
+        depth: Option<usize>,
+    },
+
+    /// Create one representative directory for each path the template describes, 
+    /// applying perms and owner from the metadata
+    #[structopt(name = "mkdirs")]
+    Mkdirs {
+        /// Input jspt file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Directory beneath which to create the tree
+        #[structopt(long = "into", parse(from_os_str))]
+        into: PathBuf,
+
+        /// Print the mkdir, chown and chmod operations instead of performing them
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+    },
+
+    /// Audit an existing directory tree against a template, reporting entries which 
+    /// match no node, perms and owners which differ from the metadata, and missing 
+    /// autocreate directories. Exits with 1 if the tree does not conform
+    #[structopt(name = "audit")]
+    Audit {
+        /// Input jspt file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Directory corresponding to the template's root
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+
+        /// Format of the report
+        #[structopt( long = "format", default_value = "text", raw(possible_values = r#"&["text", "json"]"#))]
+        format: ReportFormat,
+    },
+
+    /// Bring an existing directory tree into conformance with a template, resetting perms 
+    /// and owners, creating missing autocreate directories, and optionally quarantining 
+    /// entries which match no node. The plan is always printed first
+    #[structopt(name = "repair")]
+    Repair {
+        /// Input jspt file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Directory corresponding to the template's root
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+
+        /// Print the plan without applying it
+        #[structopt(long = "dry-run", raw(conflicts_with = r#""apply""#, required_unless = r#""apply""#))]
+        dry_run: bool,
+
+        /// Apply the plan after printing it
+        #[structopt(long = "apply")]
+        apply: bool,
+
+        /// Move entries which match no node, and files where a directory is expected, 
+        /// into this directory
+        #[structopt(long = "quarantine", parse(from_os_str))]
+        quarantine: Option<PathBuf>,
+    },
+
+    /// Compare two templates by their graphs rather than their text, reporting added and 
+    /// removed nodes and edges, and changed regexes and metadata. Nodes are identified 
+    /// by their path from root. Exits with 1 if the templates differ
+    #[structopt(name = "diff")]
+    Diff {
+        /// The old jspt file
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+
+        /// The new jspt file
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+    },
+
+    /// Check that every path which the old template accepts is accepted by the new one, 
+    /// printing a counterexample path where it is not. Exits with 1 if the new template 
+    /// is not backward compatible
+    #[structopt(name = "compat")]
+    Compat {
+        /// The old jspt file
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+
+        /// The new jspt file
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+    },
+
+    /// Plan the mkdir and mv operations which move an existing directory tree from the 
+    /// old template to the new one, printing them as a shell script or json. Directories 
+    /// which cannot be mapped are flagged rather than guessed at, and cause an exit code of 1
+    #[structopt(name = "migrate")]
+    Migrate {
+        /// The old jspt file
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+
+        /// The new jspt file
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+
+        /// Directory corresponding to the templates' root
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+
+        /// Format of the plan
+        #[structopt( long = "format", default_value = "sh", raw(possible_values = r#"&["sh", "json"]"#))]
+        format: ScriptFormat,
+    },
+
+    /// Suggest a regex which accepts the names given to --accept and rejects those given 
+    /// to --reject, in the "pos" "neg" form used in the regex section
+    #[structopt(name = "suggest-regex")]
+    SuggestRegex {
+        /// Names the regex should accept
+        #[structopt(long = "accept", raw(required = "true"))]
+        accept: Vec<String>,
+
+        /// Names the regex should reject
+        #[structopt(long = "reject")]
+        reject: Vec<String>,
+    },
+
+    /// Rewrite jspt files in the canonical style
+    #[structopt(name = "fmt")]
+    Fmt {
+        /// Report the files which are not formatted, without rewriting them. Exits 
+        /// with 1 if any file would be reformatted
+        #[structopt( long = "check")]
+        check: bool,
+
+        /// Input jspt files
+        #[structopt(parse(from_os_str), raw(required = "true"))]
+        files: Vec<PathBuf>,
+    },
 }
 
 // main is used to capture the Result of doit and provide appropriate presentation
 // to the end user before exiting
 fn main() {
-    match doit(){
-        Ok(_) => (),
+    let (opt, level) = setup_cli();
+    setup_logger(level).unwrap();
+    let mut console = Console::stderr(opt.color, opt.message_format);
+    if let Some(ref code) = opt.explain {
+        display_explanation(code, &mut console);
+        return;
+    }
+
+    let cmd = match opt.cmd {
+        Some(cmd) => cmd,
+        None => {
+            // print_help only fails if stdout has gone away
+            let _ = Opt::clap().print_help();
+            println!();
+            std::process::exit(EXIT_ERRORS);
+        }
+    };
+
+    match doit(cmd, &mut console){
+        Ok(0) => (),
+        Ok(code) => std::process::exit(code),
         Err(e) => {
-            match e {
-                JSPTemplateError::ErrorAtLine(line_num, line, state, error) => {
-                    display_formatted_error(line_num, &line, &state, error);
-                },
-                
-                _ => display_error(e),
-            }
-            std::process::exit(1);
+            // if we cannot write to stderr, there is nobody left to tell
+            let _ = console.error(&e);
+            std::process::exit(EXIT_ERRORS);
         },
     
     }
 }
 
-// guts of main. 
-fn doit() -> Result<(), JSPTemplateError> {
-    let (mut opt, level) = setup_cli();
-    setup_logger(level).unwrap();
+// guts of main. Returns the exit code on success
+fn doit(cmd: Command, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    match cmd {
+        Command::Compile { dotgraph, format, full_regex, input, output, watch: watching, interval } => {
+            let format = match format {
+                Some(format) => format,
+                None if dotgraph => OutputFormat::Dot,
+                None => OutputFormat::Template,
+            };
+            let rendering = Rendering { format, full_regex };
+            match output {
+                Some(output) if watching => watch(&input, &output, rendering, Duration::from_millis(interval), console),
+                output => compile(&input, output, rendering, console).map(|_| 0),
+            }
+        }
+        Command::Check { files } => check(&files, console),
+        Command::Fmt { check, files } => fmt(&files, check, console),
+        Command::Match { input, path } => match_path(&input, &path, console),
+        Command::Env { input, path, shell } => export_env(&input, &path, shell, console),
+        Command::BuildPath { input, node, set } => print_built_path(&input, &node, set, console),
+        Command::Samples { input, count, node } => samples(&input, count, node, console),
+        Command::Tree { input, depth, from } => print_tree(&input, depth, from, console),
+        Command::Decompile { compiled } => decompile(&compiled),
+        Command::Infer { dir, depth } => infer(&dir, depth, console),
+        Command::Mkdirs { input, into, dry_run } => mkdirs(&input, &into, dry_run, console),
+        Command::Audit { input, dir, format } => audit_dir(&input, &dir, format, console),
+        Command::Repair { input, dir, apply, quarantine, .. } => repair(&input, &dir, apply, quarantine, console),
+        Command::Diff { old, new } => diff(&old, &new, console),
+        Command::Compat { old, new } => compat(&old, &new, console),
+        Command::Migrate { old, new, dir, format } => migrate(&old, &new, &dir, format, console),
+        Command::SuggestRegex { accept, reject } => suggest_regex(&accept, &reject, console),
+    }
+}
+
+// A loaded template, along with what the Loader learned about it
+struct Template {
+    graph: JGraph,
+    keymap: JGraphKeyMap,
+    summary: Summary,
+    autocreate: HashSet<NIndex>,
+}
 
-    if !opt.input.exists() {
-        log::error!("File {:?} does not exist or we lack permissions to access it. Exiting.", &opt.input);
-        return Err(JSPTemplateError::InaccesibleFileError(opt.input.clone()));
+// Load the template, reporting any warnings to the console
+fn load(input: &Path, console: &mut Console) -> Result<Template, JSPTemplateError> {
+    console.set_file(Some(input.display().to_string()));
+
+    if !input.exists() {
+        log::error!("File {:?} does not exist or we lack permissions to access it.", &input);
+        return Err(JSPTemplateError::InaccesibleFileError(input.to_path_buf()));
     }
 
-    let file = File::open(opt.input)?;
+    let file = File::open(&input)?;
     let bufreader =  BufReader::new(file);
 
     // lets create structs that Loader::new requires
     let (mut graph, mut keymap, mut regexmap) = Loader::setup();
-    // and now call Loader::new with them.
-    let mut loader = Loader::new(&mut graph, &mut keymap, &mut regexmap);
+    let (summary, autocreate) = {
+        // and now call Loader::new with them.
+        let mut loader = Loader::new(&mut graph, &mut keymap, &mut regexmap);
+
+        loader.load(bufreader)?;
+        for warning in loader.warnings() {
+            console.warning(warning)?;
+        }
+        (loader.summary(), loader.autocreate().clone())
+    };
+    Ok(Template { graph, keymap, summary, autocreate })
+}
+
+// Compile the input, writing it to the output, or stdout if there is no output.
+fn compile(input: &Path, output: Option<PathBuf>, rendering: Rendering, console: &mut Console) -> Result<(), JSPTemplateError> {
+    let template = load(input, console)?;
+    console.success(&template.summary)?;
+    match output {
+        Some(mut output) => write_output(&mut output, &template, rendering)?,
+        None => {
+            // diskutils only knows how to write to a path, so we write to a temporary 
+            // file and copy that to stdout
+            let tmpfile = tempfile::NamedTempFile::new()?;
+            let mut tmppath = tmpfile.path().to_path_buf();
+            write_output(&mut tmppath, &template, rendering)?;
+            let stdout = io::stdout();
+            io::copy(&mut File::open(&tmppath)?, &mut stdout.lock())?;
+        }
+    }
+    Ok(())
+}
+
+// Compile the template each time it changes, reporting the diagnostics for each run. 
+// The output is replaced only when the template compiles, so a broken edit leaves 
+// the last good output in place. Runs until interrupted.
+fn watch(input: &Path, output: &Path, rendering: Rendering, interval: Duration, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let mut watcher = Watcher::new(&[input]);
+    loop {
+        match load(input, console) {
+            Ok(template) => {
+                console.success(&template.summary)?;
+                // a failed write, such as a transient NFS error, leaves the last good
+                // output in place, and is retried when the input next changes
+                if let Err(e) = replace_output(output, &template, rendering) {
+                    console.error(&e)?;
+                    console.info(format!("{} left unchanged", output.display()))?;
+                }
+            }
+            Err(e) => {
+                console.error(&e)?;
+                console.info(format!("{} left unchanged", output.display()))?;
+            }
+        }
+        console.info(format!("watching {} for changes", input.display()))?;
+        watcher.wait(interval);
+    }
+}
+
+// Write the output to a temporary file beside it and move that into place, so that 
+// readers never see a partially written template
+fn replace_output(output: &Path, template: &Template, rendering: Rendering) -> Result<(), JSPTemplateError> {
+    let parent = match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let tmpfile = tempfile::NamedTempFile::new_in(parent)?;
+    let mut tmppath = tmpfile.path().to_path_buf();
+    write_output(&mut tmppath, template, rendering)?;
+    // temporary files are private, which the output should not be
+    let permissions = match std::fs::metadata(output) {
+        Ok(metadata) => metadata.permissions(),
+        Err(_) => std::fs::Permissions::from_mode(0o644),
+    };
+    std::fs::set_permissions(&tmppath, permissions)?;
+    tmpfile.persist(output).map_err(|e| e.error)?;
+    Ok(())
+}
 
-    loader.load(bufreader)?;
-    if let Some(ref mut output) = opt.output {
-        if opt.dotgraph {
-            diskutils::write_template_as_dotfile(output, &graph);
+// Load each of the files, reporting a summary for each, and return the exit code 
+// reflecting the worst of them. Unlike the other commands, a file with errors does 
+// not stop us; we carry on to the next one.
+fn check(files: &[PathBuf], console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let mut code = 0;
+    for input in files {
+        match load(input, console) {
+            Ok(Template { summary, .. }) => {
+                console.success(&summary)?;
+                if summary.warnings > 0 && code == 0 {
+                    code = EXIT_WARNINGS;
+                }
+            }
+            Err(e) => {
+                console.error(&e)?;
+                code = EXIT_ERRORS;
+            }
+        }
+    }
+    Ok(code)
+}
+
+// Format each of the files in place or, when checking, report those which are not 
+// formatted. Returns the exit code.
+fn fmt(files: &[PathBuf], check: bool, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let mut code = 0;
+    for input in files {
+        console.set_file(Some(input.display().to_string()));
+        if !input.exists() {
+            return Err(JSPTemplateError::InaccesibleFileError(input.to_path_buf()));
+        }
+        let contents = std::fs::read_to_string(input)?;
+        let formatted = format_template(&contents)?;
+        if formatted == contents {
+            continue;
+        }
+        if check {
+            console.info(format!("{} is not formatted", input.display()))?;
+            code = EXIT_ERRORS;
         } else {
+            std::fs::write(input, formatted)?;
+            console.info(format!("formatted {}", input.display()))?;
+        }
+    }
+    Ok(code)
+}
+
+// Match the path against the template, printing each component along with the node 
+// which matched it. Returns the exit code.
+fn match_path(input: &Path, path: &str, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let Template { graph, keymap, .. } = load(input, console)?;
+    let matcher = Matcher::new(&graph, &keymap)?;
+    let result = matcher.match_path(path);
+    let width = result.components().iter().map(|c| c.component().len()).max().unwrap_or(0);
+    for component in result.components() {
+        let node = &graph[component.node()];
+        let mut details = vec![format!("node: {}", component.name()), describe_identity(node.identity())];
+        if let Some(varname) = node.metadata().varname() {
+            details.push(format!("{}={}", varname, component.component()));
+        }
+        let metadata = describe_metadata(node);
+        if !metadata.is_empty() {
+            details.push(metadata);
+        }
+        println!("{:width$}  {}", component.component(), details.join("  "), width = width);
+    }
+    match result.failure() {
+        None => Ok(0),
+        Some(failure) => {
+            println!("no match: {}", failure);
+            Ok(EXIT_ERRORS)
+        }
+    }
+}
+
+// Print the commands which export the variables set by the path. Nothing is printed 
+// to stdout unless the path matches, so that the output may be evaluated
+fn export_env(input: &Path, path: &str, shell: Shell, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let Template { graph, keymap, .. } = load(input, console)?;
+    let matcher = Matcher::new(&graph, &keymap)?;
+    let result = matcher.match_path(path);
+    match result.failure() {
+        None => {
+            print!("{}", env::script(&matcher, &result, shell));
+            Ok(0)
+        }
+        Some(failure) => {
+            console.info(format!("no match: {}", failure))?;
+            Ok(EXIT_ERRORS)
+        }
+    }
+}
+
+// Print the path to the node, built from the values
+fn print_built_path(input: &Path, node: &str, set: Vec<(String, String)>, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let Template { graph, keymap, .. } = load(input, console)?;
+    let matcher = Matcher::new(&graph, &keymap)?;
+    let node = lookup(&keymap, node)?;
+    let values = set.into_iter().collect::<HashMap<_, _>>();
+    println!("{}", build_path(&matcher, node, &values)?);
+    Ok(0)
+}
+
+// Parse a VARNAME=VALUE argument
+fn parse_assignment(assignment: &str) -> Result<(String, String), String> {
+    match assignment.find('=') {
+        Some(index) if index > 0 => Ok((assignment[..index].to_string(), assignment[index + 1..].to_string())),
+        _ => Err(format!("expected VARNAME=VALUE, not {}", assignment)),
+    }
+}
+
+// Print example paths accepted by the template, optionally ending at a particular node
+fn samples(input: &Path, count: usize, node: Option<String>, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let Template { graph, keymap, .. } = load(input, console)?;
+    let matcher = Matcher::new(&graph, &keymap)?;
+    let target = match node {
+        Some(name) => Some(lookup(&keymap, &name)?),
+        None => None,
+    };
+    report_unsampled(&matcher, target, console)?;
+    for sample in sample_paths(&matcher, target, count) {
+        println!("{}", sample);
+    }
+    Ok(0)
+}
+
+// Warn about each node for which no name can be generated, as the paths through it are skipped
+fn report_unsampled(matcher: &Matcher, target: Option<NIndex>, console: &mut Console) -> Result<(), JSPTemplateError> {
+    for node in unsampled_nodes(matcher, target) {
+        console.lint(&Diagnostic::new(
+            Severity::Warning,
+            UNSAMPLED_NODE,
+            format!("no name can be generated for node `{}`; the paths through it are skipped", matcher.name(node))
+        ))?;
+    }
+    Ok(())
+}
+
+// Print the template as a tree, starting from root or the named node
+fn print_tree(input: &Path, depth: Option<usize>, from: Option<String>, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let Template { graph, keymap, .. } = load(input, console)?;
+    let matcher = Matcher::new(&graph, &keymap)?;
+    let from = match from {
+        Some(name) => lookup(&keymap, &name)?,
+        None => matcher.root(),
+    };
+    print!("{}", tree::render(&matcher, from, depth));
+    Ok(0)
+}
+
+// Print the jspt source of a compiled template
+fn decompile(compiled: &Path) -> Result<i32, JSPTemplateError> {
+    if !compiled.exists() {
+        return Err(JSPTemplateError::InaccesibleFileError(compiled.to_path_buf()));
+    }
+    let graph: JGraph = serde_json::from_reader(BufReader::new(File::open(compiled)?))
+        .map_err(|e| JSPTemplateError::CompiledTemplateError(compiled.to_path_buf(), e.to_string()))?;
+    print!("{}", decompile::decompile(&graph)?);
+    Ok(0)
+}
 
-            diskutils::write_template(output, &graph);
+// Print a draft template inferred from the directory, warning about each directory which could not be read
+fn infer(dir: &Path, depth: Option<usize>, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    if !dir.is_dir() {
+        return Err(JSPTemplateError::InaccesibleFileError(dir.to_path_buf()));
+    }
+    let draft = infer::infer(dir, depth)?;
+    for (unreadable, reason) in draft.unreadable() {
+        console.lint(&Diagnostic::new(
+            Severity::Warning,
+            UNREADABLE_DIRECTORY,
+            format!("could not read {}: {}; nothing beneath it is reflected in the draft", unreadable.display(), reason)
+        ))?;
+    }
+    print!("{}", draft.source());
+    Ok(0)
+}
+
+// Materialise the template beneath `into`, or print the plan for doing so
+fn mkdirs(input: &Path, into: &Path, dry_run: bool, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let Template { graph, keymap, .. } = load(input, console)?;
+    let matcher = Matcher::new(&graph, &keymap)?;
+    report_unsampled(&matcher, None, console)?;
+    let operations = sandbox::plan(&matcher, into);
+    if dry_run {
+        for operation in &operations {
+            println!("{}", operation);
+        }
+        return Ok(0);
+    }
+    for (operation, reason) in sandbox::apply(&operations, sandbox::is_root())? {
+        console.lint(&Diagnostic::new(
+            Severity::Warning, 
+            sandbox::SKIPPED_CHOWN, 
+            format!("skipped `{}`: {}", operation, reason)
+        ))?;
+    }
+    let created = operations.iter().filter(|op| match op { Operation::Mkdir(_) => true, _ => false }).count();
+    console.info(format!("created {} directories beneath {}", created, into.display()))?;
+    Ok(0)
+}
+
+// Audit the directory against the template, printing the report. Returns the exit code.
+fn audit_dir(input: &Path, dir: &Path, format: ReportFormat, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let Template { graph, keymap, autocreate, .. } = load(input, console)?;
+    let matcher = Matcher::new(&graph, &keymap)?;
+    if !dir.is_dir() {
+        return Err(JSPTemplateError::InaccesibleFileError(dir.to_path_buf()));
+    }
+    let report = audit::audit(&matcher, &autocreate, dir)?;
+    match format {
+        ReportFormat::Text => println!("{}", report),
+        ReportFormat::Json => println!("{}", report.to_json()),
+    }
+    Ok(if report.is_clean() { 0 } else { EXIT_ERRORS })
+}
+
+// Print the plan which brings the directory into conformance with the template and, 
+// if asked, apply it
+fn repair(input: &Path, dir: &Path, apply: bool, quarantine: Option<PathBuf>, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let Template { graph, keymap, autocreate, .. } = load(input, console)?;
+    let matcher = Matcher::new(&graph, &keymap)?;
+    if !dir.is_dir() {
+        return Err(JSPTemplateError::InaccesibleFileError(dir.to_path_buf()));
+    }
+    let report = audit::audit(&matcher, &autocreate, dir)?;
+    let operations = repair::plan(&matcher, &autocreate, &report, quarantine.as_ref().map(|q| q.as_path()));
+    if operations.is_empty() {
+        console.info(format!("{} needs no repair", dir.display()))?;
+        return Ok(0);
+    }
+    for operation in &operations {
+        println!("{}", operation);
+    }
+    if !apply {
+        return Ok(0);
+    }
+    for (operation, reason) in sandbox::apply(&operations, sandbox::is_root())? {
+        console.lint(&Diagnostic::new(
+            Severity::Warning, 
+            sandbox::SKIPPED_CHOWN, 
+            format!("skipped `{}`: {}", operation, reason)
+        ))?;
+    }
+    console.info(format!("applied {} operations to {}", operations.len(), dir.display()))?;
+    Ok(0)
+}
+
+// Print the changes between the two templates. Returns the exit code.
+fn diff(old: &Path, new: &Path, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let old = load(old, console)?;
+    let new = load(new, console)?;
+    let changes = diff::diff(
+        &Matcher::new(&old.graph, &old.keymap)?,
+        &old.autocreate,
+        &Matcher::new(&new.graph, &new.keymap)?,
+        &new.autocreate,
+    );
+    for change in &changes {
+        println!("{}", change);
+    }
+    Ok(if changes.is_empty() { 0 } else { EXIT_ERRORS })
+}
+
+// Print a counterexample for each way in which the new template rejects paths the old one 
+// accepted, along with why the new template rejects it. Returns the exit code.
+fn compat(old: &Path, new: &Path, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let old = load(old, console)?;
+    let new = load(new, console)?;
+    let new_matcher = Matcher::new(&new.graph, &new.keymap)?;
+    let compatibility = compat::compat(&Matcher::new(&old.graph, &old.keymap)?, &new_matcher);
+    for counterexample in compatibility.counterexamples() {
+        println!("{}", counterexample);
+        if let Some(failure) = new_matcher.match_path(counterexample.path()).failure() {
+            println!("    {}", failure.to_string().replace("\n", "\n    "));
         }
     }
+    for route in compatibility.undecided() {
+        console.info(format!("could not check {}, as its regexes are too complex", route))?;
+    }
+    Ok(if compatibility.is_compatible() { 0 } else { EXIT_ERRORS })
+}
+
+// Print the plan which migrates the directory from the old template to the new one, 
+// reporting the directories which could not be mapped. Returns the exit code.
+fn migrate(old: &Path, new: &Path, dir: &Path, format: ScriptFormat, console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let old = load(old, console)?;
+    let new = load(new, console)?;
+    if !dir.is_dir() {
+        return Err(JSPTemplateError::InaccesibleFileError(dir.to_path_buf()));
+    }
+    let migration = migrate::migrate(&Matcher::new(&old.graph, &old.keymap)?, &Matcher::new(&new.graph, &new.keymap)?, dir)?;
+    match format {
+        ScriptFormat::Shell => print!("{}", migration.to_script()),
+        ScriptFormat::Json => println!("{}", migration.to_json()),
+    }
+    for unmapped in migration.unmapped() {
+        console.info(format!("could not map {}", unmapped))?;
+    }
+    Ok(if migration.unmapped().is_empty() { 0 } else { EXIT_ERRORS })
+}
+
+// Print a regex for the examples, followed by how it treats each of them
+fn suggest_regex(accept: &[String], reject: &[String], console: &mut Console) -> Result<i32, JSPTemplateError> {
+    let accept = accept.iter().map(|name| &**name).collect::<Vec<_>>();
+    let reject = reject.iter().map(|name| &**name).collect::<Vec<_>>();
+    let suggestion = suggest::suggest_regex(&accept, &reject)?;
+    println!("{}", suggestion);
+    let width = accept.iter().chain(reject.iter()).map(|name| name.len()).max().unwrap_or(0);
+    for name in accept.iter().chain(reject.iter()) {
+        let verdict = match suggestion.check(name) {
+            Verdict::Accepted => "accepted",
+            Verdict::Unmatched => "rejected: does not match the positive regex",
+            Verdict::Excluded => "rejected: matches the negative regex",
+        };
+        console.info(format!("  {:width$}  {}", name, verdict, width = width))?;
+    }
+    Ok(0)
+}
+
+// Look up a node by name, suggesting the closest names if it does not exist
+fn lookup(keymap: &JGraphKeyMap, name: &str) -> Result<NIndex, JSPTemplateError> {
+    keymap.get(name).cloned().ok_or_else(|| 
+        JSPTemplateError::KeyMapLookupError(name.to_string(), Suggestions::from_candidates(name, keymap.keys()))
+    )
+}
+
+// How a compiled template is to be written
+#[derive(Debug, Clone, Copy)]
+struct Rendering {
+    format: OutputFormat,
+    full_regex: bool,
+}
+
+// write the template to the output in the requested format
+fn write_output(output: &mut PathBuf, template: &Template, rendering: Rendering) -> Result<(), JSPTemplateError> {
+    let matcher = Matcher::new(&template.graph, &template.keymap)?;
+    let text = match rendering.format {
+        OutputFormat::Template => {
+            diskutils::write_template(output, &template.graph);
+            return Ok(());
+        }
+        OutputFormat::Dot => {
+            diskutils::write_template_as_dotfile(output, &template.graph);
+            return Ok(());
+        }
+        OutputFormat::Json => Document::new(&matcher, &template.autocreate).to_json() + "\n",
+        OutputFormat::Yaml => Document::new(&matcher, &template.autocreate).to_yaml() + "\n",
+        OutputFormat::Mermaid => diagram::mermaid(&matcher, &template.autocreate, rendering.full_regex),
+        OutputFormat::PlantUml => diagram::plantuml(&matcher, &template.autocreate, rendering.full_regex),
+    };
+    std::fs::write(output, text)?;
     Ok(())
 }
 
-fn display_error(
-    error: JSPTemplateError
-) {
-    println!("");
-    println!("{}", "Error".red().bold());
-    println!("\n\t{}", error.to_string());
-    println!("");
-}
-
-fn display_formatted_error(
-    line_num: usize, 
-    line: &str, 
-    state: &State, 
-    error: Box<JSPTemplateError>
-) {
-    println!("");
-    let title = "Error Parsing File".red().bold();
-    let error_title = "Error".bright_red();
-    let line_num_title = "LineNo".bright_red();
-    let line_title = "Line".bright_red();
-    let state_title = "State".bright_red();
-    println!("{}\n\n\t{} {}\n\t{}   {}\n\t{}  {}\n\t{}  {}", 
-        title,
-        line_num_title,
-        line_num.to_string(),
-        line_title, 
-        line,
-        state_title,
-        state, 
-        error_title,        
-        error.to_string());
-
-    println!("")
+// Print the detailed explanation of a code, or an error if the code is unknown
+fn display_explanation(code: &str, console: &mut Console) {
+    match explain(code) {
+        Some(explanation) => println!("{}", explanation),
+        None => {
+            // if we cannot write to stderr, there is nobody left to tell
+            let _ = console.info(format!("no explanation exists for code: {}", code));
+            std::process::exit(1);
+        }
+    }
 }
 
 // Set up the Fern logger with colors.
@@ -136,7 +877,8 @@ fn setup_logger(level: log::LevelFilter) -> Result<(), fern::InitError> {
             ))
         })
         .level(level)
-        .chain(std::io::stdout())
+        // stdout is reserved for compiled output
+        .chain(std::io::stderr())
         .apply()?;
     Ok(())
 }
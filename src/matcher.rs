@@ -0,0 +1,385 @@
+//! Match concrete paths against a loaded template.
+//!
+//! The Matcher walks the JGraph from `root`, matching each component of a
+//! path against the children of the previously matched node. When a path does
+//! not match, the result records the component which failed, along with the
+//! reason that each candidate node rejected it, so that template authors can
+//! see exactly why.
+use crate::{JGraphKeyMap, JSPTemplateError, spelling::Suggestions};
+use jsp::{EntryType, JGraph, NIndex, Node, NodeType};
+use std::{collections::{HashMap, HashSet}, fmt};
+
+/// Why a node rejected a path component
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Rejection {
+    /// The node is a literal, and the component is not it
+    Literal { expected: String },
+    /// The component does not match the node's regex
+    Pattern { pattern: String },
+    /// The component matches the node's regex, but also its exclude regex
+    Excluded { pattern: String },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::Literal { expected } => write!(f, "expected the literal `{}`", expected),
+            Rejection::Pattern { pattern } => write!(f, "did not match the regex `{}`", pattern),
+            Rejection::Excluded { pattern } => write!(f, "matched the regex, but was rejected by the exclude regex `{}`", pattern),
+        }
+    }
+}
+
+/// A path component, along with the node that it matched
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ComponentMatch {
+    component: String,
+    node: NIndex,
+    name: String,
+}
+
+impl ComponentMatch {
+    /// Retrieve the path component
+    pub fn component(&self) -> &str {
+        &self.component
+    }
+
+    /// Retrieve the index of the matching node within the JGraph
+    pub fn node(&self) -> NIndex {
+        self.node
+    }
+
+    /// Retrieve the name of the matching node, as defined in the template
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// The first path component which could not be matched, and why.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ComponentFailure {
+    index: usize,
+    component: String,
+    parent: String,
+    rejections: Vec<(String, Rejection)>,
+}
+
+impl ComponentFailure {
+    /// Retrieve the zero based index of the component within the path
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Retrieve the component which failed to match
+    pub fn component(&self) -> &str {
+        &self.component
+    }
+
+    /// Retrieve the name of the node whose children were tried
+    pub fn parent(&self) -> &str {
+        &self.parent
+    }
+
+    /// Retrieve the names of the children which were tried, along with the reason
+    /// each rejected the component. This is empty if the parent has no children.
+    pub fn rejections(&self) -> &[(String, Rejection)] {
+        &self.rejections
+    }
+}
+
+impl fmt::Display for ComponentFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.rejections.is_empty() {
+            return write!(f, "`{}` (component {}) is beneath `{}`, which has no children", self.component, self.index + 1, self.parent);
+        }
+        write!(f, "`{}` (component {}) does not match any child of `{}`", self.component, self.index + 1, self.parent)?;
+        for (name, rejection) in &self.rejections {
+            write!(f, "\n    {}: {}", name, rejection)?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of matching a path against a template. The components which
+/// matched are recorded even if the path as a whole does not match.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PathMatch {
+    components: Vec<ComponentMatch>,
+    failure: Option<ComponentFailure>,
+}
+
+impl PathMatch {
+    /// Did the whole path match?
+    pub fn is_match(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    /// Retrieve the components which matched, in path order
+    pub fn components(&self) -> &[ComponentMatch] {
+        &self.components
+    }
+
+    /// Retrieve the reason the path failed to match, if it did
+    pub fn failure(&self) -> Option<&ComponentFailure> {
+        self.failure.as_ref()
+    }
+}
+
+/// Describe what a node matches, as `literal: REF` or `regex: [A-Z]+ exclude: (REF|SHARED)`
+pub fn describe_identity(identity: &NodeType) -> String {
+    match identity {
+        NodeType::Root => "root".to_string(),
+        NodeType::Simple(value) => format!("literal: {}", value),
+        NodeType::RegEx { pattern, exclude: None, .. } => format!("regex: {}", pattern.as_str()),
+        NodeType::RegEx { pattern, exclude: Some(exclude), .. } => format!("regex: {} exclude: {}", pattern.as_str(), exclude.as_str()),
+        NodeType::Untracked => "untracked".to_string(),
+    }
+}
+
+/// Describe a node's volume marker, owner and perms, as they would be written in
+/// the template, such as `[volume, owner: jobsys, perms: 751]`. Empty if it has none.
+pub fn describe_metadata(node: &Node) -> String {
+    let mut components = Vec::new();
+    if *node.entry_type() == EntryType::Volume {
+        components.push("volume".to_string());
+    }
+    if let Some(owner) = node.metadata().owner() {
+        components.push(format!("owner: {}", owner));
+    }
+    if let Some(perms) = node.metadata().perms() {
+        components.push(format!("perms: {}", perms));
+    }
+    if components.is_empty() {
+        String::new()
+    } else {
+        format!("[{}]", components.join(", "))
+    }
+}
+
+/// Matches paths against a loaded template
+pub struct Matcher<'a> {
+    graph: &'a JGraph,
+    root: NIndex,
+    names: HashMap<NIndex, &'a str>,
+}
+
+impl<'a> Matcher<'a> {
+    /// New up a Matcher, given the JGraph and JGraphKeyMap produced by the Loader.
+    ///
+    /// # Returns
+    /// A Result wrapping the Matcher if successful. Otherwise a JSPTemplateError,
+    /// if the keymap lacks a root.
+    pub fn new(graph: &'a JGraph, keymap: &'a JGraphKeyMap) -> Result<Self, JSPTemplateError> {
+        let root = *keymap.get("root").ok_or_else(||
+            JSPTemplateError::KeyMapLookupError("root".to_string(), Suggestions::default())
+        )?;
+        let names = keymap.iter().map(|(name, idx)| (*idx, name.as_str())).collect();
+        Ok(Self { graph, root, names })
+    }
+
+    /// Retrieve the JGraph being matched against
+    pub fn graph(&self) -> &'a JGraph {
+        self.graph
+    }
+
+    /// Retrieve the index of the root node
+    pub fn root(&self) -> NIndex {
+        self.root
+    }
+
+    /// Retrieve the name of a node, as defined in the template
+    pub fn name(&self, node: NIndex) -> &'a str {
+        self.names.get(&node).cloned().unwrap_or("?")
+    }
+
+    /// Retrieve the children of a node, each listed once, in the order in which the
+    /// children were defined in the template
+    pub fn children(&self, node: NIndex) -> Vec<NIndex> {
+        let mut children = self.graph.neighbors(node).collect::<Vec<_>>();
+        children.sort();
+        children.dedup();
+        children
+    }
+
+    /// Retrieve the nodes defined in the template, including root, in the order
+    /// in which they were defined. A node which was redefined is listed once, by
+    /// its last definition; the earlier definitions remain in the graph, but have
+    /// no name, and are left out.
+    pub fn nodes(&self) -> Vec<NIndex> {
+        self.graph.node_indices().filter(|node| self.names.contains_key(node)).collect()
+    }
+
+    /// Retrieve the edges of the graph as (parent, child) pairs, in the order in
+    /// which they were defined. An edge which was defined more than once is only
+    /// listed the first time.
+    pub fn edges(&self) -> Vec<(NIndex, NIndex)> {
+        let mut seen = HashSet::new();
+        self.graph.edge_indices()
+            .filter_map(|edge| self.graph.edge_endpoints(edge))
+            .filter(|endpoints| seen.insert(*endpoints))
+            .collect()
+    }
+
+    /// Determine whether a single path component matches a node
+    pub fn test(&self, node: NIndex, component: &str) -> Result<(), Rejection> {
+        match self.graph[node].identity() {
+            NodeType::Simple(value) if value == component => Ok(()),
+            NodeType::Simple(value) => Err(Rejection::Literal { expected: value.clone() }),
+            NodeType::RegEx { pattern, exclude, .. } => {
+                if !pattern.is_match(component) {
+                    return Err(Rejection::Pattern { pattern: pattern.as_str().to_string() });
+                }
+                match exclude {
+                    Some(exclude) if exclude.is_match(component) => Err(Rejection::Excluded { pattern: exclude.as_str().to_string() }),
+                    _ => Ok(()),
+                }
+            }
+            NodeType::Root | NodeType::Untracked => Ok(()),
+        }
+    }
+
+    /// Match a path against the template. The path is taken to be absolute, so
+    /// that its first component is matched against the children of root.
+    ///
+    /// # Parameters
+    ///
+    /// * `path` - a path such as `/dd/shows/FOO`
+    ///
+    /// # Returns
+    /// A PathMatch describing the nodes which matched and, if the whole path did
+    /// not, which component failed and why
+    pub fn match_path(&self, path: &str) -> PathMatch {
+        let components = path.split('/').filter(|c| !c.is_empty()).collect::<Vec<_>>();
+        let mut matched = Vec::with_capacity(components.len());
+        let failure = self.walk(self.root, &components, &mut matched).err();
+        PathMatch { components: matched, failure }
+    }
+
+    // match the components beneath `node`, backtracking when more than one child
+    // matches and the first leads nowhere. We report the failure which got furthest
+    // into the path, as that is the most informative.
+    fn walk(&self, node: NIndex, components: &[&str], matched: &mut Vec<ComponentMatch>) -> Result<(), ComponentFailure> {
+        let component = match components.first() {
+            Some(component) => *component,
+            None => return Ok(()),
+        };
+        let mut rejections = Vec::new();
+        let mut deepest: Option<(ComponentFailure, Vec<ComponentMatch>)> = None;
+        for child in self.children(node) {
+            if let Err(rejection) = self.test(child, component) {
+                rejections.push((self.name(child).to_string(), rejection));
+                continue;
+            }
+            let depth = matched.len();
+            matched.push(ComponentMatch { component: component.to_string(), node: child, name: self.name(child).to_string() });
+            match self.walk(child, &components[1..], matched) {
+                Ok(()) => return Ok(()),
+                Err(failure) => {
+                    if deepest.as_ref().map_or(true, |(deepest, _)| failure.index > deepest.index) {
+                        deepest = Some((failure, matched[depth..].to_vec()));
+                    }
+                    matched.truncate(depth);
+                }
+            }
+        }
+        match deepest {
+            Some((failure, partial)) => {
+                matched.extend(partial);
+                Err(failure)
+            }
+            None => Err(ComponentFailure {
+                index: matched.len(),
+                component: component.to_string(),
+                parent: self.name(node).to_string(),
+                rejections,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_template;
+
+    fn names(path_match: &PathMatch) -> Vec<&str> {
+        path_match.components().iter().map(|c| c.name()).collect()
+    }
+
+    #[test]
+    fn matches_valid_path() {
+        let (graph, keymap) = load_template(include_str!("../examples/jsptemplate.jspt"));
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let result = matcher.match_path("/dd/shows/FOO/AA/0100");
+        assert!(result.is_match());
+        assert_eq!(names(&result), vec!["dd", "shows", "show", "sequence", "shot"]);
+        assert_eq!(result.components()[2].component(), "FOO");
+    }
+
+    #[test]
+    fn explains_excluded_component() {
+        let (graph, keymap) = load_template(include_str!("../examples/jsptemplate.jspt"));
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let result = matcher.match_path("/dd/shows/SHARED");
+        let failure = result.failure().unwrap();
+        assert_eq!(failure.index(), 2);
+        assert_eq!(failure.parent(), "shows");
+        match &failure.rejections()[0] {
+            (name, Rejection::Excluded { .. }) => assert_eq!(name, "show"),
+            other => panic!("expected Excluded. got {:?}", other),
+        }
+        assert_eq!(names(&result), vec!["dd", "shows"]);
+    }
+
+    #[test]
+    fn explains_literal_and_pattern_failures() {
+        let (graph, keymap) = load_template(include_str!("../examples/jsptemplate.jspt"));
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let failure = matcher.match_path("/dd/show").failure().cloned().unwrap();
+        assert_eq!(failure.rejections(), &[("shows".to_string(), Rejection::Literal { expected: "shows".to_string() })]);
+
+        let failure = matcher.match_path("/dd/shows/foo").failure().cloned().unwrap();
+        match &failure.rejections()[0] {
+            (name, Rejection::Pattern { .. }) => assert_eq!(name, "show"),
+            other => panic!("expected Pattern. got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn explains_path_beyond_leaf() {
+        let (graph, keymap) = load_template("[regex]\n[nodes]\ndd\n[graph]\nroot -> dd\n");
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let failure = matcher.match_path("/dd/extra").failure().cloned().unwrap();
+        assert!(failure.rejections().is_empty());
+        assert_eq!(failure.to_string(), "`extra` (component 2) is beneath `dd`, which has no children");
+    }
+
+    #[test]
+    fn backtracks_to_the_child_which_leads_somewhere() {
+        let template = "[regex]\n[nodes]\nany = \"[a-z]+\"\nfoo\nbar\n[graph]\nroot -> any\nroot -> foo -> bar\n";
+        let (graph, keymap) = load_template(template);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let result = matcher.match_path("/foo/bar");
+        assert!(result.is_match());
+        assert_eq!(names(&result), vec!["foo", "bar"]);
+    }
+
+    #[test]
+    fn lists_only_the_last_definition_of_a_node() {
+        let (graph, keymap) = load_template("[regex]\n[nodes]\ndd\nshows\ndd = DD\n[graph]\nroot -> dd -> shows\n");
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        assert_eq!(graph.node_count(), 4);
+        assert_eq!(matcher.nodes(), vec![keymap["root"], keymap["shows"], keymap["dd"]]);
+    }
+
+    #[test]
+    fn lists_each_edge_once_in_definition_order() {
+        let template = "[regex]\n[nodes]\ndd\nshows\nref = REF\n[graph]\nroot -> dd -> shows\ndd -> ref\nroot -> dd\n";
+        let (graph, keymap) = load_template(template);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let edges = matcher.edges().into_iter()
+            .map(|(from, to)| (matcher.name(from), matcher.name(to)))
+            .collect::<Vec<_>>();
+        assert_eq!(edges, vec![("root", "dd"), ("dd", "shows"), ("dd", "ref")]);
+    }
+}
@@ -0,0 +1,353 @@
+//! Plan the moves which migrate an existing directory tree from one version of a
+//! template to another.
+//!
+//! The tree is walked with the old template, so that each directory is known by
+//! its route of node names from `root`. Each route is then mapped onto the new
+//! template: to the same route if it still exists, or else to the only route
+//! which ends at a node of the same name. The new path takes each component
+//! from the old path by node name, using a literal node's new value, and keeping
+//! a regex node's value provided that the new regex accepts it. Literal nodes
+//! which are new to the route are created. Anything else is flagged rather than
+//! guessed at.
+use crate::{Matcher, samples::node_paths, sandbox::Operation};
+use jsp::{NIndex, NodeType};
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The form in which a migration is written
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ScriptFormat {
+    /// A shell script
+    Shell,
+    /// A single json document
+    Json,
+}
+
+impl FromStr for ScriptFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sh" => Ok(ScriptFormat::Shell),
+            "json" => Ok(ScriptFormat::Json),
+            _ => Err(format!("invalid script format: {}. Expected one of sh, json", s)),
+        }
+    }
+}
+
+/// A directory which could not be mapped onto the new template
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Unmapped {
+    path: PathBuf,
+    route: Option<String>,
+    reason: String,
+}
+
+impl Unmapped {
+    /// Retrieve the path of the directory
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Retrieve the route through the old template which the directory matched,
+    /// if it matched one
+    pub fn route(&self) -> Option<&str> {
+        self.route.as_ref().map(|x| &**x)
+    }
+
+    /// Retrieve the reason the directory could not be mapped
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for Unmapped {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.route {
+            Some(route) => write!(f, "{} ({}): {}", self.path.display(), route, self.reason),
+            None => write!(f, "{}: {}", self.path.display(), self.reason),
+        }
+    }
+}
+
+/// The operations which migrate a tree, along with the directories which could
+/// not be mapped
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Migration {
+    operations: Vec<Operation>,
+    unmapped: Vec<Unmapped>,
+}
+
+impl Migration {
+    /// Retrieve the mkdir and mv operations, in the order in which they must be applied
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Retrieve the directories which could not be mapped. These are left where
+    /// they are, or travel with their parent if it moves.
+    pub fn unmapped(&self) -> &[Unmapped] {
+        &self.unmapped
+    }
+
+    /// Render the migration as a shell script. Unmapped directories are listed
+    /// as comments.
+    pub fn to_script(&self) -> String {
+        let mut script = String::from("#!/bin/sh\nset -e\n");
+        for operation in &self.operations {
+            match operation {
+                Operation::Mkdir(path) => script.push_str(&format!("mkdir {}\n", quote(path))),
+                Operation::Move(from, to) => script.push_str(&format!("mv {} {}\n", quote(from), quote(to))),
+                other => script.push_str(&format!("# {}\n", other)),
+            }
+        }
+        for unmapped in &self.unmapped {
+            script.push_str(&format!("# unmapped: {}\n", unmapped));
+        }
+        script
+    }
+
+    /// Render the migration as a json document
+    pub fn to_json(&self) -> String {
+        let operations = self.operations.iter().map(|operation| match operation {
+            Operation::Mkdir(path) => json!({ "op": "mkdir", "path": path }),
+            Operation::Move(from, to) => json!({ "op": "mv", "from": from, "to": to }),
+            other => json!({ "op": other.to_string() }),
+        }).collect::<Vec<_>>();
+        let unmapped = self.unmapped.iter().map(|unmapped| json!({
+            "path": unmapped.path,
+            "route": unmapped.route,
+            "reason": unmapped.reason,
+        })).collect::<Vec<_>>();
+        serde_json::to_string_pretty(&json!({ "operations": operations, "unmapped": unmapped }))
+            .expect("Migration serializes to json")
+    }
+}
+
+// quote a path for the shell
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.display().to_string().replace('\'', "'\\''"))
+}
+
+/// Plan the migration of the tree beneath `dir`, which is taken to correspond to
+/// `root`, from the old template to the new one. Files are not considered, as
+/// they travel with their directory.
+///
+/// # Parameters
+///
+/// * `old` - a Matcher over the old template
+/// * `new` - a Matcher over the new template
+/// * `dir` - the directory to migrate
+///
+/// # Returns
+/// A Result wrapping the Migration if successful. Otherwise the io::Error
+/// encountered reading the tree.
+pub fn migrate(old: &Matcher, new: &Matcher, dir: &Path) -> io::Result<Migration> {
+    let mut routes = HashMap::new();
+    let mut by_name: HashMap<&str, Vec<Vec<NIndex>>> = HashMap::new();
+    for route in node_paths(new, None) {
+        let node = *route.last().expect("routes are never empty");
+        routes.insert(route_name(new, &route), route.clone());
+        by_name.entry(new.name(node)).or_default().push(route);
+    }
+    let mut migrator = Migrator { old, new, dir, routes, by_name, moves: Vec::new(), created: HashSet::new(), migration: Migration::default() };
+    migrator.visit(old.root(), &mut Vec::new(), dir, dir)?;
+    Ok(migrator.migration)
+}
+
+// the names of the nodes along a route, as /dd/shows/show
+fn route_name(matcher: &Matcher, route: &[NIndex]) -> String {
+    route.iter().fold(String::new(), |name, node| format!("{}/{}", name, matcher.name(*node)))
+}
+
+// state for the walk
+struct Migrator<'a, 'b> {
+    old: &'a Matcher<'b>,
+    new: &'a Matcher<'b>,
+    dir: &'a Path,
+    routes: HashMap<String, Vec<NIndex>>,
+    by_name: HashMap<&'b str, Vec<Vec<NIndex>>>,
+    // the moves planned so far, and the directories they create
+    moves: Vec<(PathBuf, PathBuf)>,
+    created: HashSet<PathBuf>,
+    migration: Migration,
+}
+
+impl<'a, 'b> Migrator<'a, 'b> {
+    // visit the directories within `path`, which matched the last node of `route` and
+    // which will be found at `current` once its ancestors have moved
+    fn visit(&mut self, node: NIndex, route: &mut Vec<(NIndex, String)>, path: &Path, current: &Path) -> io::Result<()> {
+        let mut contents = fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+        contents.sort_by_key(|entry| entry.file_name());
+        let children = self.old.children(node);
+        for entry in contents {
+            if !fs::symlink_metadata(entry.path())?.is_dir() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let child = match children.iter().find(|child| self.old.test(**child, &name).is_ok()) {
+                Some(child) => *child,
+                None => {
+                    self.flag(entry.path(), None, "matches no node in the old template".to_string());
+                    continue;
+                }
+            };
+            route.push((child, name.clone()));
+            let from = current.join(&name);
+            let names = route.iter().fold(String::new(), |names, (node, _)| format!("{}/{}", names, self.old.name(*node)));
+            let to = match self.map(route) {
+                Ok(to) => to,
+                Err(reason) => {
+                    self.flag(entry.path(), Some(names.clone()), reason);
+                    from.clone()
+                }
+            };
+            if from != to {
+                if self.exists(&to) {
+                    self.flag(entry.path(), Some(names.clone()), format!("{} already exists", to.display()));
+                    self.visit(child, route, &entry.path(), &from)?;
+                    route.pop();
+                    continue;
+                }
+                self.mkdirs(to.parent().expect("destinations are beneath the root"));
+                self.migration.operations.push(Operation::Move(from.clone(), to.clone()));
+                self.moves.push((from, to.clone()));
+            }
+            self.visit(child, route, &entry.path(), &to)?;
+            route.pop();
+        }
+        Ok(())
+    }
+
+    // map the route through the old template, along with the names which matched it,
+    // onto a path for the new template
+    fn map(&self, route: &[(NIndex, String)]) -> Result<PathBuf, String> {
+        let nodes = route.iter().map(|(node, _)| *node).collect::<Vec<_>>();
+        let last = self.old.name(*nodes.last().expect("routes are never empty"));
+        let target = match self.routes.get(&route_name(self.old, &nodes)) {
+            Some(target) => target,
+            None => match self.by_name.get(last).map(|routes| routes.as_slice()) {
+                Some([target]) => target,
+                Some(_) => return Err(format!("`{}` appears at more than one place in the new template", last)),
+                None => return Err(format!("`{}` does not exist in the new template", last)),
+            },
+        };
+        let mut path = self.dir.to_path_buf();
+        for node in target {
+            let name = self.new.name(*node);
+            let old_value = route.iter().rev().find(|(old, _)| self.old.name(*old) == name).map(|(_, value)| value);
+            let value = match (self.new.graph()[*node].identity(), old_value) {
+                (NodeType::Simple(value), _) => value.clone(),
+                (_, Some(value)) if self.new.test(*node, value).is_ok() => value.clone(),
+                (_, Some(value)) => return Err(format!("`{}` is not accepted by `{}` in the new template", value, name)),
+                (_, None) => return Err(format!("there is no value for `{}` in the old path", name)),
+            };
+            path.push(value);
+        }
+        // the new path must match the node we mapped to, rather than one of its siblings
+        let relative = path.strip_prefix(self.dir).expect("path is beneath the root").to_string_lossy().into_owned();
+        let result = self.new.match_path(&relative);
+        match (result.is_match(), result.components().last()) {
+            (true, Some(component)) if Some(&component.node()) == target.last() => Ok(path),
+            (true, Some(component)) => Err(format!("{} would match `{}` rather than `{}` in the new template", relative, component.name(), last)),
+            _ => Err(format!("{} does not match the new template", relative)),
+        }
+    }
+
+    // will the directory exist once the moves planned so far have been made?
+    fn exists(&self, path: &Path) -> bool {
+        if self.created.contains(path) {
+            return true;
+        }
+        for (from, to) in self.moves.iter().rev() {
+            if let Ok(rest) = path.strip_prefix(to) {
+                return from.join(rest).exists();
+            }
+            if path.starts_with(from) {
+                return false;
+            }
+        }
+        path.exists()
+    }
+
+    // plan the creation of the directory and any missing parents, parents first
+    fn mkdirs(&mut self, path: &Path) {
+        if path == self.dir || self.exists(path) {
+            return;
+        }
+        if let Some(parent) = path.parent() {
+            self.mkdirs(parent);
+        }
+        self.created.insert(path.to_path_buf());
+        self.migration.operations.push(Operation::Mkdir(path.to_path_buf()));
+    }
+
+    fn flag(&mut self, path: PathBuf, route: Option<String>, reason: String) {
+        self.migration.unmapped.push(Unmapped { path, route, reason });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_template;
+
+    const OLD: &str = "[regex]\n[nodes]\ndd\nshows\nref = REF\nquicktimes\nshow = \"[A-Z]+\"\nwork\n[graph]\nroot -> dd -> shows -> show -> work\ndd -> ref -> quicktimes\n";
+    const NEW: &str = "[regex]\n[nodes]\ndd\nshows\nref = REFERENCE\nquicktimes\nshow = \"[A-Z]{2,}\"\nprod\nwork\n[graph]\nroot -> dd -> shows -> show -> prod -> work\ndd -> ref -> quicktimes\n";
+
+    fn plan(setup: &[&str]) -> (Migration, PathBuf, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        for path in setup {
+            fs::create_dir_all(dir.path().join(path)).unwrap();
+        }
+        let (old_graph, old_keymap) = load_template(OLD);
+        let (new_graph, new_keymap) = load_template(NEW);
+        let old = Matcher::new(&old_graph, &old_keymap).unwrap();
+        let new = Matcher::new(&new_graph, &new_keymap).unwrap();
+        let migration = migrate(&old, &new, dir.path()).unwrap();
+        let root = dir.path().to_path_buf();
+        (migration, root, dir)
+    }
+
+    #[test]
+    fn renames_literals_and_moves_subtrees() {
+        let (migration, root, _dir) = plan(&["dd/REF/quicktimes", "dd/shows/FOO/work", "dd/shows/FOO/junk"]);
+        assert_eq!(
+            migration.operations(),
+            &[
+                Operation::Move(root.join("dd/REF"), root.join("dd/REFERENCE")),
+                Operation::Mkdir(root.join("dd/shows/FOO/prod")),
+                Operation::Move(root.join("dd/shows/FOO/work"), root.join("dd/shows/FOO/prod/work")),
+            ]
+        );
+        assert_eq!(migration.unmapped().len(), 1);
+        assert_eq!(migration.unmapped()[0].path(), root.join("dd/shows/FOO/junk").as_path());
+        assert!(migration.to_script().contains(&format!("mv '{}' '{}'\n", root.join("dd/REF").display(), root.join("dd/REFERENCE").display())));
+    }
+
+    #[test]
+    fn flags_rather_than_guesses() {
+        let (migration, root, _dir) = plan(&["dd/shows/A/work"]);
+        assert!(migration.operations().is_empty());
+        let unmapped = migration.unmapped().iter().map(|u| (u.path().strip_prefix(&root).unwrap().to_path_buf(), u.reason().to_string())).collect::<Vec<_>>();
+        assert_eq!(
+            unmapped,
+            vec![
+                (PathBuf::from("dd/shows/A"), "`A` is not accepted by `show` in the new template".to_string()),
+                (PathBuf::from("dd/shows/A/work"), "`A` is not accepted by `show` in the new template".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn does_not_move_onto_existing_directories() {
+        let (migration, root, _dir) = plan(&["dd/REF", "dd/REFERENCE"]);
+        assert!(migration.operations().is_empty());
+        assert_eq!(migration.unmapped()[0].reason(), format!("{} already exists", root.join("dd/REFERENCE").display()));
+    }
+}
@@ -6,11 +6,11 @@ use nom::{
     combinator::{all_consuming },
 };
 
-use crate::{ components::ParseResult};
+use crate::{ components::ParseResult, JSPTemplateError};
 
 
 pub mod header;
-pub use header::{parse_section_header};
+pub use header::{parse_section_header, HEADER_NAMES};
 
 pub mod regex;
 pub use regex::{parse_regex};
@@ -30,6 +30,9 @@ pub use empty::parse_empty;
 pub mod metadata;
 pub use metadata::{parse_metadata, parse_components};
 
+pub mod diagnose;
+pub use diagnose::{diagnose_start, diagnose_regex, diagnose_node, diagnose_edge};
+
 // Parse the input &str and apply a succession of parsers corresponding with, 
 // the states of the parser, returning a ParseResult from the first successful 
 // one, or an Nom Errror if unsuccessful.
@@ -46,8 +49,9 @@ fn parse_str(input: &str) -> IResult<&str, ParseResult> {
     )(input)
 }
 
-/// Parser which parses the start state.
-pub fn start_parser(input: &str) -> IResult<&str, ParseResult> {
+/// Parser which parses the start state. If the line fails to parse, 
+/// the returned ParsingError describes the mistake.
+pub fn start_parser(input: &str) -> Result<ParseResult, JSPTemplateError> {
     all_consuming(
         alt((
             parse_comment,
@@ -55,10 +59,13 @@ pub fn start_parser(input: &str) -> IResult<&str, ParseResult> {
             parse_empty,
         ))
     )(input)
+    .map(|(_, result)| result)
+    .map_err(|_| JSPTemplateError::from(diagnose_start(input)))
 }
 
-/// Parser which parses a Regex in the regex state.
-pub fn regex_parser(input: &str) -> IResult<&str, ParseResult> {
+/// Parser which parses a Regex in the regex state. If the line fails to parse, 
+/// the returned ParsingError describes the mistake.
+pub fn regex_parser(input: &str) -> Result<ParseResult, JSPTemplateError> {
     all_consuming(
         alt((
             parse_comment,
@@ -67,10 +74,13 @@ pub fn regex_parser(input: &str) -> IResult<&str, ParseResult> {
             parse_empty,
         ))
     )(input)
+    .map(|(_, result)| result)
+    .map_err(|_| JSPTemplateError::from(diagnose_regex(input)))
 }
 
-/// Parser which parses a node in the node state.
-pub fn node_parser(input: &str) -> IResult<&str, ParseResult> {
+/// Parser which parses a node in the node state. If the line fails to parse, 
+/// the returned ParsingError describes the mistake.
+pub fn node_parser(input: &str) -> Result<ParseResult, JSPTemplateError> {
     all_consuming(
         alt((
             parse_comment,
@@ -79,10 +89,13 @@ pub fn node_parser(input: &str) -> IResult<&str, ParseResult> {
             parse_empty,
         ))
     )(input)
+    .map(|(_, result)| result)
+    .map_err(|_| JSPTemplateError::from(diagnose_node(input)))
 }
 
-/// Parser which parses an edge in the edge state.
-pub fn edge_parser(input: &str) -> IResult<&str, ParseResult> {
+/// Parser which parses an edge in the edge state. If the line fails to parse, 
+/// the returned ParsingError describes the mistake.
+pub fn edge_parser(input: &str) -> Result<ParseResult, JSPTemplateError> {
     all_consuming(
         alt((
             parse_edges,
@@ -90,6 +103,8 @@ pub fn edge_parser(input: &str) -> IResult<&str, ParseResult> {
             parse_empty,
         ))
     )(input)
+    .map(|(_, result)| result)
+    .map_err(|_| JSPTemplateError::from(diagnose_edge(input)))
 }
 
 /// Given an input &Str, apply parse_str and then match against the results, 
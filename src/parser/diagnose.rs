@@ -0,0 +1,511 @@
+//! Diagnose lines which fail to parse.
+//!
+//! The nom parsers tell us that a line failed to parse, but not why, at least
+//! not in terms that a template author would understand. When a state's parser
+//! fails, we take a second, more forgiving pass over the line in order to
+//! identify the mistake and where on the line it occurs.
+use crate::{ParseError, ParseErrorKind, helpers::{is_ident_char, is_regex_char, is_perm_char}, spelling::Suggestions};
+
+// The keys which may appear within a node's metadata
+const METADATA_KEYS: &[&str] = &["volume", "autocreate", "owner", "perms", "varname"];
+
+/// Diagnose a line which failed to parse in the Start state.
+pub fn diagnose_start(input: &str) -> ParseError {
+    let mut scanner = Scanner::new(input);
+    scanner.skip_space();
+    if scanner.peek() == Some('[') {
+        if let Err(e) = scanner.header() {
+            return e;
+        }
+        return unexpected(input);
+    }
+    ParseError::new(ParseErrorKind::ExpectedHeader, scanner.pos, trimmed_end(input))
+}
+
+/// Diagnose a line which failed to parse in the RegexParsing state.
+pub fn diagnose_regex(input: &str) -> ParseError {
+    let mut scanner = Scanner::new(input);
+    scanner.skip_space();
+    if scanner.peek() == Some('[') {
+        if let Err(e) = scanner.header() {
+            return e;
+        }
+        return unexpected(input);
+    }
+    if let Some(idx) = input.find("->") {
+        return ParseError::new(ParseErrorKind::MisplacedEdge("regex".to_string()), idx, idx + 2);
+    }
+    scanner.regex_definition().err().unwrap_or_else(|| unexpected(input))
+}
+
+/// Diagnose a line which failed to parse in the NodeParsing state.
+pub fn diagnose_node(input: &str) -> ParseError {
+    let mut scanner = Scanner::new(input);
+    scanner.skip_space();
+    if scanner.peek() == Some('[') {
+        if let Err(e) = scanner.header() {
+            return e;
+        }
+        return unexpected(input);
+    }
+    if let Some(idx) = input.find("->") {
+        return ParseError::new(ParseErrorKind::MisplacedEdge("nodes".to_string()), idx, idx + 2);
+    }
+    scanner.node_definition().err().unwrap_or_else(|| unexpected(input))
+}
+
+/// Diagnose a line which failed to parse in the EdgeParsing state.
+pub fn diagnose_edge(input: &str) -> ParseError {
+    let mut scanner = Scanner::new(input);
+    scanner.skip_space();
+    if scanner.peek() == Some('[') {
+        let start = scanner.pos;
+        return match scanner.header() {
+            Ok(name) => ParseError::new(ParseErrorKind::HeaderAfterGraph(name.to_string()), start, scanner.pos),
+            Err(e) => e,
+        };
+    }
+    if !input.contains("->") {
+        if input.contains('=') {
+            return ParseError::new(ParseErrorKind::MisplacedDefinition, scanner.pos, trimmed_end(input));
+        }
+        let (start, name) = scanner.token();
+        if !name.is_empty() && is_ident(name) {
+            return ParseError::new(ParseErrorKind::ExpectedArrow(name.to_string()), start, scanner.pos);
+        }
+    }
+    let mut offset = 0;
+    for segment in input.split("->") {
+        let start = offset + leading_space(segment);
+        let name = segment.trim();
+        if name.is_empty() {
+            // point at the arrow adjacent to the missing name
+            let arrow = if offset == 0 { segment.len() } else { offset - 2 };
+            return ParseError::new(ParseErrorKind::DanglingArrow, arrow, arrow + 2);
+        }
+        if !is_ident(name) {
+            return ParseError::new(ParseErrorKind::InvalidName(name.to_string()), start, start + name.len());
+        }
+        offset += segment.len() + 2;
+    }
+    unexpected(input)
+}
+
+// A cursor over the line being diagnosed
+struct Scanner<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { input, pos: 0 }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) {
+        if let Some(c) = self.peek() {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() { break; }
+            self.bump();
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.rest().trim().is_empty()
+    }
+
+    // take a run of characters up to whitespace or a delimiter, returning its
+    // starting offset along with the run itself
+    fn token(&mut self) -> (usize, &'a str) {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || ['=', '[', ']', '"', ',', ':'].contains(&c) { break; }
+            self.bump();
+        }
+        (start, &self.input[start..self.pos])
+    }
+
+    // take a name, making sure that it is a valid identifier
+    fn name(&mut self) -> Result<&'a str, ParseError> {
+        let (start, name) = self.token();
+        if name.is_empty() {
+            return Err(ParseError::new(ParseErrorKind::Unexpected(self.rest().trim().to_string()), start, trimmed_end(self.input)));
+        }
+        if !is_ident(name) {
+            return Err(ParseError::new(ParseErrorKind::InvalidName(name.to_string()), start, self.pos));
+        }
+        Ok(name)
+    }
+
+    // [regex]
+    fn header(&mut self) -> Result<&'a str, ParseError> {
+        let start = self.pos;
+        let close = match self.rest().find(']') {
+            Some(idx) => self.pos + idx,
+            None => return Err(ParseError::new(ParseErrorKind::UnterminatedHeader, start, trimmed_end(self.input))),
+        };
+        let name = self.input[start + 1..close].trim();
+        if name.is_empty() || !is_ident(name) {
+            return Err(ParseError::new(ParseErrorKind::InvalidName(name.to_string()), start + 1, close));
+        }
+        self.pos = close + 1;
+        self.trailing()?;
+        Ok(name)
+    }
+
+    // anything left on the line is unexpected
+    fn trailing(&mut self) -> Result<(), ParseError> {
+        self.skip_space();
+        if self.at_end() {
+            return Ok(());
+        }
+        Err(ParseError::new(ParseErrorKind::Unexpected(self.rest().trim().to_string()), self.pos, trimmed_end(self.input)))
+    }
+
+    // "[a-z]+"
+    fn quoted(&mut self) -> Result<(), ParseError> {
+        let start = self.pos;
+        self.bump();
+        let close = match self.rest().find('"') {
+            Some(idx) => self.pos + idx,
+            None => return Err(ParseError::new(ParseErrorKind::UnterminatedQuote, start, trimmed_end(self.input))),
+        };
+        if close == self.pos {
+            return Err(ParseError::new(ParseErrorKind::Unexpected("\"\"".to_string()), start, close + 1));
+        }
+        for (idx, c) in self.input[self.pos..close].char_indices() {
+            if !is_regex_char(c) {
+                let at = self.pos + idx;
+                return Err(ParseError::new(ParseErrorKind::InvalidRegexCharacter(c), at, at + c.len_utf8()));
+            }
+        }
+        self.pos = close + 1;
+        Ok(())
+    }
+
+    // name = "pos" "neg"
+    fn regex_definition(&mut self) -> Result<(), ParseError> {
+        let name = self.name()?;
+        self.skip_space();
+        if self.peek() != Some('=') {
+            let (start, token) = self.token();
+            let end = if token.is_empty() { trimmed_end(self.input) } else { self.pos };
+            return Err(ParseError::new(ParseErrorKind::ExpectedRegexEquals(name.to_string()), start, end.max(start)));
+        }
+        self.bump();
+        self.skip_space();
+        if self.at_end() {
+            return Err(ParseError::new(ParseErrorKind::MissingValue(name.to_string()), self.pos, self.pos));
+        }
+        if self.peek() != Some('"') {
+            return Err(ParseError::new(ParseErrorKind::ExpectedQuotedRegex(name.to_string()), self.pos, trimmed_end(self.input)));
+        }
+        self.quoted()?;
+        self.skip_space();
+        if self.peek() == Some('"') {
+            self.quoted()?;
+        }
+        self.trailing()
+    }
+
+    // name = value [metadata]
+    fn node_definition(&mut self) -> Result<(), ParseError> {
+        let name = self.name()?;
+        self.skip_space();
+        if self.peek() == Some('[') {
+            return self.metadata();
+        }
+        if self.peek() != Some('=') {
+            let (start, token) = self.token();
+            let end = if token.is_empty() { trimmed_end(self.input) } else { self.pos };
+            return Err(ParseError::new(ParseErrorKind::ExpectedNodeEquals(name.to_string()), start, end.max(start)));
+        }
+        self.bump();
+        self.skip_space();
+        match self.peek() {
+            None | Some('[') => {
+                return Err(ParseError::new(ParseErrorKind::MissingValue(name.to_string()), self.pos, self.pos));
+            }
+            Some('"') => {
+                self.quoted()?;
+                self.skip_space();
+                if self.peek() == Some('"') {
+                    self.quoted()?;
+                }
+            }
+            Some('$') => {
+                let start = self.pos;
+                self.bump();
+                let (_, variable) = self.token();
+                if variable.is_empty() {
+                    return Err(ParseError::new(ParseErrorKind::MissingRegexName, start, start + 1));
+                }
+                if !is_ident(variable) {
+                    return Err(ParseError::new(ParseErrorKind::InvalidName(variable.to_string()), start + 1, self.pos));
+                }
+            }
+            Some(_) => {
+                self.name()?;
+            }
+        }
+        self.skip_space();
+        if self.peek() == Some('[') {
+            return self.metadata();
+        }
+        self.trailing()
+    }
+
+    // [ volume, owner: jobsys, perms: 751, varname: DD_SHOW ]
+    fn metadata(&mut self) -> Result<(), ParseError> {
+        let start = self.pos;
+        let close = match self.rest().find(']') {
+            Some(idx) => self.pos + idx,
+            None => return Err(ParseError::new(ParseErrorKind::UnterminatedMetadata, start, trimmed_end(self.input))),
+        };
+        let mut offset = start + 1;
+        for entry in self.input[start + 1..close].split(',') {
+            let entry_start = offset + leading_space(entry);
+            offset += entry.len() + 1;
+            let trimmed = entry.trim();
+            if trimmed.is_empty() {
+                return Err(ParseError::new(ParseErrorKind::EmptyMetadataEntry, entry_start, entry_start));
+            }
+            let (key, value) = match trimmed.find(':') {
+                Some(idx) => (trimmed[..idx].trim(), Some(trimmed[idx + 1..].trim())),
+                None => (trimmed, None),
+            };
+            let span = (entry_start, entry_start + trimmed.len());
+            if !METADATA_KEYS.contains(&key) {
+                return Err(ParseError::new(
+                    ParseErrorKind::UnknownMetadataKey(key.to_string(), Suggestions::from_candidates(key, METADATA_KEYS)),
+                    entry_start,
+                    entry_start + key.len()
+                ));
+            }
+            let value = match (key, value) {
+                ("volume", None) | ("autocreate", None) => continue,
+                ("volume", Some(_)) | ("autocreate", Some(_)) => {
+                    return Err(ParseError::new(ParseErrorKind::UnexpectedMetadataValue(key.to_string()), span.0, span.1));
+                }
+                (_, None) | (_, Some("")) => {
+                    return Err(ParseError::new(ParseErrorKind::MissingMetadataValue(key.to_string()), span.0, span.1));
+                }
+                (_, Some(value)) => value,
+            };
+            let valid = match key {
+                "perms" => value.chars().all(is_perm_char),
+                "owner" => is_ident(value.trim_start_matches('$')),
+                _ => is_ident(value),
+            };
+            if !valid {
+                let kind = if key == "perms" {
+                    ParseErrorKind::InvalidPermissions(value.to_string())
+                } else {
+                    ParseErrorKind::InvalidMetadataValue(key.to_string(), value.to_string())
+                };
+                return Err(ParseError::new(kind, span.0, span.1));
+            }
+        }
+        self.pos = close + 1;
+        self.trailing()
+    }
+}
+
+// is the supplied str a non-empty identifier
+fn is_ident(input: &str) -> bool {
+    !input.is_empty() && input.chars().all(is_ident_char)
+}
+
+// the number of bytes of whitespace preceding the first non whitespace character
+fn leading_space(input: &str) -> usize {
+    input.len() - input.trim_start().len()
+}
+
+// the offset of the end of the line, ignoring trailing whitespace
+fn trimmed_end(input: &str) -> usize {
+    input.trim_end().len()
+}
+
+// the fallback when we cannot put our finger on the problem
+fn unexpected(input: &str) -> ParseError {
+    ParseError::new(
+        ParseErrorKind::Unexpected(input.trim().to_string()),
+        leading_space(input),
+        trimmed_end(input)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        start_parser, regex_parser, node_parser, edge_parser,
+        JSPTemplateError, JSPTemplateLineError, ParseErrorKind, ParseResult, StateMachine,
+        spelling::Suggestions,
+    };
+
+    // pull the ParseErrorKind out of a parser's result
+    fn kind(result: Result<ParseResult, JSPTemplateError>) -> ParseErrorKind {
+        match result {
+            Err(JSPTemplateError::ParsingError(e)) => e.kind().clone(),
+            other => panic!("expected a ParsingError. got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn definition_before_header() {
+        assert_eq!(kind(start_parser(r#"num = "[0-9]+""#)), ParseErrorKind::ExpectedHeader);
+    }
+
+    #[test]
+    fn unterminated_header() {
+        assert_eq!(kind(start_parser("[regex")), ParseErrorKind::UnterminatedHeader);
+    }
+
+    #[test]
+    fn regex_missing_equals() {
+        assert_eq!(
+            kind(regex_parser(r#"num_under "[0-9_]+""#)),
+            ParseErrorKind::ExpectedRegexEquals("num_under".to_string())
+        );
+    }
+
+    #[test]
+    fn regex_not_quoted() {
+        assert_eq!(
+            kind(regex_parser("num_under = [0-9_]+")),
+            ParseErrorKind::ExpectedQuotedRegex("num_under".to_string())
+        );
+    }
+
+    #[test]
+    fn regex_unterminated_quote() {
+        assert_eq!(kind(regex_parser(r#"num_under = "[0-9_]+"#)), ParseErrorKind::UnterminatedQuote);
+        assert_eq!(kind(node_parser(r#"show = "[A-Z]+" "(REF|SHARED)"#)), ParseErrorKind::UnterminatedQuote);
+    }
+
+    #[test]
+    fn regex_with_space() {
+        assert_eq!(kind(regex_parser(r#"foobar = "[a-zA-Z] ""#)), ParseErrorKind::InvalidRegexCharacter(' '));
+    }
+
+    #[test]
+    fn invalid_name() {
+        assert_eq!(kind(regex_parser(r#"foo-bar = "[a-z]+""#)), ParseErrorKind::InvalidName("foo-bar".to_string()));
+        assert_eq!(kind(node_parser("foo.bar = FOO")), ParseErrorKind::InvalidName("foo.bar".to_string()));
+    }
+
+    #[test]
+    fn node_missing_equals() {
+        assert_eq!(kind(node_parser("show $level")), ParseErrorKind::ExpectedNodeEquals("show".to_string()));
+    }
+
+    #[test]
+    fn node_missing_value() {
+        assert_eq!(kind(node_parser("show = ")), ParseErrorKind::MissingValue("show".to_string()));
+        assert_eq!(kind(node_parser("show = [volume]")), ParseErrorKind::MissingValue("show".to_string()));
+    }
+
+    #[test]
+    fn node_missing_regex_name() {
+        assert_eq!(kind(node_parser("show = $")), ParseErrorKind::MissingRegexName);
+    }
+
+    #[test]
+    fn unknown_metadata_key() {
+        let result = node_parser("show = $level [ onwer: jobsys, perms: 751 ]");
+        match result {
+            Err(JSPTemplateError::ParsingError(e)) => {
+                assert_eq!(
+                    e.kind(),
+                    &ParseErrorKind::UnknownMetadataKey("onwer".to_string(), Suggestions::new(vec!["owner".to_string()]))
+                );
+                assert_eq!(e.to_string(), "unknown metadata key `onwer`; did you mean `owner`?");
+                assert_eq!(e.span(), (16, 21));
+            }
+            other => panic!("expected a ParsingError. got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unterminated_metadata() {
+        assert_eq!(kind(node_parser("show = $level [ owner: jobsys")), ParseErrorKind::UnterminatedMetadata);
+    }
+
+    #[test]
+    fn bad_metadata_values() {
+        assert_eq!(kind(node_parser("show = $level [ perms: 75a ]")), ParseErrorKind::InvalidPermissions("75a".to_string()));
+        assert_eq!(kind(node_parser("show = $level [ owner ]")), ParseErrorKind::MissingMetadataValue("owner".to_string()));
+        assert_eq!(kind(node_parser("refdir = REF [ volume: true ]")), ParseErrorKind::UnexpectedMetadataValue("volume".to_string()));
+        assert_eq!(kind(node_parser("refdir = REF [ volume, ]")), ParseErrorKind::EmptyMetadataEntry);
+        assert_eq!(
+            kind(node_parser("show = $level [ varname: DD-SHOW ]")),
+            ParseErrorKind::InvalidMetadataValue("varname".to_string(), "DD-SHOW".to_string())
+        );
+    }
+
+    #[test]
+    fn edge_in_nodes_section() {
+        assert_eq!(kind(node_parser("dd -> shows")), ParseErrorKind::MisplacedEdge("nodes".to_string()));
+    }
+
+    #[test]
+    fn edge_in_regex_section() {
+        assert_eq!(kind(regex_parser("dd -> shows")), ParseErrorKind::MisplacedEdge("regex".to_string()));
+    }
+
+    #[test]
+    fn definition_in_graph_section() {
+        assert_eq!(kind(edge_parser("clientvault     = CLIENT_VAULT")), ParseErrorKind::MisplacedDefinition);
+    }
+
+    #[test]
+    fn dangling_arrow() {
+        assert_eq!(kind(edge_parser("dd -> shows -> ")), ParseErrorKind::DanglingArrow);
+        assert_eq!(kind(edge_parser(" -> shows")), ParseErrorKind::DanglingArrow);
+    }
+
+    #[test]
+    fn lone_node_in_graph_section() {
+        assert_eq!(kind(edge_parser("dd")), ParseErrorKind::ExpectedArrow("dd".to_string()));
+    }
+
+    #[test]
+    fn invalid_name_in_graph_section() {
+        assert_eq!(kind(edge_parser("shot -> shared[regex]")), ParseErrorKind::InvalidName("shared[regex]".to_string()));
+    }
+
+    #[test]
+    fn header_after_graph_section() {
+        assert_eq!(kind(edge_parser("[regex]")), ParseErrorKind::HeaderAfterGraph("regex".to_string()));
+    }
+
+    #[test]
+    fn error_example_reports_definition_in_graph() {
+        let mut statemachine = StateMachine::new();
+        let error = include_str!("../../examples/error.jspt")
+            .lines()
+            .map(|line| statemachine.parse(line))
+            .find(|result| result.is_err())
+            .unwrap()
+            .unwrap_err();
+        let JSPTemplateLineError::ErrorAtLine(line_num, _, _, error) = error;
+        assert_eq!(line_num, 26);
+        match error {
+            JSPTemplateError::ParsingError(e) => assert_eq!(e.kind(), &ParseErrorKind::MisplacedDefinition),
+            other => panic!("expected a ParsingError. got {:?}", other),
+        }
+    }
+}
@@ -8,6 +8,8 @@ use nom::{
 
 use crate::{Header, ParseResult};
 
+/// The names which may appear within a section header, including aliases.
+pub const HEADER_NAMES: &[&str] = &["regex", "regexp", "re", "nodes", "node", "graph", "edge", "edges"];
 
 /// Parse the section header, consisting of an identifier surounded by square
 /// brackets. 
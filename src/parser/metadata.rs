@@ -25,6 +25,7 @@ pub fn parse_metadata(input: &str) -> IResult<&str, Metadata> {
                     MetadataComponent::EnvVarName(name) => metadata = metadata.set_varname(Some(name)),
                     MetadataComponent::Owner(name) => metadata = metadata.set_owner(Some(name)),
                     MetadataComponent::Volume => metadata = metadata.set_volume(true),
+                    MetadataComponent::AutoCreate => metadata = metadata.set_autocreate(true),
                     MetadataComponent::Separator => {
                         log::warn!("parse_metadata encountered Separateor");
                     }
@@ -42,6 +43,7 @@ pub fn parse_components(input: &str) -> IResult<&str, Vec<MetadataComponent>> {
             parse_comma,
             alt((
                 parse_volume,
+                parse_autocreate,
                 parse_permissions,
                 parse_owner,
                 parse_varname,
@@ -118,6 +120,21 @@ mod parse_components_tests {
         );
     }
 
+    #[test]
+    fn can_parse_autocreate_and_perms() {
+        let cmp = parse_components("[ autocreate, perms: 770 ]");
+        assert_eq!(
+           cmp,
+            Ok((
+                "",
+                vec![
+                    MetadataComponent::AutoCreate,
+                    MetadataComponent::Permissions("770".to_string())
+                ]
+            ))
+        );
+    }
+
 }
 
 fn parse_comma(input:  &str) -> IResult<&str, MetadataComponent> {
@@ -156,6 +173,16 @@ mod volume_tests {
     }
 }
 
+// autocreate
+fn parse_autocreate(input: &str) -> IResult<&str, MetadataComponent> {
+    map(
+        delimited(space0, tag("autocreate"), space0),
+        |_item| {
+            MetadataComponent::AutoCreate
+        }
+    )(input)
+}
+
 // owner : jgerber
 fn parse_owner(input: &str) -> IResult<&str, MetadataComponent> {
     map(
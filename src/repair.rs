@@ -0,0 +1,183 @@
+//! Bring an existing directory tree into conformance with a template.
+//!
+//! A repair plan resolves the issues found by an audit. Permissions and owners
+//! are reset from the node metadata, and missing `autocreate` children are
+//! created, along with their own autocreate children. Entries which match no
+//! node, and files where the template expects a directory, are optionally moved
+//! into a quarantine directory, preserving their path relative to the root. The
+//! plan is made up of sandbox Operations, and is applied in the same way.
+use crate::{
+    Matcher,
+    audit::{Classification, Issue, Report},
+    sandbox::{Operation, representative, resolve_owner, sort_deepest_first},
+};
+use jsp::NIndex;
+use std::{collections::HashSet, path::{Path, PathBuf}};
+
+/// Plan the operations which bring the audited tree into conformance. Moves
+/// come first, followed by the creation of missing directories, parents first,
+/// and then changes to ownership and permissions, deepest first.
+///
+/// # Parameters
+///
+/// * `matcher` - a Matcher over the loaded template
+/// * `autocreate` - the nodes marked `autocreate`, as reported by the Loader
+/// * `report` - the result of auditing the tree
+/// * `quarantine` - the directory into which non-conforming entries are moved.
+///    If None, they are left in place.
+///
+/// # Returns
+/// The operations, in the order in which they should be applied
+pub fn plan(matcher: &Matcher, autocreate: &HashSet<NIndex>, report: &Report, quarantine: Option<&Path>) -> Vec<Operation> {
+    let mut planner = Planner { matcher, autocreate, mkdirs: Vec::new(), metadata: Vec::new() };
+    let mut moves = Vec::new();
+    for entry in report.entries() {
+        // the quarantine may well live within the tree
+        if quarantine == Some(entry.path()) {
+            continue;
+        }
+        let misplaced = entry.issues().contains(&Issue::NotADirectory);
+        if entry.classification() == Classification::Rogue || misplaced {
+            if let Some(quarantine) = quarantine {
+                let relative = entry.path().strip_prefix(report.root()).unwrap_or_else(|_| entry.path());
+                moves.push(Operation::Move(entry.path().to_path_buf(), quarantine.join(relative)));
+                if let (true, Some(node)) = (misplaced, entry.node_index()) {
+                    planner.create(node, entry.path().to_path_buf());
+                }
+            }
+            continue;
+        }
+        for issue in entry.issues() {
+            match issue {
+                Issue::Permissions { expected, .. } => {
+                    if let Ok(mode) = u32::from_str_radix(expected, 8) {
+                        planner.metadata.push(Operation::Chmod(entry.path().to_path_buf(), mode));
+                    }
+                }
+                Issue::Owner { expected, .. } => {
+                    planner.metadata.push(Operation::Chown(entry.path().to_path_buf(), expected.clone()));
+                }
+                Issue::Missing { node } => {
+                    let child = entry.node_index().and_then(|parent|
+                        matcher.children(parent).into_iter().find(|child| matcher.name(*child) == node)
+                    );
+                    if let Some(child) = child {
+                        planner.create_beneath(child, entry.path());
+                    }
+                }
+                // handled above
+                Issue::NotADirectory => (),
+                // there is nothing we can do about an entry we cannot read
+                Issue::Unreadable { .. } => (),
+            }
+        }
+    }
+    sort_deepest_first(&mut planner.metadata);
+    moves.extend(planner.mkdirs);
+    moves.extend(planner.metadata);
+    moves
+}
+
+// accumulates the operations which create directories
+struct Planner<'a, 'b> {
+    matcher: &'a Matcher<'b>,
+    autocreate: &'a HashSet<NIndex>,
+    mkdirs: Vec<Operation>,
+    metadata: Vec<Operation>,
+}
+
+impl<'a, 'b> Planner<'a, 'b> {
+    // create a directory for `node` within `parent`, named as the sandbox would name it
+    fn create_beneath(&mut self, node: NIndex, parent: &Path) {
+        // a regex for which we cannot generate a name is left for a person to create
+        if let Some(name) = representative(self.matcher, node) {
+            self.create(node, parent.join(name));
+        }
+    }
+
+    // create the directory for `node` at `path`, along with its autocreate children
+    fn create(&mut self, node: NIndex, path: PathBuf) {
+        let jnode = &self.matcher.graph()[node];
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        self.mkdirs.push(Operation::Mkdir(path.clone()));
+        if let Some(owner) = jnode.metadata().owner().and_then(|owner| resolve_owner(&owner.to_string(), jnode.identity(), &name)) {
+            self.metadata.push(Operation::Chown(path.clone(), owner));
+        }
+        if let Some(mode) = jnode.metadata().perms().and_then(|perms| u32::from_str_radix(perms, 8).ok()) {
+            self.metadata.push(Operation::Chmod(path.clone(), mode));
+        }
+        for child in self.matcher.children(node) {
+            if self.autocreate.contains(&child) {
+                self.create_beneath(child, &path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{loader::{load_template_with_autocreate, mkdir}, audit::audit, sandbox::apply};
+    use std::fs;
+
+    const TEMPLATE: &str = "[regex]\n[nodes]\ndd\nshows\nshow = \"[A-Z]+\" [perms: 750]\nwork = work [autocreate, perms: 770]\nuser = user [autocreate]\n[graph]\nroot -> dd -> shows -> show -> work -> user\n";
+
+    #[test]
+    fn repairs_perms_and_missing_children() {
+        let (graph, keymap, autocreate) = load_template_with_autocreate(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        mkdir(&dir.path().join("dd/shows/FOO"), 0o755);
+        mkdir(&dir.path().join("dd/shows/BAR/work"), 0o700);
+        let report = audit(&matcher, &autocreate, dir.path()).unwrap();
+        let operations = plan(&matcher, &autocreate, &report, None);
+        let d = dir.path().display();
+        assert_eq!(
+            operations.iter().map(|op| op.to_string()).collect::<Vec<_>>(),
+            vec![
+                format!("mkdir {}/dd/shows/BAR/work/user", d),
+                format!("mkdir {}/dd/shows/FOO/work", d),
+                format!("mkdir {}/dd/shows/FOO/work/user", d),
+                format!("chmod 770 {}/dd/shows/BAR/work", d),
+                format!("chmod 770 {}/dd/shows/FOO/work", d),
+                format!("chmod 750 {}/dd/shows/BAR", d),
+                format!("chmod 750 {}/dd/shows/FOO", d),
+            ]
+        );
+        apply(&operations, false).unwrap();
+        let report = audit(&matcher, &autocreate, dir.path()).unwrap();
+        assert!(report.is_clean(), "{}", report);
+    }
+
+    #[test]
+    fn quarantines_nonconforming_entries() {
+        let (graph, keymap, autocreate) = load_template_with_autocreate(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let quarantine = dir.path().join("quarantine");
+        mkdir(&dir.path().join("dd/shows/FOO"), 0o750);
+        mkdir(&dir.path().join("dd/shows/foo/stuff"), 0o755);
+        fs::write(dir.path().join("dd/shows/FOO/work"), "").unwrap();
+        let report = audit(&matcher, &autocreate, dir.path()).unwrap();
+        let operations = plan(&matcher, &autocreate, &report, Some(&quarantine));
+        let d = dir.path().display();
+        assert_eq!(
+            operations.iter().map(|op| op.to_string()).collect::<Vec<_>>(),
+            vec![
+                format!("mv {0}/dd/shows/FOO/work {0}/quarantine/dd/shows/FOO/work", d),
+                format!("mv {0}/dd/shows/foo {0}/quarantine/dd/shows/foo", d),
+                format!("mkdir {}/dd/shows/FOO/work", d),
+                format!("mkdir {}/dd/shows/FOO/work/user", d),
+                format!("chmod 770 {}/dd/shows/FOO/work", d),
+            ]
+        );
+        apply(&operations, false).unwrap();
+        let report = audit(&matcher, &autocreate, dir.path()).unwrap();
+        assert!(quarantine.join("dd/shows/foo/stuff").is_dir());
+        assert!(quarantine.join("dd/shows/FOO/work").is_file());
+        // the quarantine itself is the only rogue entry left
+        let rogue = report.rogue().map(|entry| entry.path().to_path_buf()).collect::<Vec<_>>();
+        assert_eq!(rogue, vec![quarantine.clone()]);
+        assert_eq!(report.issue_count(), 0);
+    }
+}
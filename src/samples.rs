@@ -0,0 +1,413 @@
+//! Generate example paths from a template.
+//!
+//! Example names for regex nodes are generated by expanding the regex's syntax
+//! tree into a bounded set of candidate strings, picking a few representative
+//! characters for each class and a few counts for each repetition. Every
+//! candidate is then checked against the node's actual regex and exclude
+//! regex, so that only names which the template accepts are ever produced.
+use crate::{Matcher, helpers::is_path_component};
+use jsp::{NIndex, NodeType, Regexp};
+use std::collections::{HashMap, HashSet};
+use regex_syntax::{
+    Parser,
+    hir::{Class, Hir, HirKind, Literal, RepetitionKind, RepetitionRange},
+};
+
+/// The code of the warning reported for each node which cannot be named
+pub const UNSAMPLED_NODE: &str = "JSP1005";
+
+// the maximum number of candidates considered for any one piece of a regex
+const MAX_CANDIDATES: usize = 64;
+// the maximum number of characters picked from a character class
+const MAX_CLASS_CHARS: usize = 3;
+
+/// Generate up to `count` names which match `pattern` and do not match `exclude`.
+/// Only names which are a single path component are generated, so names which
+/// are empty, `.` or `..`, or which contain `/`, are never produced.
+///
+/// # Examples
+///
+/// ```
+/// use jspcompile::samples::regex_samples;
+/// use jsp::Regexp;
+///
+/// let pattern = Regexp::new("^[A-Z]{2}_[0-9]{4}$").unwrap();
+/// let exclude = Regexp::new("^AA_0000$").unwrap();
+/// let samples = regex_samples(&pattern, Some(&exclude), 2);
+/// assert_eq!(samples.len(), 2);
+/// for sample in samples {
+///     assert!(pattern.is_match(&sample) && !exclude.is_match(&sample));
+/// }
+/// ```
+pub fn regex_samples(pattern: &Regexp, exclude: Option<&Regexp>, count: usize) -> Vec<String> {
+    let hir = match Parser::new().parse(pattern.as_str()) {
+        Ok(hir) => hir,
+        Err(_) => return Vec::new(),
+    };
+    let mut samples = Vec::new();
+    for candidate in expand(&hir) {
+        if samples.len() == count {
+            break;
+        }
+        if !is_path_component(&candidate) || !pattern.is_match(&candidate) || exclude.map_or(false, |e| e.is_match(&candidate)) {
+            continue;
+        }
+        if !samples.contains(&candidate) {
+            samples.push(candidate);
+        }
+    }
+    samples
+}
+
+/// Generate up to `count` names which a node accepts. Literal nodes accept
+/// only their literal, and root accepts nothing, as it is implicit.
+pub fn node_samples(identity: &NodeType, count: usize) -> Vec<String> {
+    match identity {
+        NodeType::Simple(value) => vec![value.clone()],
+        NodeType::RegEx { pattern, exclude, .. } => regex_samples(pattern, exclude.as_ref(), count),
+        NodeType::Root | NodeType::Untracked => Vec::new(),
+    }
+}
+
+/// Enumerate the routes through the graph from root, as the nodes along each route,
+/// excluding root. Every prefix of a route is itself a route, so each node appears
+/// at the end of at least one route. If `target` is supplied, only the routes which
+/// end at it are returned, and nodes from which it cannot be reached are not
+/// visited. Cycles are not followed.
+pub fn node_paths(matcher: &Matcher, target: Option<NIndex>) -> Vec<Vec<NIndex>> {
+    let mut paths = Vec::new();
+    let mut stack = Vec::new();
+    let root = matcher.root();
+    let reaching = target.map(|target| reaching(matcher, target));
+    visit(matcher, root, target, reaching.as_ref(), &mut stack, &mut paths);
+    paths
+}
+
+// the nodes from which `target` can be reached, including itself
+fn reaching(matcher: &Matcher, target: NIndex) -> HashSet<NIndex> {
+    let mut parents: HashMap<NIndex, Vec<NIndex>> = HashMap::new();
+    for node in matcher.graph().node_indices() {
+        for child in matcher.children(node) {
+            parents.entry(child).or_default().push(node);
+        }
+    }
+    let mut reaching = HashSet::new();
+    let mut queue = vec![target];
+    while let Some(node) = queue.pop() {
+        if reaching.insert(node) {
+            queue.extend(parents.get(&node).into_iter().flatten().cloned());
+        }
+    }
+    reaching
+}
+
+// depth first traversal, recording each route as we come to it
+fn visit(matcher: &Matcher, node: NIndex, target: Option<NIndex>, reaching: Option<&HashSet<NIndex>>, stack: &mut Vec<NIndex>, paths: &mut Vec<Vec<NIndex>>) {
+    for child in matcher.children(node) {
+        if stack.contains(&child) || child == matcher.root() || reaching.map_or(false, |reaching| !reaching.contains(&child)) {
+            continue;
+        }
+        stack.push(child);
+        if target.map_or(true, |target| target == child) {
+            paths.push(stack.clone());
+        }
+        visit(matcher, child, target, reaching, stack, paths);
+        stack.pop();
+    }
+}
+
+/// List the nodes on the routes from root for which no name can be generated,
+/// such as a regex which only matches whitespace or the path separator. Paths
+/// through these nodes are skipped by `sample_paths` and by `sandbox::plan`.
+///
+/// # Parameters
+///
+/// * `matcher` - a Matcher over the loaded template
+/// * `target` - if supplied, only the routes ending at this node are considered
+///
+/// # Returns
+/// The nodes, in the order in which they are first reached
+pub fn unsampled_nodes(matcher: &Matcher, target: Option<NIndex>) -> Vec<NIndex> {
+    let mut checked = HashSet::new();
+    let mut unsampled = Vec::new();
+    for route in node_paths(matcher, target) {
+        for node in route {
+            if checked.insert(node) && node_samples(matcher.graph()[node].identity(), 1).is_empty() {
+                unsampled.push(node);
+            }
+        }
+    }
+    unsampled
+}
+
+/// Generate up to `count` example paths which the template accepts. The samples
+/// cycle through the routes from root, so that as much of the template as possible
+/// is covered, using a different name for each regex node on each pass.
+///
+/// # Parameters
+///
+/// * `matcher` - a Matcher over the loaded template
+/// * `target` - if supplied, only paths ending at this node are generated
+/// * `count` - the maximum number of paths to generate
+pub fn sample_paths(matcher: &Matcher, target: Option<NIndex>, count: usize) -> Vec<String> {
+    // names for each node on the routes, generated once per node
+    let mut names = HashMap::new();
+    let routes = node_paths(matcher, target).into_iter()
+        .filter(|route| {
+            route.iter().all(|node| {
+                !names.entry(*node)
+                    .or_insert_with(|| node_samples(matcher.graph()[*node].identity(), count.max(1)))
+                    .is_empty()
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut samples = Vec::new();
+    if routes.is_empty() {
+        return samples;
+    }
+    let mut pass = 0;
+    while samples.len() < count {
+        let before = samples.len();
+        for route in &routes {
+            if samples.len() == count {
+                break;
+            }
+            let path = route.iter()
+                .map(|node| {
+                    let choices = &names[node];
+                    choices[pass % choices.len()].as_str()
+                })
+                .collect::<Vec<_>>();
+            let path = format!("/{}", path.join("/"));
+            if !samples.contains(&path) {
+                samples.push(path);
+            }
+        }
+        pass += 1;
+        // once a pass produces nothing new, we have run out of variations
+        if samples.len() == before && pass > 1 {
+            break;
+        }
+    }
+    samples
+}
+
+// expand a regex's syntax tree into a bounded list of candidate strings. Candidates
+// are not guaranteed to match, as anchors, word boundaries and the like are ignored.
+fn expand(hir: &Hir) -> Vec<String> {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Anchor(_) | HirKind::WordBoundary(_) => vec![String::new()],
+        HirKind::Literal(Literal::Unicode(c)) => vec![c.to_string()],
+        HirKind::Literal(Literal::Byte(b)) => vec![(*b as char).to_string()],
+        HirKind::Class(Class::Unicode(class)) => pick_chars(|c| {
+            class.iter().any(|range| range.start() <= c && c <= range.end())
+        }),
+        HirKind::Class(Class::Bytes(class)) => pick_chars(|c| {
+            class.iter().any(|range| range.start() as char <= c && c <= range.end() as char)
+        }),
+        HirKind::Group(group) => expand(&group.hir),
+        HirKind::Repetition(repetition) => {
+            let (min, max) = match repetition.kind {
+                RepetitionKind::ZeroOrOne => (0, Some(1)),
+                RepetitionKind::ZeroOrMore => (0, None),
+                RepetitionKind::OneOrMore => (1, None),
+                RepetitionKind::Range(RepetitionRange::Exactly(n)) => (n, Some(n)),
+                RepetitionKind::Range(RepetitionRange::AtLeast(n)) => (n, None),
+                RepetitionKind::Range(RepetitionRange::Bounded(m, n)) => (m, Some(n)),
+            };
+            repeat(&expand(&repetition.hir), min, max)
+        }
+        HirKind::Concat(hirs) => hirs.iter().fold(vec![String::new()], |prefixes, hir| {
+            // once any piece has no candidates, neither does the whole
+            if prefixes.is_empty() {
+                return prefixes;
+            }
+            let suffixes = expand(hir);
+            if suffixes.is_empty() {
+                return suffixes;
+            }
+            let mut combined = Vec::new();
+            for idx in 0..MAX_CANDIDATES {
+                // shift the suffix along with the prefix, so that both vary early on,
+                // rather than only the prefix until the candidates run out
+                let (shift, row) = (idx / prefixes.len(), idx % prefixes.len());
+                let prefix = &prefixes[row];
+                let suffix = &suffixes[(shift + row) % suffixes.len()];
+                let candidate = format!("{}{}", prefix, suffix);
+                if !combined.contains(&candidate) {
+                    combined.push(candidate);
+                }
+                if idx + 1 >= prefixes.len() * suffixes.len() {
+                    break;
+                }
+            }
+            combined
+        }),
+        HirKind::Alternation(hirs) => {
+            let branches = hirs.iter().map(expand).collect::<Vec<_>>();
+            let longest = branches.iter().map(Vec::len).max().unwrap_or(0);
+            // interleave the branches, so that each is represented
+            let mut candidates = Vec::new();
+            for idx in 0..longest {
+                for branch in &branches {
+                    if let Some(candidate) = branch.get(idx) {
+                        candidates.push(candidate.clone());
+                    }
+                }
+            }
+            candidates.truncate(MAX_CANDIDATES);
+            candidates
+        }
+    }
+}
+
+// repeat the candidates between min and max times, trying the smallest non empty
+// count first, followed by one more, and finally the minimum
+fn repeat(candidates: &[String], min: u32, max: Option<u32>) -> Vec<String> {
+    if candidates.is_empty() {
+        return if min == 0 { vec![String::new()] } else { Vec::new() };
+    }
+    let clamp = |n: u32| max.map_or(n, |max| n.min(max));
+    let first = clamp(min.max(1));
+    let mut counts = vec![first, clamp(first + 1), min];
+    counts.dedup();
+    let mut repeated = Vec::new();
+    for count in counts {
+        for offset in 0..candidates.len() {
+            let candidate = (0..count as usize)
+                .map(|idx| candidates[(offset + idx) % candidates.len()].as_str())
+                .collect::<String>();
+            if !repeated.contains(&candidate) {
+                repeated.push(candidate);
+            }
+        }
+    }
+    repeated.truncate(MAX_CANDIDATES);
+    repeated
+}
+
+// pick a few representative characters which belong to a class. We restrict
+// ourselves to printable ascii, less the path separator, and prefer letters
+// and numbers, spreading our picks across the class.
+fn pick_chars<F>(contains: F) -> Vec<String>
+where
+    F: Fn(char) -> bool
+{
+    let preferred = ('A'..='Z').chain('0'..='9').chain('a'..='z').chain("_-.".chars());
+    let others = ('!'..='~').filter(|c| !c.is_ascii_alphanumeric() && !"_-./".contains(*c));
+    let members = preferred.chain(others).filter(|c| contains(*c)).collect::<Vec<_>>();
+    if members.len() <= MAX_CLASS_CHARS {
+        return members.iter().map(|c| c.to_string()).collect();
+    }
+    let alphanumeric = members.iter().filter(|c| c.is_ascii_alphanumeric()).count();
+    // spread our picks across the letters and numbers if there are any
+    let span = if alphanumeric > 0 { alphanumeric } else { members.len() };
+    (0..MAX_CLASS_CHARS)
+        .map(|idx| members[idx * (span - 1) / (MAX_CLASS_CHARS - 1)].to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_template;
+
+    fn re(pattern: &str) -> Regexp {
+        Regexp::new(pattern).unwrap()
+    }
+
+    #[test]
+    fn samples_match_pattern() {
+        for pattern in &[
+            "^[A-Z]+[A-Z0-9]*$",
+            "^(([A-Z]{2,4})|LIBRARY)$",
+            "^work\\.(?P<work>[a-z]+)$",
+            "^[a-z]+([_]{0,1}[a-z0-9])*$",
+            "^(([0-9]{4,5})|([0-9]{1,2}?[a-z]+)|([a-z]{2}[0-9]{4,5}))$",
+            "^[^/]+$",
+        ] {
+            let pattern = re(pattern);
+            let samples = regex_samples(&pattern, None, 5);
+            assert_eq!(samples.len(), 5, "{} produced {:?}", pattern, samples);
+            assert!(samples.iter().all(|s| pattern.is_match(s)));
+        }
+    }
+
+    #[test]
+    fn samples_avoid_exclude() {
+        let pattern = re("^(REF|SHARED|[A-Z]+)$");
+        let exclude = re("^(REF|SHARED)$");
+        let samples = regex_samples(&pattern, Some(&exclude), 10);
+        assert!(!samples.is_empty());
+        assert!(samples.iter().all(|s| !exclude.is_match(s)));
+    }
+
+    #[test]
+    fn samples_are_single_path_components() {
+        assert!(regex_samples(&re("^a/b$"), None, 3).is_empty());
+        assert!(regex_samples(&re("^\\.\\.$"), None, 3).is_empty());
+        assert_eq!(regex_samples(&re("^(\\.|\\.x)$"), None, 3), vec![".x"]);
+        let (graph, keymap) = load_template("[regex]\n[nodes]\nslashed = \"a/b\"\ndots = \"\\.\\.\"\n[graph]\nroot -> slashed\nroot -> dots\n");
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        assert!(sample_paths(&matcher, None, 10).is_empty());
+        assert_eq!(unsampled_nodes(&matcher, None), vec![keymap["slashed"], keymap["dots"]]);
+    }
+
+    #[test]
+    fn picks_chars_across_class() {
+        assert_eq!(pick_chars(|c| c.is_ascii_uppercase()), vec!["A", "M", "Z"]);
+        assert_eq!(pick_chars(|c| c == '_' || c == '-'), vec!["_", "-"]);
+    }
+
+    #[test]
+    fn sample_paths_are_accepted_by_the_template() {
+        let (graph, keymap) = load_template(include_str!("../examples/jsptemplate.jspt"));
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let samples = sample_paths(&matcher, None, 200);
+        assert_eq!(samples.len(), 200);
+        for sample in &samples {
+            assert!(matcher.match_path(sample).is_match(), "{} does not match", sample);
+        }
+    }
+
+    #[test]
+    fn skips_nodes_which_cannot_be_named() {
+        let (graph, keymap) = load_template("[regex]\n[nodes]\nshows\nshow = \"[A-Z]+\"\nspaced = \"a\\sb\"\nslash = \"[/]\"\ngreek = \"\\p{Greek}+\"\n[graph]\nroot -> shows -> show\nshows -> spaced -> show\nshows -> slash\nshows -> greek\n");
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        for node in &["spaced", "slash", "greek"] {
+            assert!(node_samples(graph[keymap[*node]].identity(), 3).is_empty(), "{}", node);
+        }
+        let samples = sample_paths(&matcher, None, 10);
+        assert!(!samples.is_empty());
+        assert!(samples.iter().all(|sample| matcher.match_path(sample).is_match()));
+        assert_eq!(unsampled_nodes(&matcher, None), vec![keymap["spaced"], keymap["slash"], keymap["greek"]]);
+        assert_eq!(unsampled_nodes(&matcher, Some(keymap["show"])), vec![keymap["spaced"]]);
+        assert!(crate::sandbox::plan(&matcher, std::path::Path::new("/sandbox")).iter().all(|op| match op {
+            crate::sandbox::Operation::Mkdir(path) => !path.to_string_lossy().contains("slash"),
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn routes_to_a_target_are_those_which_end_there() {
+        let (graph, keymap) = load_template(include_str!("../examples/jsptemplate.jspt"));
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let shot = keymap["shot"];
+        let expected = node_paths(&matcher, None).into_iter().filter(|route| route.last() == Some(&shot)).collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+        assert_eq!(node_paths(&matcher, Some(shot)), expected);
+    }
+
+    #[test]
+    fn sample_paths_end_at_target() {
+        let (graph, keymap) = load_template(include_str!("../examples/jsptemplate.jspt"));
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let shot = keymap["shot"];
+        let samples = sample_paths(&matcher, Some(shot), 3);
+        assert_eq!(samples.len(), 3);
+        for sample in &samples {
+            let result = matcher.match_path(sample);
+            assert_eq!(result.components().last().map(|c| c.name()), Some("shot"));
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Materialise a template as a directory tree.
+//!
+//! A sandbox contains one representative directory for each route through the
+//! template from root, with regex nodes named by the sample generator. This
+//! gives pipeline developers a realistic tree to test against, without needing
+//! a production show.
+use crate::{Matcher, helpers::is_path_component, samples::{node_paths, node_samples}};
+use jsp::{NIndex, NodeType};
+use std::{
+    collections::HashSet,
+    fmt, fs, io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The code of the warning reported for each chown which is skipped
+pub const SKIPPED_CHOWN: &str = "JSP1004";
+
+/// A single step in materialising a template
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Operation {
+    /// Create a directory
+    Mkdir(PathBuf),
+    /// Set the permissions of a directory to the octal mode
+    Chmod(PathBuf, u32),
+    /// Change the owner of a directory
+    Chown(PathBuf, String),
+    /// Move an entry elsewhere, creating the destination's parent if need be
+    Move(PathBuf, PathBuf),
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operation::Mkdir(path) => write!(f, "mkdir {}", path.display()),
+            Operation::Chmod(path, mode) => write!(f, "chmod {:o} {}", mode, path.display()),
+            Operation::Chown(path, owner) => write!(f, "chown {} {}", owner, path.display()),
+            Operation::Move(from, to) => write!(f, "mv {} {}", from.display(), to.display()),
+        }
+    }
+}
+
+/// Plan the operations which materialise the template beneath `into`. Directories
+/// are created parents first. Ownership and permissions are applied once every
+/// directory exists, deepest first, so that restrictive permissions on a parent
+/// cannot prevent its children from being created.
+///
+/// # Parameters
+///
+/// * `matcher` - a Matcher over the loaded template
+/// * `into` - the directory beneath which the tree is created
+///
+/// # Returns
+/// The operations, in the order in which they should be applied
+pub fn plan(matcher: &Matcher, into: &Path) -> Vec<Operation> {
+    let mut mkdirs = Vec::new();
+    let mut metadata = Vec::new();
+    let mut seen = HashSet::new();
+    for route in node_paths(matcher, None) {
+        let names = match route.iter().map(|node| representative(matcher, *node)).collect::<Option<Vec<_>>>() {
+            Some(names) => names,
+            // a regex for which we cannot generate a name
+            None => continue,
+        };
+        let path = names.iter().fold(into.to_path_buf(), |path, name| path.join(name));
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+        let node = *route.last().expect("routes are never empty");
+        let name = names.last().expect("routes are never empty");
+        mkdirs.push(Operation::Mkdir(path.clone()));
+        let node = &matcher.graph()[node];
+        if let Some(owner) = node.metadata().owner().and_then(|owner| resolve_owner(&owner.to_string(), node.identity(), name)) {
+            metadata.push(Operation::Chown(path.clone(), owner));
+        }
+        if let Some(mode) = node.metadata().perms().and_then(|perms| u32::from_str_radix(perms, 8).ok()) {
+            metadata.push(Operation::Chmod(path, mode));
+        }
+    }
+    sort_deepest_first(&mut metadata);
+    mkdirs.extend(metadata);
+    mkdirs
+}
+
+/// Sort operations so that those on the deepest paths come first. This keeps
+/// restrictive permissions on a parent from preventing changes to its children.
+pub fn sort_deepest_first(operations: &mut [Operation]) {
+    operations.sort_by_key(|op| match op {
+        Operation::Mkdir(path) | Operation::Chmod(path, _) | Operation::Chown(path, _) | Operation::Move(path, _) => {
+            std::cmp::Reverse(path.components().count())
+        }
+    });
+}
+
+/// Apply the operations. Ownership may only be changed by root, so when not
+/// running as root, chown operations are skipped. A chown which fails, perhaps
+/// because the owner does not exist on this host, is skipped as well.
+///
+/// # Returns
+/// A Result wrapping the operations which were skipped, along with the reason
+/// each was skipped, if successful. Otherwise the io::Error which stopped us.
+pub fn apply(operations: &[Operation], is_root: bool) -> io::Result<Vec<(Operation, String)>> {
+    let mut skipped = Vec::new();
+    for operation in operations {
+        match operation {
+            Operation::Mkdir(path) => {
+                if !path.is_dir() {
+                    fs::create_dir_all(path)?;
+                }
+            }
+            Operation::Chmod(path, mode) => fs::set_permissions(path, fs::Permissions::from_mode(*mode))?,
+            Operation::Chown(path, owner) => {
+                if !is_root {
+                    skipped.push((operation.clone(), "only root may change ownership".to_string()));
+                    continue;
+                }
+                let output = Command::new("chown").arg(owner).arg(path).output()?;
+                if !output.status.success() {
+                    let reason = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                    skipped.push((operation.clone(), reason));
+                }
+            }
+            Operation::Move(from, to) => {
+                if let Some(parent) = to.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::rename(from, to)?;
+            }
+        }
+    }
+    Ok(skipped)
+}
+
+/// Are we running as root?
+pub fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Retrieve the name used for a node in the sandbox. Literals are named for
+/// themselves, and regexes by the sample generator. The name is always a single,
+/// normal path component, so that joining it onto a directory never leaves that
+/// directory; a node which cannot be named so has no representative.
+pub fn representative(matcher: &Matcher, node: NIndex) -> Option<String> {
+    node_samples(matcher.graph()[node].identity(), 1).into_iter()
+        .next()
+        .filter(|name| is_path_component(name))
+}
+
+// Owners of the form `$name` refer to a named capture group within the node's regex.
+// Resolve these against the directory's name.
+pub(crate) fn resolve_owner(owner: &str, identity: &NodeType, name: &str) -> Option<String> {
+    if !owner.starts_with('$') {
+        return Some(owner.to_string());
+    }
+    match identity {
+        NodeType::RegEx { pattern, .. } => pattern.captures(name)
+            .and_then(|captures| captures.name(&owner[1..]).map(|m| m.as_str().to_string())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_template;
+
+    const TEMPLATE: &str = "[regex]\n[nodes]\ndd\nshows\nshow = \"[A-Z]+\" \"(REF)\" [perms: 750]\nwork = \"work\\.(?P<user>[a-z]+)\" [owner: $user]\nshared = SHARED [owner: jobsys, perms: 0555]\n[graph]\nroot -> dd -> shows -> show -> work\nshow -> shared\n";
+
+    #[test]
+    fn plans_directories_then_metadata() {
+        let (graph, keymap) = load_template(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let operations = plan(&matcher, Path::new("/sandbox")).iter().map(|op| op.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            operations,
+            vec![
+                "mkdir /sandbox/dd",
+                "mkdir /sandbox/dd/shows",
+                "mkdir /sandbox/dd/shows/A",
+                "mkdir /sandbox/dd/shows/A/work.a",
+                "mkdir /sandbox/dd/shows/A/SHARED",
+                "chown a /sandbox/dd/shows/A/work.a",
+                "chown jobsys /sandbox/dd/shows/A/SHARED",
+                "chmod 555 /sandbox/dd/shows/A/SHARED",
+                "chmod 750 /sandbox/dd/shows/A",
+            ]
+        );
+    }
+
+    #[test]
+    fn creates_tree_and_skips_chown() {
+        let (graph, keymap) = load_template(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        let sandbox = tempfile::tempdir().unwrap();
+        let skipped = apply(&plan(&matcher, sandbox.path()), false).unwrap();
+        assert_eq!(skipped.len(), 2);
+        let show = sandbox.path().join("dd/shows/A");
+        assert!(show.join("work.a").is_dir());
+        assert_eq!(fs::metadata(&show).unwrap().permissions().mode() & 0o777, 0o750);
+        // let the tempdir clean up after itself
+        fs::set_permissions(show.join("SHARED"), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn never_plans_a_path_outside_the_sandbox() {
+        let template = "[regex]\n[nodes]\nup = \"\\.\\.\" [perms: 777]\nhere = \"\\.\" [perms: 777]\nshow = \"[A-Z]+\"\n[graph]\nroot -> up -> show\nroot -> here -> show\nroot -> show\n";
+        let (graph, keymap) = load_template(template);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        assert_eq!(representative(&matcher, keymap["up"]), None);
+        assert_eq!(representative(&matcher, keymap["here"]), None);
+        let into = Path::new("/sandbox");
+        let operations = plan(&matcher, into);
+        assert_eq!(operations.iter().map(|op| op.to_string()).collect::<Vec<_>>(), vec!["mkdir /sandbox/A"]);
+        for operation in &operations {
+            let path = match operation {
+                Operation::Mkdir(path) | Operation::Chown(path, _) | Operation::Chmod(path, _) => path,
+                other => panic!("unexpected operation {}", other),
+            };
+            assert!(path.starts_with(into), "{}", operation);
+            assert!(path.components().all(|component| component != std::path::Component::ParentDir), "{}", operation);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Spelling suggestions for misspelled names.
+//!
+//! When a template refers to a node, regex, header or metadata key which does
+//! not exist, we look for the closest known names by edit distance so that
+//! the error can ask "did you mean ...?".
+use std::fmt;
+
+// the maximum number of suggestions offered for a single misspelling
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Calculate the edit distance between two strs, counting insertions, deletions,
+/// substitutions and transpositions of adjacent characters as a single edit each.
+///
+/// # Examples
+///
+/// ```
+/// use jspcompile::spelling::edit_distance;
+///
+/// assert_eq!(edit_distance("grapha", "graph"), 1);
+/// assert_eq!(edit_distance("onwer", "owner"), 1);
+/// ```
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // distances[i][j] holds the distance between the first i chars of a and
+    // the first j chars of b
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in distances[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+/// The names closest to a misspelled name, ordered from closest to furthest.
+/// Suggestions display as a trailing "did you mean" clause, or as nothing at
+/// all if there are none, so they may be appended to an error message.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Suggestions(Vec<String>);
+
+impl Suggestions {
+    /// New up a Suggestions instance from a list of names
+    pub fn new(names: Vec<String>) -> Self {
+        Suggestions(names)
+    }
+
+    /// Find the candidates which are close enough to `target` to be
+    /// worth suggesting. A candidate is close enough if it is within an edit
+    /// distance of a third of the target's length (and at least one), or if
+    /// one is an abbreviation of the other, as in `seq` and `sequence`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jspcompile::spelling::Suggestions;
+    ///
+    /// let suggestions = Suggestions::from_candidates("sequence", vec!["show", "seq", "shot"]);
+    /// assert_eq!(suggestions.names(), &["seq".to_string()]);
+    /// ```
+    pub fn from_candidates<I, S>(target: &str, candidates: I) -> Self
+    where
+        I: IntoIterator<Item=S>,
+        S: AsRef<str>
+    {
+        let threshold = std::cmp::max(1, target.chars().count() / 3);
+        let mut scored = candidates.into_iter()
+            .filter_map(|candidate| {
+                let candidate = candidate.as_ref();
+                if candidate == target {
+                    return None;
+                }
+                let distance = edit_distance(target, candidate);
+                let abbreviation = candidate.len() > 1 && target.len() > 1
+                    && (target.starts_with(candidate) || candidate.starts_with(target));
+                if distance <= threshold || abbreviation {
+                    Some((distance, candidate.to_string()))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        scored.sort();
+        scored.dedup();
+        Suggestions(scored.into_iter().take(MAX_SUGGESTIONS).map(|(_, name)| name).collect())
+    }
+
+    /// Retrieve the suggested names as a slice
+    pub fn names(&self) -> &[String] {
+        &self.0
+    }
+
+    /// Are there no suggestions?
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for Suggestions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let quoted = self.0.iter().map(|name| format!("`{}`", name)).collect::<Vec<_>>();
+        match quoted.split_last() {
+            None => Ok(()),
+            Some((last, [])) => write!(f, "; did you mean {}?", last),
+            Some((last, rest)) => write!(f, "; did you mean {} or {}?", rest.join(", "), last),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_to_self_is_zero() {
+        assert_eq!(edit_distance("shot", "shot"), 0);
+    }
+
+    #[test]
+    fn distance_counts_edits() {
+        assert_eq!(edit_distance("", "shot"), 4);
+        assert_eq!(edit_distance("shot", "shots"), 1);
+        assert_eq!(edit_distance("shot", "slot"), 1);
+        assert_eq!(edit_distance("quicktimes", "quickimes"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn distance_counts_transposition_as_one() {
+        assert_eq!(edit_distance("onwer", "owner"), 1);
+    }
+
+    #[test]
+    fn suggests_abbreviation() {
+        let suggestions = Suggestions::from_candidates("sequence", vec!["root", "dd", "shows", "show", "seq", "shot"]);
+        assert_eq!(suggestions.names(), &["seq".to_string()]);
+    }
+
+    #[test]
+    fn suggests_closest_first() {
+        let suggestions = Suggestions::from_candidates("shos", vec!["shows", "shot", "seq"]);
+        assert_eq!(suggestions.names(), &["shot".to_string(), "shows".to_string()]);
+    }
+
+    #[test]
+    fn suggests_nothing_when_nothing_is_close() {
+        let suggestions = Suggestions::from_candidates("clientvault", vec!["dd", "shows", "show"]);
+        assert!(suggestions.is_empty());
+        assert_eq!(suggestions.to_string(), "");
+    }
+
+    #[test]
+    fn displays_as_did_you_mean() {
+        let one = Suggestions::new(vec!["graph".to_string()]);
+        assert_eq!(one.to_string(), "; did you mean `graph`?");
+        let many = Suggestions::new(vec!["shot".to_string(), "show".to_string(), "shows".to_string()]);
+        assert_eq!(many.to_string(), "; did you mean `shot`, `show` or `shows`?");
+    }
+}
@@ -1,5 +1,4 @@
-use nom::{IResult};
-use crate::{ParseResult, Header, start_parser, regex_parser, node_parser, edge_parser, JSPTemplateError, JSPTemplateLineError};
+use crate::{ParseResult, Header, parser::HEADER_NAMES, spelling::Suggestions, start_parser, regex_parser, node_parser, edge_parser, JSPTemplateError, JSPTemplateLineError};
 use std::cell::Cell;
 use std::fmt;
 
@@ -40,10 +39,10 @@ pub struct StateMachine {
     // that we will be passing through. This can be a tuple
     // as the transitions are well defined. 
     parsers: (
-        fn(&str)->IResult<&str, ParseResult>,
-        fn(&str)->IResult<&str, ParseResult>,
-        fn(&str)->IResult<&str, ParseResult>,
-        fn(&str)->IResult<&str, ParseResult>  
+        fn(&str)->Result<ParseResult, JSPTemplateError>,
+        fn(&str)->Result<ParseResult, JSPTemplateError>,
+        fn(&str)->Result<ParseResult, JSPTemplateError>,
+        fn(&str)->Result<ParseResult, JSPTemplateError>  
     )
 }
 
@@ -100,13 +99,25 @@ impl StateMachine {
             Ok(result) => {
                 // inner Result determines whether parsing of line is ok
                 match result {
-                    Ok((_, value)) => {
+                    Ok(value) => {
                         // If we encounter a header, we transition to the state
                         // associated with the header. We only allow valid transitions as 
                         // dictated by the next_state method.
                         if let ParseResult::Header(ref header) = value {
                             let current_state = self.state.clone();
 
+                            // an unknown header is most likely a misspelling of a known one
+                            if let Header::Unknown(name) = header {
+                                return Err(
+                                    JSPTemplateLineError::from(
+                                        (self.line.get(),
+                                        input.to_owned(),
+                                        self.state.clone(),
+                                        JSPTemplateError::UnknownHeader(name.clone(), Suggestions::from_candidates(name, HEADER_NAMES)))
+                                        )
+                                    )
+                            }
+
                             // get the next allowed state from the statemachine
                             let next_valid_state = match self.next_valid_state(){
                                 Ok(a) => a,
@@ -143,7 +154,7 @@ impl StateMachine {
                     Err(e) => {
                         return Err(
                             JSPTemplateLineError::from(
-                                ( self.line.get(), input.to_owned(), self.state.clone(), JSPTemplateError::from(e)) )
+                                ( self.line.get(), input.to_owned(), self.state.clone(), e) )
                             );
                     },
                 }
@@ -0,0 +1,181 @@
+//! Suggest a regex for a `[regex]` entry from names it should accept and names
+//! it should reject.
+//!
+//! The names to accept are grouped by shape, as `infer` groups directory names,
+//! and each group is generalized into character classes, keeping any part which
+//! every name in the group shares. Groups of different shapes become
+//! alternatives. Names to reject which the regex would still match are written
+//! into a negative regex, in the `"pos" "neg"` form of `Regex::Complex`. Both are
+//! then verified against every example.
+use crate::{JSPTemplateError, decompile::quote, infer::{generalize, shape}};
+use ext_regex::Regex;
+use std::{collections::BTreeMap, fmt};
+
+/// How a Suggestion treats a name
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Verdict {
+    /// The name matches the positive regex, and not the negative one
+    Accepted,
+    /// The name does not match the positive regex
+    Unmatched,
+    /// The name matches the negative regex
+    Excluded,
+}
+
+/// A positive regex, along with a negative regex where one is needed
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pattern: String,
+    exclude: Option<String>,
+    regex: Regex,
+    exclude_regex: Option<Regex>,
+}
+
+impl Suggestion {
+    // new up a Suggestion, anchoring the regexes as the Loader does
+    fn new(pattern: String, exclude: Option<String>) -> Self {
+        let anchor = |regex: &str| Regex::new(&format!("^{}$", regex)).expect("suggested regexes are valid");
+        let regex = anchor(&pattern);
+        let exclude_regex = exclude.as_ref().map(|exclude| anchor(exclude));
+        Self { pattern, exclude, regex, exclude_regex }
+    }
+
+    /// Retrieve the positive regex, as written in a template
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Retrieve the negative regex, as written in a template, if one is needed
+    pub fn exclude(&self) -> Option<&str> {
+        self.exclude.as_ref().map(|x| &**x)
+    }
+
+    /// Test a name against the regexes, as the Loader would.
+    ///
+    /// # Parameters
+    ///
+    /// * `name` - the name to test
+    ///
+    /// # Returns
+    /// The Verdict
+    pub fn check(&self, name: &str) -> Verdict {
+        if !self.regex.is_match(name) {
+            Verdict::Unmatched
+        } else if self.exclude_regex.as_ref().map(|exclude| exclude.is_match(name)).unwrap_or(false) {
+            Verdict::Excluded
+        } else {
+            Verdict::Accepted
+        }
+    }
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", quote(&self.pattern, self.exclude()))
+    }
+}
+
+/// Suggest a regex which accepts every name in `accept`, and rejects every name
+/// in `reject`.
+///
+/// # Parameters
+///
+/// * `accept` - the names to accept
+/// * `reject` - the names to reject
+///
+/// # Returns
+/// A Result wrapping the Suggestion if successful. Otherwise a JSPTemplateError,
+/// if a name is both to be accepted and rejected.
+///
+/// # Examples
+///
+/// ```
+/// use jspcompile::suggest::suggest_regex;
+///
+/// let suggestion = suggest_regex(&["FOO", "BAR"], &["REF", "SHARED"]).unwrap();
+/// assert_eq!(suggestion.to_string(), r#""[A-Z]{3}" "(REF)""#);
+/// ```
+pub fn suggest_regex(accept: &[&str], reject: &[&str]) -> Result<Suggestion, JSPTemplateError> {
+    let mut shapes = BTreeMap::new();
+    for name in accept {
+        let members = shapes.entry(shape(name)).or_insert_with(Vec::new);
+        if !members.contains(name) {
+            members.push(*name);
+        }
+    }
+    let alternatives = shapes.values().map(|members| generalize(members, members.len() > 1)).collect::<Vec<_>>();
+    let pattern = match alternatives.len() {
+        1 => alternatives[0].clone(),
+        _ => format!("({})", alternatives.join("|")),
+    };
+
+    let positive = Suggestion::new(pattern.clone(), None);
+    let mut excluded = Vec::new();
+    for name in reject.iter().filter(|name| positive.check(name) == Verdict::Accepted) {
+        let name = ext_regex::escape(name);
+        if !excluded.contains(&name) {
+            excluded.push(name);
+        }
+    }
+    let suggestion = if excluded.is_empty() {
+        positive
+    } else {
+        Suggestion::new(pattern, Some(format!("({})", excluded.join("|"))))
+    };
+
+    // the negative regex is built from the rejected names, so an accepted name
+    // only fails if it was also rejected
+    for name in accept {
+        if suggestion.check(name) != Verdict::Accepted {
+            return Err(JSPTemplateError::ConflictingExamplesError(name.to_string()));
+        }
+    }
+    for name in reject {
+        if suggestion.check(name) == Verdict::Accepted {
+            return Err(JSPTemplateError::ConflictingExamplesError(name.to_string()));
+        }
+    }
+    Ok(suggestion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{loader::load_template, Matcher};
+
+    #[test]
+    fn generalizes_names_without_a_negative_regex_when_none_is_needed() {
+        let suggestion = suggest_regex(&["AA_0100", "BB_0200"], &["REF", "SHARED"]).unwrap();
+        assert_eq!(suggestion.pattern(), "[A-Z]{2}_[0-9]{4}");
+        assert_eq!(suggestion.exclude(), None);
+        assert_eq!(suggestion.to_string(), r#""[A-Z]{2}_[0-9]{4}""#);
+        assert_eq!(suggestion.check("CC_0300"), Verdict::Accepted);
+        assert_eq!(suggestion.check("REF"), Verdict::Unmatched);
+    }
+
+    #[test]
+    fn excludes_rejected_names_which_the_regex_matches() {
+        let suggestion = suggest_regex(&["FOO", "BARBAZ", "v001", "v002"], &["REF", "SHARED", "lib"]).unwrap();
+        assert_eq!(suggestion.to_string(), r#""([A-Z]+|v[0-9]{3})" "(REF|SHARED)""#);
+        assert_eq!(suggestion.check("SHARED"), Verdict::Excluded);
+        assert_eq!(suggestion.check("lib"), Verdict::Unmatched);
+    }
+
+    #[test]
+    fn rejects_names_which_are_both_accepted_and_rejected() {
+        match suggest_regex(&["AA_0100", "REF"], &["REF"]) {
+            Err(JSPTemplateError::ConflictingExamplesError(name)) => assert_eq!(name, "REF"),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn suggestions_load_as_templates() {
+        let suggestion = suggest_regex(&["FOO", "BAR"], &["REF"]).unwrap();
+        let template = format!("[regex]\nshow = {}\n[nodes]\nshow = $show\n[graph]\nroot -> show\n", suggestion);
+        let (graph, keymap) = load_template(&template);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        assert!(matcher.match_path("/BAZ").is_match());
+        assert!(!matcher.match_path("/REF").is_match());
+    }
+}
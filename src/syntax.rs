@@ -0,0 +1,203 @@
+//! A lossless syntax tree for jspt templates.
+//!
+//! The Loader only cares about what a template means, so the parsers discard
+//! whitespace, blank lines and the comment markers along the way. Tools which
+//! rewrite templates, such as the formatter, need all of that back. The
+//! SyntaxTree pairs each line of the template, verbatim, with what it parsed
+//! as, so that the original text may always be reproduced exactly.
+use crate::{Edge, Header, JSPTemplateError, Node, ParseResult, Regex, StateMachine};
+use std::fmt;
+
+/// The token which introduced a comment
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CommentMarker {
+    /// `#`
+    Pound,
+    /// `//`
+    Slashes,
+}
+
+impl CommentMarker {
+    /// Retrieve the marker as it appears in the template
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommentMarker::Pound => "#",
+            CommentMarker::Slashes => "//",
+        }
+    }
+}
+
+/// What a line of the template parsed as
+#[derive(Debug, PartialEq, Eq)]
+pub enum SyntaxKind {
+    /// A line with nothing but whitespace on it
+    Blank,
+    /// A comment, along with the marker which introduced it and the text following the marker
+    Comment { marker: CommentMarker, text: String },
+    /// A section header, along with its name as written (eg `re` for `[re]`)
+    Header { header: Header, name: String },
+    /// A named regex
+    Regex(Regex),
+    /// A node
+    Node(Node),
+    /// A chain of one or more edges
+    Edges(Vec<Edge>),
+}
+
+/// A single line of the template, along with what it parsed as.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SyntaxLine {
+    number: usize,
+    text: String,
+    kind: SyntaxKind,
+}
+
+impl SyntaxLine {
+    /// Retrieve the one based line number
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Retrieve the line exactly as written, without its line ending
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Retrieve what the line parsed as
+    pub fn kind(&self) -> &SyntaxKind {
+        &self.kind
+    }
+
+    /// Retrieve the whitespace preceding the content of the line
+    pub fn indent(&self) -> &str {
+        let trimmed = self.text.trim_start();
+        &self.text[..self.text.len() - trimmed.len()]
+    }
+}
+
+/// A template as a sequence of lines. Displaying a SyntaxTree reproduces the
+/// template it was parsed from, byte for byte.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SyntaxTree {
+    lines: Vec<SyntaxLine>,
+    // the line ending used by the template
+    newline: &'static str,
+    trailing_newline: bool,
+}
+
+impl SyntaxTree {
+    /// Parse a template into a SyntaxTree. The template is subject to the same
+    /// rules as when loading it, so sections must appear in order.
+    ///
+    /// # Parameters
+    ///
+    /// * `input` - the contents of a jspt template
+    ///
+    /// # Returns
+    /// A Result wrapping the SyntaxTree if successful. Otherwise a JSPTemplateError.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jspcompile::syntax::SyntaxTree;
+    ///
+    /// let template = "[regex]\n[nodes]\n# a comment\ndd   \n";
+    /// let tree = SyntaxTree::parse(template).unwrap();
+    /// assert_eq!(tree.lines().len(), 4);
+    /// assert_eq!(tree.to_string(), template);
+    /// ```
+    pub fn parse(input: &str) -> Result<Self, JSPTemplateError> {
+        let newline = if input.contains("\r\n") { "\r\n" } else { "\n" };
+        let trailing_newline = input.ends_with('\n');
+        let body = if trailing_newline { &input[..input.len() - newline.len()] } else { input };
+        let mut statemachine = StateMachine::new();
+        let mut lines = Vec::new();
+        if input.is_empty() {
+            return Ok(Self { lines, newline, trailing_newline });
+        }
+        for text in body.split(newline) {
+            let kind = match statemachine.parse(text)? {
+                ParseResult::Empty => SyntaxKind::Blank,
+                ParseResult::Comment(comment) => {
+                    let marker = if text.trim_start().starts_with('#') { CommentMarker::Pound } else { CommentMarker::Slashes };
+                    SyntaxKind::Comment { marker, text: comment }
+                }
+                ParseResult::Header(header) => {
+                    let name = text.trim().trim_start_matches('[').trim_end_matches(']').trim().to_string();
+                    SyntaxKind::Header { header, name }
+                }
+                ParseResult::Regex(regex) => SyntaxKind::Regex(regex),
+                ParseResult::Node(node) => SyntaxKind::Node(node),
+                ParseResult::Edges(edges) => SyntaxKind::Edges(edges),
+            };
+            lines.push(SyntaxLine { number: statemachine.line_number(), text: text.to_string(), kind });
+        }
+        Ok(Self { lines, newline, trailing_newline })
+    }
+
+    /// Retrieve the lines of the template
+    pub fn lines(&self) -> &[SyntaxLine] {
+        &self.lines
+    }
+
+    /// Retrieve the line ending used by the template
+    pub fn newline(&self) -> &'static str {
+        self.newline
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, line) in self.lines.iter().enumerate() {
+            if idx > 0 {
+                write!(f, "{}", self.newline)?;
+            }
+            write!(f, "{}", line.text)?;
+        }
+        if self.trailing_newline {
+            write!(f, "{}", self.newline)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reproduces_examples_exactly() {
+        for template in &[
+            include_str!("../examples/jsptemplate.jspt"),
+            include_str!("../examples/features.jspt"),
+        ] {
+            let tree = SyntaxTree::parse(template).unwrap();
+            assert_eq!(&tree.to_string(), template);
+        }
+    }
+
+    #[test]
+    fn records_comment_markers_and_headers() {
+        let tree = SyntaxTree::parse("  // slashes\n[ re ]\n# pound").unwrap();
+        assert_eq!(tree.lines()[0].kind(), &SyntaxKind::Comment { marker: CommentMarker::Slashes, text: " slashes".to_string() });
+        assert_eq!(tree.lines()[0].indent(), "  ");
+        assert_eq!(tree.lines()[1].kind(), &SyntaxKind::Header { header: Header::Regex, name: "re".to_string() });
+        assert_eq!(tree.lines()[2].kind(), &SyntaxKind::Comment { marker: CommentMarker::Pound, text: " pound".to_string() });
+        assert_eq!(tree.lines()[2].number(), 3);
+        assert_eq!(tree.to_string(), "  // slashes\n[ re ]\n# pound");
+    }
+
+    #[test]
+    fn preserves_crlf() {
+        let template = "[regex]\r\n[nodes]\r\ndd\r\n";
+        assert_eq!(SyntaxTree::parse(template).unwrap().to_string(), template);
+    }
+
+    #[test]
+    fn reports_errors_with_line() {
+        match SyntaxTree::parse("[regex]\n[nodes]\ndd -> shows\n") {
+            Err(JSPTemplateError::ErrorAtLine(3, ..)) => (),
+            other => panic!("expected error at line 3. got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Render a loaded template as an indented tree, in the manner of the `tree` command.
+//!
+//! Each node is shown with what it matches and its metadata. A node with more
+//! than one parent appears beneath each of them, marked as shared, but its
+//! children are only listed the first time, as they are the same beneath every
+//! parent.
+use crate::{Matcher, matcher::{describe_identity, describe_metadata}};
+use jsp::{NIndex, NodeType};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Render the graph beneath `from` as a tree.
+///
+/// # Parameters
+///
+/// * `matcher` - a Matcher over the loaded template
+/// * `from` - the node at the top of the tree, normally root
+/// * `depth` - if supplied, the number of levels beneath `from` to show
+///
+/// # Returns
+/// The tree, one node per line
+///
+/// # Examples
+///
+/// ```
+/// use jspcompile::{Loader, Matcher, tree::render};
+/// use std::io::Cursor;
+///
+/// let (mut graph, mut keymap, mut regexmap) = Loader::setup();
+/// {
+///     let mut loader = Loader::new(&mut graph, &mut keymap, &mut regexmap);
+///     loader.load(Cursor::new("[regex]\n[nodes]\ndd\nshows\n[graph]\nroot -> dd -> shows\n")).unwrap();
+/// }
+/// let matcher = Matcher::new(&graph, &keymap).unwrap();
+/// assert_eq!(render(&matcher, matcher.root(), None), "root\n└── dd  literal: dd\n    └── shows  literal: shows\n");
+/// ```
+pub fn render(matcher: &Matcher, from: NIndex, depth: Option<usize>) -> String {
+    let mut renderer = Renderer { matcher, depth, shared: shared(matcher), expanded: HashSet::new(), stack: vec![from], out: String::new() };
+    renderer.line("", from);
+    renderer.children(from, "");
+    renderer.out
+}
+
+// the nodes reachable from root which have more than one parent
+fn shared(matcher: &Matcher) -> HashSet<NIndex> {
+    let mut parents: HashMap<NIndex, HashSet<NIndex>> = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(matcher.root());
+    while let Some(node) = queue.pop_front() {
+        if !visited.insert(node) {
+            continue;
+        }
+        for child in matcher.children(node) {
+            parents.entry(child).or_default().insert(node);
+            queue.push_back(child);
+        }
+    }
+    parents.into_iter().filter(|(_, parents)| parents.len() > 1).map(|(node, _)| node).collect()
+}
+
+// state for rendering
+struct Renderer<'a, 'b> {
+    matcher: &'a Matcher<'b>,
+    depth: Option<usize>,
+    shared: HashSet<NIndex>,
+    // the shared nodes whose children have been listed
+    expanded: HashSet<NIndex>,
+    // the nodes from `from` down to the one being rendered
+    stack: Vec<NIndex>,
+    out: String,
+}
+
+impl<'a, 'b> Renderer<'a, 'b> {
+    // list the children of the node at the top of the stack, beneath a line starting with `prefix`
+    fn children(&mut self, node: NIndex, prefix: &str) {
+        if self.depth.map_or(false, |depth| self.stack.len() > depth) {
+            return;
+        }
+        let children = self.matcher.children(node);
+        for (index, child) in children.iter().enumerate() {
+            let last = index + 1 == children.len();
+            let (branch, indent) = if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+            if self.stack.contains(child) {
+                self.out.push_str(&format!("{}{}{}  (cycle)\n", prefix, branch, self.matcher.name(*child)));
+                continue;
+            }
+            self.line(&format!("{}{}", prefix, branch), *child);
+            if self.shared.contains(child) {
+                if self.expanded.contains(child) {
+                    continue;
+                }
+                // only once its children are actually listed
+                if self.depth.map_or(true, |depth| self.stack.len() < depth) {
+                    self.expanded.insert(*child);
+                }
+            }
+            self.stack.push(*child);
+            self.children(*child, &format!("{}{}", prefix, indent));
+            self.stack.pop();
+        }
+    }
+
+    // describe the node on a line of its own
+    fn line(&mut self, prefix: &str, node: NIndex) {
+        let jnode = &self.matcher.graph()[node];
+        let mut details = vec![self.matcher.name(node).to_string()];
+        match jnode.identity() {
+            NodeType::Root => (),
+            identity => details.push(describe_identity(identity)),
+        }
+        let metadata = describe_metadata(jnode);
+        if !metadata.is_empty() {
+            details.push(metadata);
+        }
+        if let Some(varname) = jnode.metadata().varname() {
+            details.push(format!("${}", varname));
+        }
+        if self.shared.contains(&node) {
+            details.push(if self.expanded.contains(&node) { "(shared, see above)" } else { "(shared)" }.to_string());
+        }
+        self.out.push_str(&format!("{}{}\n", prefix, details.join("  ")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_template;
+
+    const TEMPLATE: &str = "[regex]\n[nodes]\ndd\nshows\nshow = \"[A-Z]+\" [owner: jobsys, perms: 751, varname: DD_SHOW]\nseq = \"[A-Z]{2,4}\"\nshared = SHARED [volume]\nref\n[graph]\nroot -> dd -> shows -> show -> seq -> shared -> ref\nshow -> shared\n";
+
+    #[test]
+    fn marks_shared_nodes() {
+        let (graph, keymap) = load_template(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        assert_eq!(
+            render(&matcher, matcher.root(), None),
+            "root\n\
+             └── dd  literal: dd\n    \
+                 └── shows  literal: shows\n        \
+                     └── show  regex: ^[A-Z]+$  [owner: jobsys, perms: 751]  $DD_SHOW\n            \
+                         ├── seq  regex: ^[A-Z]{2,4}$\n            \
+                         │   └── shared  literal: SHARED  [volume]  (shared)\n            \
+                         │       └── ref  literal: ref\n            \
+                         └── shared  literal: SHARED  [volume]  (shared, see above)\n"
+        );
+    }
+
+    #[test]
+    fn limits_depth_and_starts_from_a_node() {
+        let (graph, keymap) = load_template(TEMPLATE);
+        let matcher = Matcher::new(&graph, &keymap).unwrap();
+        assert_eq!(render(&matcher, matcher.root(), Some(1)), "root\n└── dd  literal: dd\n");
+        assert_eq!(
+            render(&matcher, keymap["seq"], Some(1)),
+            "seq  regex: ^[A-Z]{2,4}$\n└── shared  literal: SHARED  [volume]  (shared)\n"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Poll files for changes, so that templates may be recompiled as they are edited.
+//!
+//! We poll rather than subscribe to filesystem events, as those are not delivered
+//! for changes made over NFS. Files are compared by their contents rather than by
+//! their modification times, which NFS clients may cache.
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// Watches a set of files for changes to their contents
+#[derive(Debug)]
+pub struct Watcher {
+    files: Vec<(PathBuf, Option<u64>)>,
+}
+
+impl Watcher {
+    /// New up a Watcher, recording the current contents of each file
+    ///
+    /// # Parameters
+    ///
+    /// * `files` - the files to watch. A file which does not exist yet is
+    ///    considered changed once it does.
+    ///
+    /// # Returns
+    /// A Watcher
+    pub fn new<I, P>(files: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let files = files.into_iter()
+            .map(|file| (file.as_ref().to_path_buf(), fingerprint(file.as_ref())))
+            .collect();
+        Self { files }
+    }
+
+    /// Retrieve the files being watched
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(file, _)| file.as_path())
+    }
+
+    /// Has any file changed since the Watcher was created, or since the last
+    /// call to `changed`? A file which has been removed counts as a change.
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (file, recorded) in self.files.iter_mut() {
+            let current = fingerprint(file);
+            if current != *recorded {
+                *recorded = current;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Block until a file changes, checking every `interval`
+    pub fn wait(&mut self, interval: Duration) {
+        while !self.changed() {
+            thread::sleep(interval);
+        }
+    }
+}
+
+// hash the contents of the file, or None if it cannot be read
+fn fingerprint(file: &Path) -> Option<u64> {
+    let contents = fs::read(file).ok()?;
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_changed_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("show.jspt");
+        fs::write(&file, "[regex]\n").unwrap();
+        let mut watcher = Watcher::new(&[&file]);
+        assert!(!watcher.changed());
+        // rewriting the same contents is not a change
+        fs::write(&file, "[regex]\n").unwrap();
+        assert!(!watcher.changed());
+        fs::write(&file, "[regex]\n[nodes]\n").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+    }
+
+    #[test]
+    fn detects_created_and_removed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("show.jspt");
+        let mut watcher = Watcher::new(&[&file]);
+        assert!(!watcher.changed());
+        fs::write(&file, "[regex]\n").unwrap();
+        assert!(watcher.changed());
+        fs::remove_file(&file).unwrap();
+        assert!(watcher.changed());
+    }
+}